@@ -0,0 +1,341 @@
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::ChannelData;
+
+/// Kind of artifact reported by a detector
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtifactKind {
+    EyeBlink,
+    Emg,
+    Movement,
+    AmplitudeExcursion,
+}
+
+impl ArtifactKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ArtifactKind::EyeBlink => "eye_blink",
+            ArtifactKind::Emg => "emg",
+            ArtifactKind::Movement => "movement",
+            ArtifactKind::AmplitudeExcursion => "amplitude_excursion",
+        }
+    }
+}
+
+/// Payload of the `artifact_detected` event
+#[derive(Debug, Clone, Serialize)]
+pub struct ArtifactEvent {
+    pub kind: ArtifactKind,
+    pub channel: usize,
+    pub onset_sample: u64, // Index of the first affected sample since acquisition start
+    pub onset_ms: u64,     // Wall-clock onset in milliseconds since the Unix epoch
+    pub duration_ms: f64,
+    pub peak: f64,         // Largest detector feature value during the episode
+}
+
+/// Thresholds and switches of the built-in detectors
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ArtifactConfig {
+    pub enabled: bool,
+    pub annotate_recording: bool, // Write detections as annotations into the active recording
+    pub blink_enabled: bool,
+    pub blink_channels: Vec<usize>, // Frontal channels that see eye blinks
+    pub blink_threshold: f64,       // Low-passed deflection from baseline
+    pub emg_enabled: bool,
+    pub emg_threshold: f64,         // Envelope of the high-passed signal
+    pub movement_enabled: bool,
+    pub movement_threshold: f64,    // Sample-to-sample jump
+    pub excursion_enabled: bool,
+    pub excursion_threshold: f64,   // Absolute deflection from baseline
+}
+
+impl Default for ArtifactConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            annotate_recording: false,
+            blink_enabled: true,
+            blink_channels: vec![0, 1],
+            blink_threshold: 100.0,
+            emg_enabled: true,
+            emg_threshold: 40.0,
+            movement_enabled: true,
+            movement_threshold: 300.0,
+            excursion_enabled: true,
+            excursion_threshold: 500.0,
+        }
+    }
+}
+
+impl ArtifactConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.blink_channels.iter().any(|&c| c >= 8) {
+            return Err("Blink channels must be between 0 and 7".to_string());
+        }
+        let thresholds = [
+            self.blink_threshold,
+            self.emg_threshold,
+            self.movement_threshold,
+            self.excursion_threshold,
+        ];
+        if thresholds.iter().any(|&t| t <= 0.0) {
+            return Err("Artifact thresholds must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// A detector sees every sample of every channel and reports finished episodes
+pub trait ArtifactDetector: Send {
+    fn process(&mut self, sample_index: u64, data: &ChannelData, sample_rate: f64) -> Vec<Episode>;
+}
+
+/// A finished above-threshold episode on one channel
+#[derive(Debug, Clone)]
+pub struct Episode {
+    pub kind: ArtifactKind,
+    pub channel: usize,
+    pub onset_sample: u64,
+    pub length: u64,
+    pub peak: f64,
+}
+
+/// First-order low-pass used for baselines and envelopes
+#[derive(Debug, Clone, Default)]
+struct Ema {
+    value: Option<f64>,
+}
+
+impl Ema {
+    fn update(&mut self, x: f64, time_constant_s: f64, sample_rate: f64) -> f64 {
+        let alpha = 1.0 - (-1.0 / (time_constant_s * sample_rate).max(1.0)).exp();
+        let next = match self.value {
+            Some(v) => v + alpha * (x - v),
+            None => x,
+        };
+        self.value = Some(next);
+        next
+    }
+}
+
+/// Hysteresis tracker turning a per-sample feature into episodes
+#[derive(Debug, Clone, Default)]
+struct EpisodeTracker {
+    onset: Option<u64>,
+    peak: f64,
+    below: u64,
+}
+
+impl EpisodeTracker {
+    /// `release` is how many consecutive quiet samples close an episode
+    fn update(&mut self, index: u64, feature: f64, threshold: f64, release: u64) -> Option<(u64, u64, f64)> {
+        if feature > threshold {
+            if self.onset.is_none() {
+                self.onset = Some(index);
+                self.peak = 0.0;
+            }
+            self.peak = self.peak.max(feature);
+            self.below = 0;
+            return None;
+        }
+        let onset = self.onset?;
+        self.below += 1;
+        if self.below < release {
+            return None;
+        }
+        self.onset = None;
+        let length = (index + 1 - self.below).saturating_sub(onset).max(1);
+        Some((onset, length, self.peak))
+    }
+}
+
+/// Large low-frequency deflections on frontal channels lasting 100-500 ms
+struct BlinkDetector {
+    channels: Vec<usize>,
+    threshold: f64,
+    baseline: [Ema; 8],
+    smooth: [Ema; 8],
+    trackers: [EpisodeTracker; 8],
+}
+
+impl ArtifactDetector for BlinkDetector {
+    fn process(&mut self, index: u64, data: &ChannelData, fs: f64) -> Vec<Episode> {
+        let mut episodes = Vec::new();
+        for &ch in &self.channels {
+            let x = data[ch] as f64;
+            let baseline = self.baseline[ch].update(x, 2.0, fs);
+            let feature = self.smooth[ch].update(x - baseline, 0.02, fs).abs();
+            let release = (0.05 * fs).ceil() as u64;
+            if let Some((onset, length, peak)) = self.trackers[ch].update(index, feature, self.threshold, release) {
+                let seconds = length as f64 / fs;
+                if (0.1..=0.5).contains(&seconds) {
+                    episodes.push(Episode { kind: ArtifactKind::EyeBlink, channel: ch, onset_sample: onset, length, peak });
+                }
+            }
+        }
+        episodes
+    }
+}
+
+/// Sustained high-frequency power such as jaw clenches
+struct EmgDetector {
+    threshold: f64,
+    slow: [Ema; 8],
+    envelope: [Ema; 8],
+    trackers: [EpisodeTracker; 8],
+}
+
+impl ArtifactDetector for EmgDetector {
+    fn process(&mut self, index: u64, data: &ChannelData, fs: f64) -> Vec<Episode> {
+        let mut episodes = Vec::new();
+        for (ch, &value) in data.iter().enumerate() {
+            let x = value as f64;
+            // Subtracting a ~30 Hz low-pass leaves the EMG band
+            let high = x - self.slow[ch].update(x, 1.0 / (2.0 * std::f64::consts::PI * 30.0), fs);
+            let feature = self.envelope[ch].update(high.abs(), 0.05, fs);
+            let release = (0.1 * fs).ceil() as u64;
+            if let Some((onset, length, peak)) = self.trackers[ch].update(index, feature, self.threshold, release) {
+                if length as f64 / fs >= 0.1 {
+                    episodes.push(Episode { kind: ArtifactKind::Emg, channel: ch, onset_sample: onset, length, peak });
+                }
+            }
+        }
+        episodes
+    }
+}
+
+/// Abrupt sample-to-sample jumps from electrode or cable movement
+struct MovementDetector {
+    threshold: f64,
+    previous: Option<ChannelData>,
+    trackers: [EpisodeTracker; 8],
+}
+
+impl ArtifactDetector for MovementDetector {
+    fn process(&mut self, index: u64, data: &ChannelData, fs: f64) -> Vec<Episode> {
+        let mut episodes = Vec::new();
+        if let Some(previous) = self.previous {
+            for (ch, (&value, &last)) in data.iter().zip(previous.iter()).enumerate() {
+                let feature = (value as f64 - last as f64).abs();
+                let release = (0.05 * fs).ceil() as u64;
+                if let Some((onset, length, peak)) = self.trackers[ch].update(index, feature, self.threshold, release) {
+                    episodes.push(Episode { kind: ArtifactKind::Movement, channel: ch, onset_sample: onset, length, peak });
+                }
+            }
+        }
+        self.previous = Some(*data);
+        episodes
+    }
+}
+
+/// Any deflection from the running baseline beyond an absolute limit
+struct ExcursionDetector {
+    threshold: f64,
+    baseline: [Ema; 8],
+    trackers: [EpisodeTracker; 8],
+}
+
+impl ArtifactDetector for ExcursionDetector {
+    fn process(&mut self, index: u64, data: &ChannelData, fs: f64) -> Vec<Episode> {
+        let mut episodes = Vec::new();
+        for (ch, &value) in data.iter().enumerate() {
+            let x = value as f64;
+            let feature = (x - self.baseline[ch].update(x, 5.0, fs)).abs();
+            let release = (0.1 * fs).ceil() as u64;
+            if let Some((onset, length, peak)) = self.trackers[ch].update(index, feature, self.threshold, release) {
+                episodes.push(Episode { kind: ArtifactKind::AmplitudeExcursion, channel: ch, onset_sample: onset, length, peak });
+            }
+        }
+        episodes
+    }
+}
+
+/// Runs the enabled detectors over the live stream
+pub struct ArtifactEngine {
+    config: ArtifactConfig,
+    detectors: Vec<Box<dyn ArtifactDetector>>,
+    sample_index: u64,
+}
+
+impl ArtifactEngine {
+    pub fn new(config: ArtifactConfig) -> Self {
+        let mut detectors: Vec<Box<dyn ArtifactDetector>> = Vec::new();
+        if config.blink_enabled {
+            detectors.push(Box::new(BlinkDetector {
+                channels: config.blink_channels.clone(),
+                threshold: config.blink_threshold,
+                baseline: Default::default(),
+                smooth: Default::default(),
+                trackers: Default::default(),
+            }));
+        }
+        if config.emg_enabled {
+            detectors.push(Box::new(EmgDetector {
+                threshold: config.emg_threshold,
+                slow: Default::default(),
+                envelope: Default::default(),
+                trackers: Default::default(),
+            }));
+        }
+        if config.movement_enabled {
+            detectors.push(Box::new(MovementDetector {
+                threshold: config.movement_threshold,
+                previous: None,
+                trackers: Default::default(),
+            }));
+        }
+        if config.excursion_enabled {
+            detectors.push(Box::new(ExcursionDetector {
+                threshold: config.excursion_threshold,
+                baseline: Default::default(),
+                trackers: Default::default(),
+            }));
+        }
+        Self {
+            config,
+            detectors,
+            sample_index: 0,
+        }
+    }
+
+    pub fn config(&self) -> &ArtifactConfig {
+        &self.config
+    }
+
+    /// Feed one sample; returns the artifacts that finished with it
+    pub fn add_data(&mut self, data: ChannelData, sample_rate: f64) -> Vec<ArtifactEvent> {
+        let index = self.sample_index;
+        self.sample_index += 1;
+        if !self.config.enabled || sample_rate <= 0.0 {
+            return Vec::new();
+        }
+
+        let now = SystemTime::now();
+        let mut events = Vec::new();
+        for detector in self.detectors.iter_mut() {
+            for episode in detector.process(index, &data, sample_rate) {
+                // Episodes finish a few samples after their onset; back-date the wall clock
+                let ago = Duration::from_secs_f64((index - episode.onset_sample) as f64 / sample_rate);
+                let onset_ms = now
+                    .checked_sub(ago)
+                    .unwrap_or(now)
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis() as u64;
+                events.push(ArtifactEvent {
+                    kind: episode.kind,
+                    channel: episode.channel,
+                    onset_sample: episode.onset_sample,
+                    onset_ms,
+                    duration_ms: episode.length as f64 * 1000.0 / sample_rate,
+                    peak: episode.peak,
+                });
+            }
+        }
+        events
+    }
+}
@@ -0,0 +1,605 @@
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::compression::{self, CompressedFile};
+use crate::writer::{RecordingInfo, RecordingWriter, Row, Timeline};
+
+/// First bytes of a versioned binary recording. The line endings and the
+/// high first byte catch files mangled by text-mode transfers.
+pub const MAGIC: [u8; 8] = *b"\x89SBR\r\n\x1a\n";
+
+/// Current format version; files without the magic are the headerless version 1.
+/// Version 3 added the sample index of the first row to each chunk.
+pub const VERSION: u16 = 3;
+
+const CHUNK_TAG: [u8; 4] = *b"DATA";
+
+/// Stored in place of NaN in i32 chunks
+const I32_MISSING: i32 = i32::MIN;
+
+/// Largest JSON header accepted; real ones take a few hundred bytes per channel
+const MAX_HEADER_BYTES: usize = 1 << 20;
+
+/// Bytes read at a time while looking for the next chunk after a corrupt one
+const RESYNC_BLOCK: usize = 64 * 1024;
+
+/// Encoding of the values in data chunks
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SampleFormat {
+    F32,
+    I32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BinaryOptions {
+    pub sample_format: SampleFormat,
+    pub resolution: f64, // Microvolts per integer step of i32 data
+    pub chunk_ms: u32,   // Length of signal held in each data chunk
+}
+
+impl Default for BinaryOptions {
+    fn default() -> Self {
+        Self {
+            sample_format: SampleFormat::F32,
+            resolution: 0.001,
+            chunk_ms: 1000,
+        }
+    }
+}
+
+impl BinaryOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.resolution.is_finite() || self.resolution <= 0.0 {
+            return Err("Binary resolution must be positive".to_string());
+        }
+        if !(10..=60_000).contains(&self.chunk_ms) {
+            return Err("Binary chunk length must be between 10 ms and 60 s".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelInfo {
+    pub label: String,
+    pub unit: String,
+    pub scale: f64, // Physical value of one integer step; 1 for f32 data
+}
+
+/// JSON header following the magic and version
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BinaryHeader {
+    pub sample_rate: f64,
+    pub start_time_us: u64, // Microseconds since the Unix epoch
+    pub sample_format: SampleFormat,
+    pub channels: Vec<ChannelInfo>,
+    pub device: String,
+    pub software: String,
+}
+
+/// Rows of one data chunk; row `i` has sample index `first_index + i` and was
+/// sampled at `first_timestamp_us + i * (last - first) / (rows - 1)`
+#[derive(Debug, Clone)]
+pub struct BinaryChunk {
+    pub first_index: Option<u64>, // None in version 2 files
+    pub first_timestamp_us: u64,
+    pub last_timestamp_us: u64,
+    pub rows: Vec<Vec<f32>>,
+}
+
+impl BinaryChunk {
+    /// Timestamp of each row in microseconds, spread evenly over the chunk
+    pub fn timestamps_us(&self) -> Vec<u64> {
+        let count = self.rows.len();
+        let span = self.last_timestamp_us.saturating_sub(self.first_timestamp_us) as f64;
+        (0..count)
+            .map(|i| {
+                let fraction = if count > 1 { i as f64 / (count - 1) as f64 } else { 0.0 };
+                self.first_timestamp_us + (span * fraction).round() as u64
+            })
+            .collect()
+    }
+}
+
+fn timestamp_us(timestamp: &SystemTime) -> u64 {
+    timestamp
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_else(|_| Duration::from_secs(0))
+        .as_micros() as u64
+}
+
+/// Writes the header on the first sample, then one CRC-protected chunk per
+/// `chunk_ms` of signal.
+///
+/// Layout, little endian:
+/// `magic[8] version:u16 header_len:u32 header:json header_crc:u32`, then chunks of
+/// `"DATA" first_index:u64 first_us:u64 last_us:u64 rows:u32 channels:u16 values[rows*channels] crc:u32`
+/// where values are f32 or i32 per the header and the CRC covers the chunk from its tag.
+pub struct BinaryWriter {
+    file: BufWriter<CompressedFile>,
+    options: BinaryOptions,
+    info: RecordingInfo,
+    header_written: bool,
+    chunk_rows: usize,
+    pending: Vec<Row>,
+    timeline: Timeline,
+}
+
+impl BinaryWriter {
+    pub fn new(file: CompressedFile, info: &RecordingInfo) -> Result<Self, String> {
+        let options = info.binary.clone();
+        options.validate()?;
+        let chunk_rows = ((info.sample_rate * options.chunk_ms as f64 / 1000.0).round() as usize).max(1);
+        Ok(Self {
+            file: BufWriter::new(file),
+            options,
+            info: info.clone(),
+            header_written: false,
+            chunk_rows,
+            pending: Vec::new(),
+            timeline: Timeline::default(),
+        })
+    }
+
+    fn write_header(&mut self, start: &SystemTime) -> io::Result<()> {
+        let scale = match self.options.sample_format {
+            SampleFormat::F32 => 1.0,
+            SampleFormat::I32 => self.options.resolution,
+        };
+        let header = BinaryHeader {
+            sample_rate: self.info.sample_rate,
+            start_time_us: timestamp_us(start),
+            sample_format: self.options.sample_format,
+            channels: self
+                .info
+                .labels
+                .iter()
+                .map(|label| ChannelInfo {
+                    label: label.clone(),
+                    unit: "uV".to_string(),
+                    scale,
+                })
+                .collect(),
+            device: self.info.device.clone(),
+            software: format!("serial-brain {}", env!("CARGO_PKG_VERSION")),
+        };
+        let json = serde_json::to_vec(&header).map_err(io::Error::other)?;
+
+        let mut block = VERSION.to_le_bytes().to_vec();
+        block.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        block.extend_from_slice(&json);
+        self.file.write_all(&MAGIC)?;
+        self.file.write_all(&block)?;
+        self.file.write_all(&crc32fast::hash(&block).to_le_bytes())
+    }
+
+    fn write_chunk(&mut self, rows: &[Row]) -> io::Result<()> {
+        let (Some((first_index, first, _)), Some((_, last, _))) = (rows.first(), rows.last()) else {
+            return Ok(());
+        };
+        let channels = self.info.labels.len();
+        let mut chunk = Vec::with_capacity(34 + rows.len() * channels * 4);
+        chunk.extend_from_slice(&CHUNK_TAG);
+        chunk.extend_from_slice(&first_index.to_le_bytes());
+        chunk.extend_from_slice(&timestamp_us(first).to_le_bytes());
+        chunk.extend_from_slice(&timestamp_us(last).to_le_bytes());
+        chunk.extend_from_slice(&(rows.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(&(channels as u16).to_le_bytes());
+        for (_, _, row) in rows {
+            // Rows are padded or cut to the header's channel count
+            for i in 0..channels {
+                let value = row.get(i).copied().unwrap_or(f32::NAN);
+                match self.options.sample_format {
+                    SampleFormat::F32 => chunk.extend_from_slice(&value.to_le_bytes()),
+                    SampleFormat::I32 => {
+                        let digital = if value.is_finite() {
+                            (value as f64 / self.options.resolution)
+                                .round()
+                                .clamp(I32_MISSING as f64 + 1.0, i32::MAX as f64) as i32
+                        } else {
+                            I32_MISSING
+                        };
+                        chunk.extend_from_slice(&digital.to_le_bytes());
+                    }
+                }
+            }
+        }
+        let crc = crc32fast::hash(&chunk);
+        self.file.write_all(&chunk)?;
+        self.file.write_all(&crc.to_le_bytes())?;
+        self.file.flush()
+    }
+}
+
+impl RecordingWriter for BinaryWriter {
+    fn write_samples(&mut self, rows: &[Row]) -> io::Result<()> {
+        if let (false, Some((_, first, _))) = (self.header_written, rows.first()) {
+            self.write_header(first)?;
+            self.header_written = true;
+        }
+        for row in rows {
+            // Chunks hold consecutive samples, so the first index of the next one shows a gap
+            if self.timeline.add(row.0, row.1) > 0 {
+                let chunk = std::mem::take(&mut self.pending);
+                self.write_chunk(&chunk)?;
+            }
+            self.pending.push(row.clone());
+            if self.pending.len() >= self.chunk_rows {
+                let chunk = std::mem::take(&mut self.pending);
+                self.write_chunk(&chunk)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if !self.header_written {
+            // Even an empty recording describes its channels
+            self.write_header(&SystemTime::now())?;
+            self.header_written = true;
+        }
+        let rest = std::mem::take(&mut self.pending);
+        self.write_chunk(&rest)?;
+        self.file.flush()?;
+        self.file.get_mut().finish()
+    }
+}
+
+/// Reads a versioned binary recording chunk by chunk.
+pub struct BinaryReader<R: Read> {
+    input: R,
+    version: u16,
+    header: BinaryHeader,
+    max_rows: usize,    // Most rows a chunk of this recording can hold
+    buffer: Vec<u8>,    // Read from the input but not consumed yet
+    chunks_read: usize,
+    skipped_bytes: u64, // Passed over while resynchronizing after corrupt chunks
+    position: u64,      // Input offset of the end of the last chunk read or skipped
+}
+
+/// What the bytes at the read position hold
+enum Parsed {
+    Chunk(BinaryChunk),
+    End,             // The input ends, possibly inside an unfinished chunk
+    Corrupt(String), // Not a valid chunk
+}
+
+impl BinaryReader<Box<dyn Read>> {
+    /// Opens a recording file, decompressing it if needed
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let input = compression::open_reader(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        Self::new(input)
+    }
+}
+
+impl<R: Read> BinaryReader<R> {
+    /// Parses the header; fails on a missing magic, an unknown version or a bad header CRC
+    pub fn new(mut input: R) -> Result<Self, String> {
+        let mut magic = [0u8; 8];
+        input.read_exact(&mut magic).map_err(|_| "Not a versioned binary recording".to_string())?;
+        if magic != MAGIC {
+            return Err("Not a versioned binary recording".to_string());
+        }
+        let mut fixed = [0u8; 6];
+        input.read_exact(&mut fixed).map_err(|e| format!("Truncated binary header: {}", e))?;
+        let version = u16::from_le_bytes([fixed[0], fixed[1]]);
+        if !(2..=VERSION).contains(&version) {
+            return Err(format!("Unsupported binary recording version {}", version));
+        }
+        // The length is checked before the CRC can vouch for it
+        let length = u32::from_le_bytes(fixed[2..6].try_into().unwrap()) as usize;
+        if length > MAX_HEADER_BYTES {
+            return Err("Binary recording header is corrupt (implausible length)".to_string());
+        }
+        let mut json = vec![0u8; length];
+        let mut crc = [0u8; 4];
+        input
+            .read_exact(&mut json)
+            .and_then(|_| input.read_exact(&mut crc))
+            .map_err(|e| format!("Truncated binary header: {}", e))?;
+
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&fixed);
+        hasher.update(&json);
+        if hasher.finalize() != u32::from_le_bytes(crc) {
+            return Err("Binary recording header is corrupt (CRC mismatch)".to_string());
+        }
+        let header: BinaryHeader =
+            serde_json::from_slice(&json).map_err(|e| format!("Invalid binary recording header: {}", e))?;
+        if !header.sample_rate.is_finite() || header.sample_rate <= 0.0 {
+            return Err("Invalid sample rate in binary recording header".to_string());
+        }
+        // Chunks hold at most 60 s of signal; see `BinaryOptions::validate`
+        let max_rows = (header.sample_rate * 60.0).ceil() as usize + 1;
+        Ok(Self {
+            input,
+            version,
+            header,
+            max_rows,
+            buffer: Vec::new(),
+            chunks_read: 0,
+            skipped_bytes: 0,
+            position: (MAGIC.len() + fixed.len() + length + crc.len()) as u64,
+        })
+    }
+
+    pub fn header(&self) -> &BinaryHeader {
+        &self.header
+    }
+
+    /// Bytes passed over to get past corrupt chunks
+    pub fn skipped_bytes(&self) -> u64 {
+        self.skipped_bytes
+    }
+
+    /// Offset in the input up to which chunks were read; reading a file that
+    /// is still being written continues there
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Next data chunk in physical units. A chunk cut off by an interrupted
+    /// recording ends the file; a corrupt chunk is skipped, resuming at the next
+    /// chunk tag.
+    pub fn next_chunk(&mut self) -> Result<Option<BinaryChunk>, String> {
+        loop {
+            match self.parse_chunk()? {
+                Parsed::Chunk(chunk) => return Ok(Some(chunk)),
+                Parsed::End => return Ok(None),
+                Parsed::Corrupt(reason) => {
+                    eprintln!(
+                        "[Binary] Corrupt data after chunk {} ({}), skipping to the next chunk",
+                        self.chunks_read, reason
+                    );
+                    if !self.resync()? {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+    }
+
+    fn parse_chunk(&mut self) -> Result<Parsed, String> {
+        // Version 3 chunks carry the first sample index after the tag
+        let index_len = if self.version >= 3 { 8 } else { 0 };
+        let fixed_len = 26 + index_len;
+        if !self.fill(fixed_len)? {
+            return Ok(Parsed::End);
+        }
+        let fixed = &self.buffer[..fixed_len];
+        if fixed[..4] != CHUNK_TAG {
+            return Ok(Parsed::Corrupt("invalid tag".to_string()));
+        }
+        let first_index = (index_len > 0).then(|| u64::from_le_bytes(fixed[4..12].try_into().unwrap()));
+        let rest = &fixed[4 + index_len..];
+        let first_timestamp_us = u64::from_le_bytes(rest[..8].try_into().unwrap());
+        let last_timestamp_us = u64::from_le_bytes(rest[8..16].try_into().unwrap());
+        let rows = u32::from_le_bytes(rest[16..20].try_into().unwrap()) as usize;
+        let channels = u16::from_le_bytes([rest[20], rest[21]]) as usize;
+        // Lengths are checked before anything is allocated for them
+        if channels != self.header.channels.len() || rows == 0 || rows > self.max_rows {
+            return Ok(Parsed::Corrupt(format!("implausible size of {} rows by {} channels", rows, channels)));
+        }
+
+        let values_len = rows * channels * 4;
+        if !self.fill(fixed_len + values_len + 4)? {
+            return Ok(Parsed::End);
+        }
+        let values = &self.buffer[fixed_len..fixed_len + values_len];
+        let crc = &self.buffer[fixed_len + values_len..fixed_len + values_len + 4];
+        if crc32fast::hash(&self.buffer[..fixed_len + values_len]) != u32::from_le_bytes(crc.try_into().unwrap()) {
+            return Ok(Parsed::Corrupt("CRC mismatch".to_string()));
+        }
+
+        let scales: Vec<f64> = self.header.channels.iter().map(|c| c.scale).collect();
+        let format = self.header.sample_format;
+        let rows = values
+            .chunks_exact(channels.max(1) * 4)
+            .take(rows)
+            .map(|row| {
+                row.chunks_exact(4)
+                    .zip(&scales)
+                    .map(|(bytes, scale)| {
+                        let bytes: [u8; 4] = bytes.try_into().unwrap();
+                        match format {
+                            SampleFormat::F32 => f32::from_le_bytes(bytes),
+                            SampleFormat::I32 => match i32::from_le_bytes(bytes) {
+                                I32_MISSING => f32::NAN,
+                                digital => (digital as f64 * scale) as f32,
+                            },
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        self.buffer.drain(..fixed_len + values_len + 4);
+        self.position += (fixed_len + values_len + 4) as u64;
+        self.chunks_read += 1;
+        Ok(Parsed::Chunk(BinaryChunk {
+            first_index,
+            first_timestamp_us,
+            last_timestamp_us,
+            rows,
+        }))
+    }
+
+    /// Reads until the buffer holds `len` bytes; false when the input ends first.
+    /// The buffer only grows by what the input actually holds.
+    fn fill(&mut self, len: usize) -> Result<bool, String> {
+        let wanted = len.saturating_sub(self.buffer.len()) as u64;
+        if wanted == 0 {
+            return Ok(true);
+        }
+        let read = (&mut self.input)
+            .take(wanted)
+            .read_to_end(&mut self.buffer)
+            .map_err(|e| format!("Failed to read binary recording: {}", e))?;
+        Ok(read as u64 == wanted)
+    }
+
+    /// Drops `len` bytes at the read position as corrupt
+    fn skip(&mut self, len: usize) {
+        self.buffer.drain(..len);
+        self.skipped_bytes += len as u64;
+        self.position += len as u64;
+    }
+
+    /// Drops the corrupt bytes at the read position up to the next chunk tag;
+    /// false when the input ends without one
+    fn resync(&mut self) -> Result<bool, String> {
+        let mut from = 1; // The tag of the corrupt chunk itself is no way back in
+        loop {
+            let start = from.min(self.buffer.len());
+            if let Some(position) = self.buffer[start..].windows(4).position(|w| w == CHUNK_TAG) {
+                self.skip(start + position);
+                return Ok(true);
+            }
+            // Keep the last bytes, which may be the start of a tag
+            let skip = self.buffer.len().saturating_sub(CHUNK_TAG.len() - 1).max(start);
+            self.skip(skip);
+            from = 0;
+            let more = self.fill(self.buffer.len() + RESYNC_BLOCK)?;
+            if !more && !self.buffer.windows(4).any(|w| w == CHUNK_TAG) {
+                self.skip(self.buffer.len());
+                return Ok(false);
+            }
+        }
+    }
+}
+
+impl<R: Read + Seek> BinaryReader<R> {
+    /// Continues reading at `position`, the end of a chunk read before
+    pub fn seek(&mut self, position: u64) -> Result<(), String> {
+        self.input
+            .seek(SeekFrom::Start(position))
+            .map_err(|e| format!("Failed to seek in binary recording: {}", e))?;
+        self.buffer.clear();
+        self.position = position;
+        Ok(())
+    }
+}
+
+/// True when `bytes` start with the versioned binary magic
+pub fn is_versioned(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{rows, temp_dir};
+
+    /// Writes `rows` as a binary recording with 100 ms chunks and returns its bytes
+    fn write(rows: &[Row], sample_format: SampleFormat) -> Vec<u8> {
+        let path = temp_dir("binary").join("recording.bin");
+        let info = RecordingInfo {
+            labels: vec!["Fp1".to_string(), "Fp2".to_string()],
+            sample_rate: 100.0,
+            binary: BinaryOptions {
+                sample_format,
+                chunk_ms: 100,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let file = CompressedFile::new(std::fs::File::create(&path).unwrap(), &Default::default()).unwrap();
+        let mut writer = BinaryWriter::new(file, &info).unwrap();
+        writer.write_samples(rows).unwrap();
+        writer.finish().unwrap();
+        std::fs::read(&path).unwrap()
+    }
+
+    fn read_all(bytes: &[u8]) -> Result<(Vec<BinaryChunk>, u64), String> {
+        let mut reader = BinaryReader::new(bytes)?;
+        let mut chunks = Vec::new();
+        while let Some(chunk) = reader.next_chunk()? {
+            chunks.push(chunk);
+        }
+        Ok((chunks, reader.skipped_bytes()))
+    }
+
+    /// Offset of the `n`th chunk tag
+    fn chunk_offset(bytes: &[u8], n: usize) -> usize {
+        bytes.windows(4).enumerate().filter(|(_, w)| *w == CHUNK_TAG).nth(n).unwrap().0
+    }
+
+    #[test]
+    fn round_trips_both_sample_formats() {
+        let mut input = rows(25, 2, 100.0);
+        input[3].2[1] = f32::NAN;
+        for format in [SampleFormat::F32, SampleFormat::I32] {
+            let (chunks, skipped) = read_all(&write(&input, format)).unwrap();
+            assert_eq!(skipped, 0);
+            assert_eq!(chunks.iter().map(|c| c.rows.len()).collect::<Vec<_>>(), vec![10, 10, 5]);
+            assert_eq!(chunks[2].first_index, Some(20));
+            let output: Vec<_> = chunks.iter().flat_map(|c| c.rows.iter()).collect();
+            for ((_, _, expected), actual) in input.iter().zip(output) {
+                for (e, a) in expected.iter().zip(actual) {
+                    assert!(e.is_nan() && a.is_nan() || (e - a).abs() < 0.01, "{} != {}", e, a);
+                }
+            }
+            assert_eq!(chunks[0].timestamps_us()[1] - chunks[0].timestamps_us()[0], 10_000);
+        }
+    }
+
+    #[test]
+    fn gaps_in_the_index_end_chunks() {
+        let mut input = rows(30, 2, 100.0);
+        input.drain(13..17);
+        let (chunks, _) = read_all(&write(&input, SampleFormat::F32)).unwrap();
+        let layout: Vec<_> = chunks.iter().map(|c| (c.first_index.unwrap(), c.rows.len())).collect();
+        assert_eq!(layout, vec![(0, 10), (10, 3), (17, 10), (27, 3)]);
+        assert_eq!(chunks[2].timestamps_us()[0], chunks[1].last_timestamp_us + 50_000);
+    }
+
+    #[test]
+    fn skips_corrupt_chunks() {
+        let mut bytes = write(&rows(30, 2, 100.0), SampleFormat::F32);
+        let second = chunk_offset(&bytes, 1);
+        bytes[second + 40] ^= 0xff;
+        let (chunks, skipped) = read_all(&bytes).unwrap();
+        assert_eq!(chunks.iter().map(|c| c.first_index.unwrap()).collect::<Vec<_>>(), vec![0, 20]);
+        assert_eq!(skipped as usize, chunk_offset(&bytes, 2) - second);
+
+        // A row count beyond any chunk is not allocated for
+        let mut bytes = write(&rows(30, 2, 100.0), SampleFormat::F32);
+        let first = chunk_offset(&bytes, 0);
+        bytes[first + 28..first + 32].copy_from_slice(&u32::MAX.to_le_bytes());
+        let (chunks, _) = read_all(&bytes).unwrap();
+        assert_eq!(chunks.iter().map(|c| c.first_index.unwrap()).collect::<Vec<_>>(), vec![10, 20]);
+    }
+
+    #[test]
+    fn reads_complete_chunks_of_truncated_files() {
+        let bytes = write(&rows(30, 2, 100.0), SampleFormat::I32);
+        let cut = chunk_offset(&bytes, 2) + 20;
+        let (chunks, skipped) = read_all(&bytes[..cut]).unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(skipped, 0);
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        let bytes = write(&rows(5, 2, 100.0), SampleFormat::F32);
+        assert!(read_all(b"not a recording").is_err());
+
+        let mut version = bytes.clone();
+        version[8] = 9;
+        assert!(read_all(&version).unwrap_err().contains("version"));
+
+        // A header length beyond the limit fails before the CRC check allocates for it
+        let mut length = bytes.clone();
+        length[10..14].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(read_all(&length).unwrap_err().contains("implausible length"));
+
+        let mut header = bytes.clone();
+        header[20] ^= 0xff;
+        assert!(read_all(&header).unwrap_err().contains("CRC"));
+        assert!(read_all(&bytes[..20]).unwrap_err().contains("Truncated"));
+    }
+}
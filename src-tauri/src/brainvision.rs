@@ -0,0 +1,595 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::compression::{self, CompressedFile};
+use crate::recording::Annotation;
+use crate::recording_reader::{time_from_millis, Contents, Sample, SourceInfo};
+use crate::writer::{self, RecordingInfo, RecordingWriter, Row, Timeline};
+
+/// Sample encoding of the `.eeg` data file
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BrainVisionBinary {
+    Float32,
+    Int16,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BrainVisionOptions {
+    pub binary_format: BrainVisionBinary,
+    pub resolution: f64, // Microvolts per integer step; 1 for float data
+    pub unit: String,
+}
+
+impl Default for BrainVisionOptions {
+    fn default() -> Self {
+        Self {
+            binary_format: BrainVisionBinary::Float32,
+            resolution: 0.1,
+            unit: "µV".to_string(),
+        }
+    }
+}
+
+impl BrainVisionOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.resolution.is_finite() || self.resolution <= 0.0 {
+            return Err("BrainVision resolution must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    fn channel_resolution(&self) -> f64 {
+        match self.binary_format {
+            BrainVisionBinary::Float32 => 1.0,
+            BrainVisionBinary::Int16 => self.resolution,
+        }
+    }
+}
+
+/// Commas in names are written as `\1` in BrainVision files
+fn escape(value: &str) -> String {
+    value.replace(',', "\\1").replace(['\r', '\n'], " ")
+}
+
+/// Marker type and description for an annotation
+fn marker_fields(label: &str) -> (&'static str, String) {
+    match label.strip_prefix("marker:") {
+        Some(description) => ("Stimulus", escape(description)),
+        None => ("Comment", escape(label)),
+    }
+}
+
+/// Writes the BrainVision triplet: `<name>.vhdr` header, `<name>.vmrk` markers
+/// and `<name>.eeg` multiplexed binary data. With compression enabled the data
+/// file becomes `<name>.eeg.gz`/`.zst`, and the header and markers name it so;
+/// readers other than this app need it decompressed first. A gap in the sample
+/// index starts a new segment, whose marker gives the time of its first point.
+pub struct BrainVisionWriter {
+    data: BufWriter<CompressedFile>,
+    markers: File,
+    options: BrainVisionOptions,
+    sample_rate: f64,
+    next_marker: u64,
+    pending: VecDeque<Annotation>, // Markers waiting for the data points they span
+    timeline: Timeline,            // Where the data points went, for marker positions
+}
+
+impl BrainVisionWriter {
+    /// `header` is the already created `.vhdr` file at `path`
+    pub fn new(mut header: File, path: &Path, info: &RecordingInfo) -> Result<Self, String> {
+        let options = info.brainvision.clone();
+        options.validate()?;
+        if info.sample_rate <= 0.0 {
+            return Err("BrainVision requires a positive sample rate".to_string());
+        }
+        let name = |extension: &str| path.with_extension(extension);
+        let file_name = |extension: &str| {
+            name(extension)
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+        let data_name = format!("{}{}", file_name("eeg"), info.compression.suffix());
+        let create = |extension: &str| {
+            File::create(name(extension))
+                .map_err(|e| format!("Failed to create BrainVision {} file: {}", extension, e))
+        };
+
+        let binary_format = match options.binary_format {
+            BrainVisionBinary::Float32 => "IEEE_FLOAT_32",
+            BrainVisionBinary::Int16 => "INT_16",
+        };
+        let mut text = String::new();
+        text.push_str("Brain Vision Data Exchange Header File Version 1.0\r\n");
+        text.push_str("; Data created by serial-brain\r\n\r\n");
+        text.push_str("[Common Infos]\r\nCodepage=UTF-8\r\n");
+        text.push_str(&format!("DataFile={}\r\nMarkerFile={}\r\n", data_name, file_name("vmrk")));
+        text.push_str("DataFormat=BINARY\r\n");
+        text.push_str("; Data orientation: MULTIPLEXED=ch1,pt1, ch2,pt1 ...\r\nDataOrientation=MULTIPLEXED\r\n");
+        text.push_str(&format!("NumberOfChannels={}\r\n", info.labels.len()));
+        text.push_str("; Sampling interval in microseconds\r\n");
+        text.push_str(&format!("SamplingInterval={}\r\n\r\n", 1e6 / info.sample_rate));
+        text.push_str(&format!("[Binary Infos]\r\nBinaryFormat={}\r\n\r\n", binary_format));
+        text.push_str("[Channel Infos]\r\n");
+        text.push_str("; Each entry: Ch<Channel number>=<Name>,<Reference channel name>,<Resolution in \"Unit\">,<Unit>\r\n");
+        for (i, label) in info.labels.iter().enumerate() {
+            text.push_str(&format!(
+                "Ch{}={},,{},{}\r\n",
+                i + 1,
+                escape(label),
+                options.channel_resolution(),
+                options.unit
+            ));
+        }
+        header
+            .write_all(text.as_bytes())
+            .map_err(|e| format!("Failed to write BrainVision header: {}", e))?;
+
+        let mut markers = create("vmrk")?;
+        let marker_header = format!(
+            "Brain Vision Data Exchange Marker File, Version 1.0\r\n\r\n\
+             [Common Infos]\r\nCodepage=UTF-8\r\nDataFile={}\r\n\r\n\
+             [Marker Infos]\r\n\
+             ; Each entry: Mk<Marker number>=<Type>,<Description>,<Position in data points>,\r\n\
+             ; <Size in data points>, <Channel number (0 = marker is related to all channels)>\r\n",
+            data_name
+        );
+        markers
+            .write_all(marker_header.as_bytes())
+            .map_err(|e| format!("Failed to write BrainVision markers: {}", e))?;
+
+        let data = File::create(path.with_file_name(&data_name))
+            .and_then(|file| CompressedFile::new(file, &info.compression))
+            .map_err(|e| format!("Failed to create BrainVision eeg file: {}", e))?;
+        Ok(Self {
+            data: BufWriter::new(data),
+            markers,
+            options,
+            sample_rate: info.sample_rate,
+            next_marker: 1,
+            pending: VecDeque::new(),
+            timeline: Timeline::default(),
+        })
+    }
+
+    fn write_marker(
+        &mut self,
+        marker_type: &str,
+        description: &str,
+        position: u64,
+        size: u64,
+        channel: usize,
+        extra: &str,
+    ) -> io::Result<()> {
+        write!(
+            self.markers,
+            "Mk{}={},{},{},{},{}{}\r\n",
+            self.next_marker, marker_type, description, position, size, channel, extra
+        )?;
+        self.next_marker += 1;
+        Ok(())
+    }
+
+    /// Writes an annotation at the data points written around its time, so a
+    /// pause or lost samples before it don't shift it. A span reaching into a
+    /// gap ends there; one lying within a gap marks the first point after it.
+    fn write_annotation(&mut self, annotation: &Annotation) -> io::Result<()> {
+        let onset = SystemTime::UNIX_EPOCH + Duration::from_millis(annotation.onset_ms);
+        let end = onset + Duration::from_secs_f64(annotation.duration_ms.max(0.0) / 1000.0);
+        // Past the last data point, as at the end of the file, is pinned to it
+        let last = self.timeline.written().saturating_sub(1);
+        let first = self.timeline.position(onset, self.sample_rate).min(last);
+        let size = (self.timeline.position(end, self.sample_rate).min(last) - first).max(1);
+        let channel = annotation.channel.map(|c| c + 1).unwrap_or(0);
+        let (marker_type, description) = marker_fields(&annotation.label);
+        // Positions are 1-based data points
+        self.write_marker(marker_type, &description, first + 1, size, channel, "")
+    }
+
+    /// Writes the pending annotations whose span the data written so far
+    /// covers, or all of them
+    fn write_pending(&mut self, all: bool) -> io::Result<()> {
+        let mut waiting = VecDeque::new();
+        while let Some(annotation) = self.pending.pop_front() {
+            let end = annotation.onset_ms + annotation.duration_ms.max(0.0).ceil() as u64;
+            if all || self.timeline.reaches(SystemTime::UNIX_EPOCH + Duration::from_millis(end)) {
+                self.write_annotation(&annotation)?;
+            } else {
+                waiting.push_back(annotation);
+            }
+        }
+        self.pending = waiting;
+        self.markers.flush()
+    }
+}
+
+impl RecordingWriter for BrainVisionWriter {
+    fn write_samples(&mut self, rows: &[Row]) -> io::Result<()> {
+        if let (0, Some((index, first, _))) = (self.timeline.written(), rows.first()) {
+            self.write_marker("New Segment", "", 1, 1, 0, &segment_stamp(*first))?;
+            // Data points are positional; this gives the session sample index of the first one
+            self.write_marker("Comment", &writer::index_label(*index, 0), 1, 1, 0, "")?;
+            self.markers.flush()?;
+        }
+
+        for (index, timestamp, row) in rows {
+            let missing = self.timeline.add(*index, *timestamp);
+            if missing > 0 {
+                let position = self.timeline.written();
+                self.write_marker("New Segment", "", position, 1, 0, &segment_stamp(*timestamp))?;
+                self.write_marker("Comment", &writer::index_label(*index, missing), position, 1, 0, "")?;
+                self.markers.flush()?;
+            }
+            for &value in row {
+                match self.options.binary_format {
+                    BrainVisionBinary::Float32 => self.data.write_all(&value.to_le_bytes())?,
+                    BrainVisionBinary::Int16 => {
+                        let scaled = if value.is_finite() { value as f64 / self.options.resolution } else { 0.0 };
+                        let digital = scaled.round().clamp(i16::MIN as f64, i16::MAX as f64) as i16;
+                        self.data.write_all(&digital.to_le_bytes())?;
+                    }
+                }
+            }
+        }
+        self.data.flush()?;
+        if !self.pending.is_empty() {
+            self.write_pending(false)?;
+        }
+        Ok(())
+    }
+
+    fn write_annotations(&mut self, annotations: &[Annotation]) -> io::Result<bool> {
+        self.pending.extend(annotations.iter().cloned());
+        self.write_pending(false)?;
+        Ok(true)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.write_pending(true)?;
+        self.data.flush()?;
+        self.data.get_mut().finish()
+    }
+}
+
+/// Date field of a new segment marker, down to microseconds
+fn segment_stamp(time: SystemTime) -> String {
+    let date: DateTime<Utc> = time.into();
+    date.format(",%Y%m%d%H%M%S%6f").to_string()
+}
+
+/// Reverses `escape`
+fn unescape(value: &str) -> String {
+    value.replace("\\1", ",")
+}
+
+/// `key=value` entries of a BrainVision header or marker file, by section
+fn parse_sections(text: &str) -> HashMap<String, Vec<(String, String)>> {
+    let mut sections: HashMap<String, Vec<(String, String)>> = HashMap::new();
+    let mut section = String::new();
+    for line in text.lines().map(str::trim) {
+        if line.starts_with(';') || line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name.to_string();
+        } else if let Some((key, value)) = line.split_once('=') {
+            sections.entry(section.clone()).or_default().push((key.trim().to_string(), value.to_string()));
+        }
+    }
+    sections
+}
+
+/// Time of a "New Segment" marker date: `YYYYMMDDhhmmss` and microseconds, UTC
+fn segment_date(stamp: &str) -> Option<SystemTime> {
+    let stamp = stamp.trim();
+    if stamp.len() != 20 || stamp.starts_with("00000000") {
+        return None;
+    }
+    let seconds = NaiveDateTime::parse_from_str(&stamp[..14], "%Y%m%d%H%M%S").ok()?;
+    let micros: u64 = stamp[14..].parse().ok()?;
+    Some(SystemTime::from(seconds.and_utc()) + Duration::from_micros(micros))
+}
+
+/// Reads a BrainVision recording from its `.vhdr` header, with the data file
+/// (plain or compressed; a header naming `.eeg` also finds `.eeg.gz`/`.zst`)
+/// and the marker file.
+/// Data points are timed from the date of the "New Segment" marker before them;
+/// without one the recording is taken to end when its data file was last modified.
+pub fn read_brainvision(path: &Path) -> Result<Contents, String> {
+    let read_text = |path: &Path| {
+        fs::read(path)
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+    };
+    let header = parse_sections(&read_text(path)?);
+    let value = |section: &str, key: &str| {
+        header
+            .get(section)
+            .and_then(|entries| entries.iter().find(|(k, _)| k == key))
+            .map(|(_, v)| v.trim().to_string())
+    };
+    if value("Common Infos", "DataFormat").map(|f| f != "BINARY").unwrap_or(false)
+        || value("Common Infos", "DataOrientation").map(|o| o != "MULTIPLEXED").unwrap_or(false)
+    {
+        return Err("Only multiplexed binary BrainVision data is supported".to_string());
+    }
+    let channels: usize = value("Common Infos", "NumberOfChannels")
+        .and_then(|n| n.parse().ok())
+        .ok_or("BrainVision header without a channel count")?;
+    let interval_us: f64 = value("Common Infos", "SamplingInterval")
+        .and_then(|n| n.parse().ok())
+        .filter(|&n: &f64| n > 0.0)
+        .ok_or("BrainVision header without a sampling interval")?;
+    let sample_rate = 1e6 / interval_us;
+
+    let data_name = value("Common Infos", "DataFile").ok_or("BrainVision header without a data file")?;
+    let data_path = ["", ".gz", ".zst"]
+        .iter()
+        .map(|suffix| path.with_file_name(format!("{}{}", data_name, suffix)))
+        .find(|candidate| candidate.exists())
+        .ok_or_else(|| format!("BrainVision data file {} not found", data_name))?;
+    let bytes = compression::read_file(&data_path)?;
+    let (width, decode): (usize, fn(&[u8]) -> f64) = match value("Binary Infos", "BinaryFormat").as_deref() {
+        Some("IEEE_FLOAT_32") | None => (4, |b| f32::from_le_bytes(b.try_into().unwrap()) as f64),
+        Some("INT_16") => (2, |b| i16::from_le_bytes(b.try_into().unwrap()) as f64),
+        Some("INT_32") => (4, |b| i32::from_le_bytes(b.try_into().unwrap()) as f64),
+        Some(other) => return Err(format!("Unsupported BrainVision binary format {}", other)),
+    };
+    // Every channel takes a value in the data file or at least an entry in the header
+    let described = header.get("Channel Infos").map(|entries| entries.len()).unwrap_or(0);
+    if channels == 0 || channels > (bytes.len() / width).max(described) {
+        return Err(format!("Implausible BrainVision channel count {}", channels));
+    }
+    let points = bytes.len() / (width * channels);
+
+    let mut labels = Vec::with_capacity(channels);
+    let mut resolutions = Vec::with_capacity(channels);
+    for i in 0..channels {
+        let entry = value("Channel Infos", &format!("Ch{}", i + 1)).unwrap_or_default();
+        let mut fields = entry.split(',');
+        let label = fields.next().filter(|l| !l.is_empty()).map(unescape);
+        labels.push(label.unwrap_or_else(|| format!("Ch{}", i + 1)));
+        let resolution = fields.nth(1).and_then(|r| r.trim().parse().ok()).unwrap_or(1.0);
+        resolutions.push(resolution);
+    }
+
+    let marker_entries = match value("Common Infos", "MarkerFile") {
+        Some(name) if path.with_file_name(&name).exists() => {
+            parse_sections(&read_text(&path.with_file_name(&name))?).remove("Marker Infos").unwrap_or_default()
+        }
+        _ => Vec::new(),
+    };
+    let markers: Vec<Vec<String>> = marker_entries
+        .iter()
+        .filter(|(key, _)| key.starts_with("Mk"))
+        .map(|(_, entry)| entry.split(',').map(str::to_string).collect())
+        .collect();
+    // Each "New Segment" marker times the data points from its position on
+    let mut segments: Vec<(u64, f64)> = markers
+        .iter()
+        .filter(|fields| fields[0] == "New Segment")
+        .filter_map(|fields| {
+            let position = fields.get(2)?.trim().parse::<u64>().ok()?.saturating_sub(1);
+            let date = segment_date(fields.get(5)?)?;
+            Some((position, date.duration_since(SystemTime::UNIX_EPOCH).ok()?.as_secs_f64() * 1000.0))
+        })
+        .collect();
+    segments.sort_by_key(|(position, _)| *position);
+    match segments.first().copied() {
+        Some((0, _)) => {}
+        Some((position, start_ms)) => segments.insert(0, (0, start_ms - position as f64 * 1000.0 / sample_rate)),
+        None => {
+            let start = fs::metadata(&data_path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|modified| modified.checked_sub(Duration::from_secs_f64(points as f64 / sample_rate)))
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            segments.push((0, start.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs_f64() * 1000.0));
+        }
+    }
+    let time_ms = |point: u64| {
+        let (first, start_ms) = segments[segments.partition_point(|(position, _)| *position <= point) - 1];
+        start_ms + (point - first) as f64 * 1000.0 / sample_rate
+    };
+
+    let samples = bytes
+        .chunks_exact(width * channels)
+        .enumerate()
+        .map(|(point, row)| {
+            let values = row
+                .chunks_exact(width)
+                .zip(&resolutions)
+                .map(|(value, resolution)| (decode(value) * resolution) as f32)
+                .collect();
+            Sample {
+                time: time_from_millis(time_ms(point as u64)),
+                index: None,
+                values,
+            }
+        })
+        .collect();
+
+    let mut annotations = Vec::new();
+    for fields in &markers {
+        if fields.len() < 5 || fields[0] == "New Segment" {
+            continue;
+        }
+        let (Ok(position), Ok(size)) = (fields[2].trim().parse::<u64>(), fields[3].trim().parse::<u64>()) else {
+            continue;
+        };
+        let description = unescape(&fields[1]);
+        let label = match fields[0].as_str() {
+            "Stimulus" => format!("marker:{}", description),
+            _ if !description.is_empty() => description,
+            other => other.to_string(),
+        };
+        // Positions are 1-based; a size of one data point is how instants are written
+        annotations.push(Annotation {
+            onset_ms: time_ms(position.saturating_sub(1)).round().max(0.0) as u64,
+            duration_ms: if size > 1 { size as f64 * 1000.0 / sample_rate } else { 0.0 },
+            channel: fields[4].trim().parse::<usize>().ok().filter(|&c| c > 0).map(|c| c - 1),
+            label,
+        });
+    }
+    Ok(Contents {
+        labels,
+        samples,
+        annotations,
+        source: SourceInfo {
+            sample_rate: Some(sample_rate),
+            ..Default::default()
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::{Compression, CompressionConfig};
+    use crate::test_utils::{micros_apart, millis, rows, temp_dir};
+
+    fn annotation(onset: SystemTime, duration_ms: f64, label: &str) -> Annotation {
+        Annotation {
+            onset_ms: millis(onset),
+            duration_ms,
+            channel: None,
+            label: label.to_string(),
+        }
+    }
+
+    #[test]
+    fn markers_follow_the_written_data_points() {
+        let mut rows = rows(1000, 2, 250.0);
+        let gap: Vec<Row> = rows.drain(400..600).collect();
+        let after = rows[500].1; // Sample index 700
+        let info = RecordingInfo {
+            labels: vec!["C3".to_string(), "C4".to_string()],
+            sample_rate: 250.0,
+            ..Default::default()
+        };
+        let path = temp_dir("brainvision").join("recording.vhdr");
+        let mut writer = writer::create_writer("brainvision", &path, &info).unwrap();
+        writer
+            .write_annotations(&[
+                annotation(after, 400.0, "blink"),
+                annotation(gap[0].1, 800.0, "pause"),
+            ])
+            .unwrap();
+        writer.write_samples(&rows).unwrap();
+        writer.finish().unwrap();
+
+        let markers = std::fs::read_to_string(path.with_extension("vmrk")).unwrap();
+        assert!(markers.contains("=Comment,blink,501,100,0"), "{}", markers);
+        assert!(markers.contains("=Comment,pause,401,1,0"), "{}", markers);
+        assert!(markers.contains("=Comment,Sample index 600 after 200 missing samples,401,1,0"), "{}", markers);
+
+        let Contents { samples, annotations, .. } = read_brainvision(&path).unwrap();
+        assert_eq!(samples.len(), rows.len());
+        for (sample, (_, time, _)) in samples.iter().zip(&rows) {
+            assert!(micros_apart(sample.time, *time) <= 1);
+        }
+        let blink = annotations.iter().find(|a| a.label == "blink").unwrap();
+        assert_eq!((blink.onset_ms, blink.duration_ms), (millis(after), 400.0));
+        let pause = annotations.iter().find(|a| a.label == "pause").unwrap();
+        assert_eq!((pause.onset_ms, pause.duration_ms), (millis(rows[400].1), 0.0));
+    }
+
+    #[test]
+    fn int16_data_round_trips_through_compressed_files() {
+        let rows = rows(500, 3, 500.0);
+        let info = RecordingInfo {
+            labels: vec!["Fp1,left".to_string(), "Fp2".to_string(), "Cz".to_string()],
+            sample_rate: 500.0,
+            brainvision: BrainVisionOptions {
+                binary_format: BrainVisionBinary::Int16,
+                resolution: 0.1,
+                ..Default::default()
+            },
+            compression: CompressionConfig {
+                method: Compression::Gzip,
+                level: None,
+            },
+            ..Default::default()
+        };
+        let path = temp_dir("brainvision").join("recording.vhdr");
+        let mut writer = writer::create_writer("brainvision", &path, &info).unwrap();
+        writer.write_samples(&rows).unwrap();
+        writer.write_annotations(&[annotation(rows[100].1, 0.0, "marker:S  1,x")]).unwrap();
+        writer.finish().unwrap();
+        assert!(path.with_file_name("recording.eeg.gz").exists());
+
+        let Contents {
+            labels,
+            samples,
+            annotations,
+            source,
+        } = read_brainvision(&path).unwrap();
+        assert_eq!(labels, info.labels);
+        assert_eq!(source.sample_rate, Some(500.0));
+        assert_eq!(samples.len(), rows.len());
+        for (sample, (_, time, values)) in samples.iter().zip(&rows) {
+            assert!(micros_apart(sample.time, *time) <= 1);
+            for (read, written) in sample.values.iter().zip(values) {
+                assert!((read - written).abs() <= 0.051, "{} != {}", read, written);
+            }
+        }
+        let marker = annotations.iter().find(|a| a.label.starts_with("marker:")).unwrap();
+        assert_eq!(marker.label, "marker:S  1,x");
+        assert_eq!((marker.onset_ms, marker.duration_ms), (millis(rows[100].1), 0.0));
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        let path = temp_dir("brainvision").join("r.vhdr");
+        std::fs::write(path.with_extension("eeg"), [0u8; 64]).unwrap();
+        let cases = [
+            ("DataFile=r.eeg\nDataFormat=ASCII\nNumberOfChannels=2\nSamplingInterval=4000", "multiplexed"),
+            ("DataFile=r.eeg\nDataOrientation=VECTORIZED\nNumberOfChannels=2\nSamplingInterval=4000", "multiplexed"),
+            ("DataFile=r.eeg\nSamplingInterval=4000", "channel count"),
+            ("DataFile=r.eeg\nNumberOfChannels=2\nSamplingInterval=0", "sampling interval"),
+            ("NumberOfChannels=2\nSamplingInterval=4000", "without a data file"),
+            ("DataFile=missing.eeg\nNumberOfChannels=2\nSamplingInterval=4000", "not found"),
+            (
+                "DataFile=r.eeg\nNumberOfChannels=2\nSamplingInterval=4000\n[Binary Infos]\nBinaryFormat=UINT_8",
+                "Unsupported",
+            ),
+        ];
+        for (entries, error) in cases {
+            let header = format!("Brain Vision Data Exchange Header File Version 1.0\n[Common Infos]\n{}\n", entries);
+            std::fs::write(&path, header).unwrap();
+            let result = read_brainvision(&path).unwrap_err();
+            assert!(result.contains(error), "{}: {}", entries, result);
+        }
+
+        // A data point cut short at the end of the data file is left out
+        let header = "Brain Vision Data Exchange Header File Version 1.0\n[Common Infos]\n\
+                      DataFile=r.eeg\nNumberOfChannels=3\nSamplingInterval=4000\n";
+        std::fs::write(&path, header).unwrap();
+        let contents = read_brainvision(&path).unwrap();
+        assert_eq!(contents.samples.len(), 5);
+        assert_eq!(contents.labels, vec!["Ch1", "Ch2", "Ch3"]);
+    }
+
+    #[test]
+    fn rejects_implausible_channel_counts() {
+        let path = temp_dir("brainvision").join("recording.vhdr");
+        std::fs::write(path.with_extension("eeg"), [0u8; 64]).unwrap();
+        for count in ["0", "4000000000"] {
+            let header = format!(
+                "Brain Vision Data Exchange Header File Version 1.0\n[Common Infos]\nDataFile=recording.eeg\n\
+                 DataFormat=BINARY\nDataOrientation=MULTIPLEXED\nNumberOfChannels={}\nSamplingInterval=4000\n",
+                count
+            );
+            std::fs::write(&path, header).unwrap();
+            assert!(read_brainvision(&path).unwrap_err().contains("Implausible"), "{}", count);
+        }
+    }
+}
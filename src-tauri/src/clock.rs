@@ -0,0 +1,183 @@
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+/// A sync point is recorded at least this often
+const SYNC_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Falling behind the monotonic clock by more than this means samples were lost
+/// (a stalled or reconnected device); the index then skips ahead over the gap
+const MAX_LAG: Duration = Duration::from_secs(1);
+
+/// Most the stamps are moved toward arrival times per sample, as a fraction of
+/// the sample period: enough to follow a device clock 0.5% off its nominal
+/// rate, too little to follow the jitter of batched reads
+const MAX_SLEW: f64 = 0.005;
+
+/// System clock reading taken when a sample arrived, relating the nominal
+/// session time of that sample to wall-clock time and to the monotonic clock
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncPoint {
+    pub sample_index: u64,
+    pub session_time_s: f64, // Nominal time of the sample: index / sample rate
+    pub monotonic_s: f64,    // Monotonic time since the first sample
+    pub wall_time_ms: u64,   // System clock, milliseconds since the Unix epoch
+    #[serde(default)]
+    pub slew_s: f64,         // Correction added to the nominal time in the sample's stamp
+}
+
+/// Stamps the samples of a session from their index and the nominal rate.
+///
+/// Sample `i` is stamped `t0 + i / rate + slew`, where `t0` is the wall-clock
+/// time of the first sample. Samples decoded from one read get distinct, evenly
+/// spaced times and later changes of the system clock (NTP) don't move them;
+/// sync points record how the nominal times relate to the system clock instead.
+///
+/// `slew` follows the arrival times on the monotonic clock, by at most
+/// `MAX_SLEW` of a sample period per sample, so the stamps of a device running
+/// slightly fast or slow stay close to the time the samples arrived. A device
+/// falling behind by more than `MAX_LAG` lost samples; the index skips them.
+pub struct SessionClock {
+    rate: f64,
+    anchor: Option<(Instant, SystemTime)>, // Monotonic and wall-clock time of sample 0
+    slew: f64,                             // Seconds added to the nominal times
+    next_index: u64,
+    last_sync: Option<Instant>,
+    sync_points: Vec<SyncPoint>, // Recorded but not yet stored in the session manifest
+}
+
+impl SessionClock {
+    pub fn new(rate: f64) -> Self {
+        Self {
+            rate,
+            anchor: None,
+            slew: 0.0,
+            next_index: 0,
+            last_sync: None,
+            sync_points: Vec::new(),
+        }
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Index of a sample that just arrived
+    pub fn tick(&mut self) -> u64 {
+        self.tick_at(Instant::now(), SystemTime::now())
+    }
+
+    /// Index of a sample that arrived at `now`, when the system clock read `wall`
+    fn tick_at(&mut self, now: Instant, wall: SystemTime) -> u64 {
+        let (started, _) = *self.anchor.get_or_insert((now, wall));
+        let elapsed = now.duration_since(started).as_secs_f64();
+        let lag = elapsed - (self.next_index as f64 / self.rate + self.slew);
+        if lag > MAX_LAG.as_secs_f64() {
+            let expected = ((elapsed - self.slew) * self.rate) as u64;
+            println!(
+                "[Clock] {} samples missing at index {}, skipping ahead",
+                expected - self.next_index,
+                self.next_index
+            );
+            self.next_index = expected;
+            self.last_sync = None;
+        } else {
+            let step = MAX_SLEW / self.rate;
+            self.slew += lag.clamp(-step, step);
+        }
+        let index = self.next_index;
+        self.next_index += 1;
+        if self.last_sync.map(|last| now.duration_since(last) >= SYNC_INTERVAL).unwrap_or(true) {
+            self.last_sync = Some(now);
+            self.sync_points.push(SyncPoint {
+                sample_index: index,
+                session_time_s: index as f64 / self.rate,
+                monotonic_s: elapsed,
+                wall_time_ms: wall.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_millis() as u64,
+                slew_s: self.slew,
+            });
+        }
+        index
+    }
+
+    /// Time of sample `index` on a grid of `rate` Hz starting at sample 0
+    pub fn time_at(&self, index: u64, rate: f64) -> SystemTime {
+        let start = self.anchor.map(|(_, wall)| wall).unwrap_or_else(SystemTime::now);
+        let seconds = index as f64 / rate + self.slew;
+        if seconds >= 0.0 {
+            start + Duration::from_secs_f64(seconds)
+        } else {
+            start - Duration::from_secs_f64(-seconds)
+        }
+    }
+
+    /// Index and time of the sample being acquired now, from the monotonic
+    /// clock; None before the first sample
+    pub fn now(&self) -> Option<(u64, SystemTime)> {
+        let (started, _) = self.anchor?;
+        let index = ((started.elapsed().as_secs_f64() - self.slew).max(0.0) * self.rate) as u64;
+        Some((index, self.time_at(index, self.rate)))
+    }
+
+    pub fn take_sync_points(&mut self) -> Vec<SyncPoint> {
+        std::mem::take(&mut self.sync_points)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ticks `count` samples arriving every `period_s` seconds, in reads of `batch`
+    /// samples; returns each sample's stamp and arrival, in seconds after the first
+    fn run(clock: &mut SessionClock, count: usize, period_s: f64, batch: usize) -> Vec<(f64, f64)> {
+        let started = Instant::now();
+        let wall = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        (0..count)
+            .map(|i| {
+                // A read delivers every sample that arrived since the last one
+                let arrival = ((i / batch + 1) * batch - 1) as f64 * period_s;
+                let index = clock.tick_at(started + Duration::from_secs_f64(arrival), wall);
+                let stamp = clock.time_at(index, clock.rate()).duration_since(wall).unwrap().as_secs_f64();
+                (stamp, arrival)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stamps_are_evenly_spaced_at_the_nominal_rate() {
+        let mut clock = SessionClock::new(250.0);
+        let stamps = run(&mut clock, 2500, 1.0 / 250.0, 10);
+        for (i, (stamp, _)) in stamps.iter().enumerate().skip(1) {
+            assert!((stamp - stamps[i - 1].0 - 0.004).abs() < 1e-4, "sample {}", i);
+        }
+        assert!((stamps.last().unwrap().0 - 9.996).abs() < 0.04);
+    }
+
+    #[test]
+    fn follows_devices_off_their_nominal_rate() {
+        for actual_rate in [250.75, 249.25] {
+            let mut clock = SessionClock::new(250.0);
+            let stamps = run(&mut clock, 60_000, 1.0 / actual_rate, 5);
+            // Unslewed the stamps would be 0.3% (0.7 s) off the arrival times by now
+            let (stamp, arrival) = stamps.last().unwrap();
+            assert!((stamp - arrival).abs() < 0.03, "{} Hz: {} vs {}", actual_rate, stamp, arrival);
+            assert!(stamps.windows(2).all(|w| w[1].0 > w[0].0));
+        }
+    }
+
+    #[test]
+    fn skips_samples_lost_in_a_stall() {
+        let mut clock = SessionClock::new(100.0);
+        let started = Instant::now();
+        let wall = SystemTime::UNIX_EPOCH;
+        for i in 0..100 {
+            assert_eq!(clock.tick_at(started + Duration::from_millis(i * 10), wall), i);
+        }
+        // Nothing arrives for 3 s
+        let index = clock.tick_at(started + Duration::from_millis(4000), wall);
+        assert!((399..=401).contains(&index), "{}", index);
+        assert_eq!(clock.tick_at(started + Duration::from_millis(4010), wall), index + 1);
+        assert_eq!(clock.take_sync_points().last().unwrap().sample_index, index);
+    }
+}
@@ -1,13 +1,38 @@
+use crate::artifact::{ArtifactConfig, ArtifactEngine};
+use crate::binary::{BinaryHeader, BinaryOptions, BinaryReader};
+use crate::brainvision::BrainVisionOptions;
+use crate::compression::{self, CompressionConfig};
+use crate::convert::{self, ConversionSummary};
+use crate::edf::EdfOptions;
+use crate::expression::{CompiledExpression, DerivedExpression, EvalContext};
+use crate::impedance::{self, ImpedanceConfig};
+use crate::integrity::{self, VerificationReport};
+use crate::jsonl::{self, JsonValidation};
+use crate::markers::{self, Marker, MarkerRequest};
 use crate::mdns;
+use crate::montage::{self, CompiledMontage, Montage};
+use crate::pipeline::PipelineStats;
 use crate::reader::{
     reader_loop, FakeBinaryReader, SerialBinaryReader, SocketBinaryReader,
 };
+use crate::quality::QualityThresholds;
+use crate::recording_reader::{self, RecordingMetadata, RecordingWindow};
+use crate::resample::{ResampleConfig, Resampler};
+use crate::rotation::RotationConfig;
+use crate::session::{self, SessionDetails, SessionDetailsUpdate, SessionInspection, SessionMetadata, SessionSummary};
+use crate::spectrum::{BandPower, FrequencyBand, SpectrumConfig};
 use crate::state::AppState;
-use crate::types::{FakeDataConfig};
+use crate::storage::{self, RetentionReport, StorageConfig, StorageStatus};
+use crate::trigger::{self, TriggerConfig};
+use crate::types::{DeviceInfo, FakeDataConfig};
+use crate::writer::RecordingInfo;
 use serialport;
 use std::{
-    path::Path,
-    sync::{atomic::Ordering, mpsc, Arc},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
     thread,
 };
 use tauri::{AppHandle, Emitter, Manager, State};
@@ -33,6 +58,15 @@ pub fn connect_serial(
     let (tx, rx) = mpsc::channel::<String>();
     *state.communication.outbound_tx.lock().unwrap() = Some(tx);
 
+    *state.stream.device.lock().unwrap() = DeviceInfo {
+        kind: "serial".to_string(),
+        port: Some(port.clone()),
+        baud_rate: Some(baud_rate),
+        stop_bits: Some(stop_bits),
+        parity: Some(parity.clone()),
+        data_bits: Some(data_bits),
+        ..Default::default()
+    };
     let reader = SerialBinaryReader::new(port.clone(), baud_rate, stop_bits, parity, data_bits, rx);
     state.stream.signal_stream_running.store(true, Ordering::SeqCst);
     let running_flag = state.stream.signal_stream_running.clone();
@@ -74,6 +108,12 @@ pub fn connect_socket(
 
     // Create the reader but don't set it up yet - setup will be done in reader_loop
     // Pass the app_handle to the reader so it can emit socket status events
+    *state.stream.device.lock().unwrap() = DeviceInfo {
+        kind: "socket".to_string(),
+        host: Some(host.clone()),
+        tcp_port: Some(port),
+        ..Default::default()
+    };
     let reader = SocketBinaryReader::new(host.clone(), port)
         .with_app_handle(app_handle.clone());
     state.stream.signal_stream_running.store(true, Ordering::SeqCst);
@@ -113,8 +153,15 @@ pub fn start_fake_data(
 
     // Set the fake signal data enabled flag to true
     state.stream.fake_signal_enabled.store(true, Ordering::SeqCst);
+    // Fake packets are generated at the configured frequency
+    *state.stream.sample_rate.lock().unwrap() = config.frequency;
+    *state.stream.device.lock().unwrap() = DeviceInfo {
+        kind: "fake".to_string(),
+        ..Default::default()
+    };
     
-    let reader = FakeBinaryReader::new(config);
+    let reader = FakeBinaryReader::new(config)
+        .with_impedance(state.impedance.active.clone(), state.impedance.config.clone());
     state.stream.signal_stream_running.store(true, Ordering::SeqCst);
     let running_flag = state.stream.signal_stream_running.clone();
     let state_inner = state.inner().clone();
@@ -189,6 +236,18 @@ pub fn stop_recording(app_handle: AppHandle) -> Result<(), String> {
     crate::recording::stop_recording(app_handle)
 }
 
+/// Stops writing samples and video frames without closing the recording files
+#[tauri::command]
+pub fn pause_recording(app_handle: AppHandle) -> Result<(), String> {
+    crate::recording::pause_recording(app_handle)
+}
+
+/// Continues a paused recording; returns the length of the pause in ms
+#[tauri::command]
+pub fn resume_recording(app_handle: AppHandle) -> Result<f64, String> {
+    crate::recording::resume_recording(app_handle)
+}
+
 
 #[tauri::command]
 pub async fn record_video_stream(
@@ -218,6 +277,10 @@ pub async fn stop_video_recording(
         // Clone app_handle to avoid move issues with borrow
         let _ = tauri_plugin_record_stream::stop_record(app_handle.clone());
         state.recording.video_recording_active.store(false, Ordering::SeqCst);
+        *state.recording.video_filename.lock().unwrap() = None;
+        if let Some(path) = state.recording.video_path.lock().unwrap().take() {
+            integrity::seal_video(path);
+        }
     }
     
     Ok(true)
@@ -241,12 +304,21 @@ pub async fn push_video_frame(
     frame_data: Vec<u8>,
     width: u32,
     height: u32,
+    state: tauri::State<'_, Arc<AppState>>,
 ) -> Result<tauri_plugin_record_stream::FrameAnalysisResponse, String> {
     // Don't print the raw data - it would flood the console
     println!("[Main App] Pushing video frame...({} x {})", width, height);
     
-    tauri_plugin_record_stream::push_frame(app_handle, frame_data, width, height)
-        .map_err(|e| format!("Failed to push video frame: {}", e))
+    if !state.recording.video_recording_active.load(Ordering::SeqCst) {
+        return Err("Video recording is not active".to_string());
+    }
+    if state.recording.paused.load(Ordering::SeqCst) {
+        return Err("Recording is paused".to_string());
+    }
+    let analysis = tauri_plugin_record_stream::push_frame(app_handle, frame_data, width, height)
+        .map_err(|e| format!("Failed to push video frame: {}", e))?;
+    state.recording.add_video_frame(std::time::SystemTime::now());
+    Ok(analysis)
 }
 
 #[tauri::command]
@@ -255,6 +327,13 @@ pub async fn start_stream_recording(
     file_path: String,
     state: tauri::State<'_, Arc<AppState>>,
 ) -> Result<bool, String> {
+    let video_file = Path::new(&file_path)
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let requested = Path::new(&file_path).parent().map(|p| p.to_string_lossy().into_owned()).unwrap_or_default();
+    let full_path = Path::new(&session::video_directory(&state, &requested)).join(&video_file);
+    let file_path = full_path.to_string_lossy().into_owned();
     println!("[Main App] Starting video stream recording to: {}", file_path);
     
     // Start the recording in the plugin
@@ -268,6 +347,9 @@ pub async fn start_stream_recording(
     // If successful, set the recording flag in SerialState
     if result.success {
         state.recording.video_recording_active.store(true, std::sync::atomic::Ordering::SeqCst);
+        session::attach_video(&state, &video_file);
+        *state.recording.video_filename.lock().unwrap() = Some(video_file);
+        *state.recording.video_path.lock().unwrap() = Some(full_path);
         println!("[Main App] Video recording started successfully");
     }
     
@@ -283,10 +365,15 @@ pub async fn stop_stream_recording(
     
     // First set the recording flag to false to stop streaming frames
     state.recording.video_recording_active.store(false, std::sync::atomic::Ordering::SeqCst);
+    *state.recording.video_filename.lock().unwrap() = None;
+    let video_path = state.recording.video_path.lock().unwrap().take();
     
     // Then stop the recording in the plugin
     let result = tauri_plugin_record_stream::stop_record(app_handle)
         .map_err(|e| format!("Failed to stop video recording: {}", e))?;
+    if let Some(path) = video_path {
+        integrity::seal_video(path);
+    }
     
     println!("[Main App] Video recording stopped successfully");
     
@@ -354,6 +441,553 @@ pub fn set_default_stream_url(app_handle: AppHandle, url: String) -> Result<(),
     Ok(())
 }
 
+#[tauri::command]
+pub fn set_sample_rate(sample_rate: f64, state: State<Arc<AppState>>) -> Result<(), String> {
+    if !sample_rate.is_finite() || sample_rate <= 0.0 {
+        return Err("Sample rate must be positive".to_string());
+    }
+    *state.stream.sample_rate.lock().unwrap() = sample_rate;
+    println!("Signal sample rate set to: {} Hz", sample_rate);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn configure_spectrum(config: SpectrumConfig, state: State<Arc<AppState>>) -> Result<(), String> {
+    config.validate()?;
+    state.spectrum.engine.lock().unwrap().set_config(config);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_spectrum_config(state: State<Arc<AppState>>) -> SpectrumConfig {
+    state.spectrum.engine.lock().unwrap().config().clone()
+}
+
+#[tauri::command]
+pub fn get_band_power(
+    bands: Option<Vec<FrequencyBand>>,
+    state: State<Arc<AppState>>,
+) -> Result<Vec<BandPower>, String> {
+    let sample_rate = state.stream.get_sample_rate();
+    let engine = state.spectrum.engine.lock().unwrap();
+    // Compute on demand so callers can ask for arbitrary bands between spectrum events
+    let bands = bands.unwrap_or_else(|| engine.config().bands());
+    for band in &bands {
+        if band.low < 0.0 || band.high <= band.low {
+            return Err(format!("Invalid frequency band '{}'", band.name));
+        }
+    }
+    match engine.compute(sample_rate, &bands) {
+        Some(result) => Ok(result.band_powers),
+        None => Err(format!(
+            "Insufficient data for band power: {} of {} samples buffered",
+            engine.buffered(),
+            engine.config().window_size
+        )),
+    }
+}
+
+#[tauri::command]
+pub fn set_quality_thresholds(
+    thresholds: QualityThresholds,
+    state: State<Arc<AppState>>,
+) -> Result<(), String> {
+    thresholds.validate()?;
+    state.signal_quality.engine.lock().unwrap().set_thresholds(thresholds);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_quality_thresholds(state: State<Arc<AppState>>) -> QualityThresholds {
+    state.signal_quality.engine.lock().unwrap().thresholds().clone()
+}
+
+#[tauri::command]
+pub fn start_impedance(app_handle: AppHandle, config: Option<ImpedanceConfig>) -> Result<(), String> {
+    // Delegate to the implementation in the impedance module
+    impedance::start_impedance(app_handle, config)
+}
+
+#[tauri::command]
+pub fn stop_impedance(app_handle: AppHandle) -> Result<(), String> {
+    // Delegate to the implementation in the impedance module
+    impedance::stop_impedance(app_handle)
+}
+
+#[tauri::command]
+pub fn get_impedance_config(state: State<Arc<AppState>>) -> ImpedanceConfig {
+    state.impedance.config.lock().unwrap().clone()
+}
+
+#[tauri::command]
+pub fn configure_artifact_detection(config: ArtifactConfig, state: State<Arc<AppState>>) -> Result<(), String> {
+    config.validate()?;
+    // Rebuild the detectors so their filters start from the new thresholds
+    *state.artifact.engine.lock().unwrap() = ArtifactEngine::new(config);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_artifact_config(state: State<Arc<AppState>>) -> ArtifactConfig {
+    state.artifact.engine.lock().unwrap().config().clone()
+}
+
+#[tauri::command]
+pub fn set_derived_channels(
+    channels: Vec<DerivedExpression>,
+    state: State<Arc<AppState>>,
+) -> Result<(), String> {
+    // Check every expression against the current channels before replacing the set
+    let labels = state.montage.labels();
+    let raw = [0f32; 8];
+    let display = vec![0f32; labels.len()];
+    let ctx = EvalContext {
+        raw: &raw,
+        display: &display,
+        labels: &labels,
+        sample_rate: state.stream.get_sample_rate(),
+    };
+    let mut compiled = Vec::with_capacity(channels.len());
+    for definition in &channels {
+        if channels.iter().filter(|c| c.name == definition.name).count() > 1 {
+            return Err(format!("Duplicate derived channel '{}'", definition.name));
+        }
+        let expression = CompiledExpression::parse(definition)?;
+        expression.check(&ctx)?;
+        compiled.push(expression);
+    }
+
+    *state.expressions.definitions.lock().unwrap() = channels;
+    *state.expressions.compiled.lock().unwrap() = compiled;
+    // The buffered rows have a different channel count than the new set
+    state.buffer.active_buffer.lock().unwrap().clear();
+    state.buffer.read_buffer.lock().unwrap().clear();
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_derived_channels(state: State<Arc<AppState>>) -> Vec<DerivedExpression> {
+    state.expressions.definitions.lock().unwrap().clone()
+}
+
+#[tauri::command]
+pub fn configure_recording_trigger(config: TriggerConfig, state: State<Arc<AppState>>) -> Result<(), String> {
+    config.validate()?;
+    if state.trigger.armed.load(Ordering::SeqCst) {
+        return Err("Disarm the trigger before changing it".to_string());
+    }
+    *state.trigger.config.lock().unwrap() = config;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_recording_trigger(state: State<Arc<AppState>>) -> TriggerConfig {
+    state.trigger.config.lock().unwrap().clone()
+}
+
+#[tauri::command]
+pub fn disarm_recording_trigger(app_handle: AppHandle) {
+    trigger::disarm_trigger(&app_handle);
+}
+
+/// Publishes an external marker; fires an armed marker trigger and annotates a running recording
+#[tauri::command]
+pub fn send_marker(app_handle: AppHandle, label: String) -> Result<(), String> {
+    markers::add_marker(
+        &app_handle,
+        MarkerRequest {
+            label,
+            duration_ms: 0.0,
+            channel: None,
+            source: Some("external".to_string()),
+        },
+    )
+    .map(|_| ())
+}
+
+/// Places a marker on the acquisition clock and returns it as stamped
+#[tauri::command]
+pub fn add_marker(app_handle: AppHandle, marker: MarkerRequest) -> Result<Marker, String> {
+    markers::add_marker(&app_handle, marker)
+}
+
+/// Markers placed since startup, or only those after `after_id`
+#[tauri::command]
+pub fn get_markers(after_id: Option<u64>, state: State<Arc<AppState>>) -> Vec<Marker> {
+    state.markers.recent(after_id)
+}
+
+/// Sets the "host:port" UDP targets that receive every marker as JSON
+#[tauri::command]
+pub fn set_marker_outputs(targets: Vec<String>, state: State<Arc<AppState>>) -> Result<(), String> {
+    state.markers.set_outputs(targets)
+}
+
+#[tauri::command]
+pub fn get_marker_outputs(state: State<Arc<AppState>>) -> Vec<String> {
+    state.markers.outputs()
+}
+
+/// Sets the "host:port" UDP targets that receive every displayed sample as JSON,
+/// at the resampled output rate when resampling is enabled
+#[tauri::command]
+pub fn set_sample_outputs(targets: Vec<String>, state: State<Arc<AppState>>) -> Result<(), String> {
+    state.sample_output.set_outputs(targets)
+}
+
+#[tauri::command]
+pub fn get_sample_outputs(state: State<Arc<AppState>>) -> Vec<String> {
+    state.sample_output.outputs()
+}
+
+/// Updates subject, session, operator, protocol, filters and notes. Without `id`
+/// the change applies to the running session and the ones after it; with the ID
+/// of a session stored in `directory` it edits that earlier session.
+#[tauri::command]
+pub fn update_session_metadata(
+    update: SessionDetailsUpdate,
+    directory: Option<String>,
+    id: Option<String>,
+    state: State<Arc<AppState>>,
+) -> Result<SessionDetails, String> {
+    match id {
+        Some(id) => {
+            let directory = directory.ok_or("The directory of the session is required")?;
+            session::update_stored(Path::new(&directory), &id, update)
+        }
+        None => Ok(session::update_details(&state, update)),
+    }
+}
+
+/// Manifest of the running session, if any
+#[tauri::command]
+pub fn get_session_metadata(state: State<Arc<AppState>>) -> Option<SessionMetadata> {
+    state.session.active.lock().unwrap().as_ref().map(|session| session.metadata.clone())
+}
+
+/// Sessions stored in `directory`, oldest first
+#[tauri::command]
+pub fn list_sessions(directory: String, state: State<Arc<AppState>>) -> Result<Vec<SessionSummary>, String> {
+    session::list_sessions(&state, Path::new(&directory))
+}
+
+/// Manifest of a stored session and the files in its directory
+#[tauri::command]
+pub fn inspect_session(directory: String, id: String) -> Result<SessionInspection, String> {
+    session::inspect_session(Path::new(&directory), &id)
+}
+
+/// Deletes a stored session with all its segments and videos; the running session can't be deleted
+#[tauri::command]
+pub fn delete_session(directory: String, id: String, state: State<Arc<AppState>>) -> Result<(), String> {
+    session::delete_session(&state, Path::new(&directory), &id)
+}
+
+/// Checks the sealed signal and video files of session `id` in `directory` against
+/// their SHA-256 checksums, reporting missing, truncated, altered and unsealed files
+#[tauri::command(async)]
+pub fn verify_recording(directory: String, id: String) -> Result<VerificationReport, String> {
+    integrity::verify_recording(Path::new(&directory), &id)
+}
+
+/// Sets the free space thresholds of the recording directory and the retention policy
+#[tauri::command]
+pub fn set_storage_options(config: StorageConfig, state: State<Arc<AppState>>) -> Result<(), String> {
+    config.validate()?;
+    *state.storage.config.lock().unwrap() = config;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_storage_options(state: State<Arc<AppState>>) -> StorageConfig {
+    state.storage.config.lock().unwrap().clone()
+}
+
+/// Free space of `directory` and the threshold level it is at
+#[tauri::command]
+pub fn get_storage_status(directory: String, state: State<Arc<AppState>>) -> Result<StorageStatus, String> {
+    storage::storage_status(&state, Path::new(&directory))
+}
+
+/// Deletes the sessions in `directory` the retention policy doesn't keep; `dry_run` only lists them
+#[tauri::command(async)]
+pub fn apply_retention(directory: String, dry_run: bool, state: State<Arc<AppState>>) -> Result<RetentionReport, String> {
+    storage::apply_retention(&state, Path::new(&directory), dry_run)
+}
+
+#[tauri::command]
+pub fn get_session_details(state: State<Arc<AppState>>) -> SessionDetails {
+    state.session.details.lock().unwrap().clone()
+}
+
+/// Sets the compression of recording segments created from now on
+#[tauri::command]
+pub fn set_recording_compression(config: CompressionConfig, state: State<Arc<AppState>>) -> Result<(), String> {
+    config.validate()?;
+    *state.recording.compression.lock().unwrap() = config;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_recording_compression(state: State<Arc<AppState>>) -> CompressionConfig {
+    state.recording.compression.lock().unwrap().clone()
+}
+
+/// Counters of the recording pipeline: rows queued, spilled to disk, written and dropped
+#[tauri::command]
+pub fn get_recording_pipeline_stats(state: State<Arc<AppState>>) -> PipelineStats {
+    state.recording.pipeline.stats()
+}
+
+/// Sets the size, sample count and clock limits that start a new segment; applies from the next segment
+#[tauri::command]
+pub fn set_rotation_options(config: RotationConfig, state: State<Arc<AppState>>) -> Result<(), String> {
+    config.validate()?;
+    *state.recording.rotation.lock().unwrap() = config;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_rotation_options(state: State<Arc<AppState>>) -> RotationConfig {
+    state.recording.rotation.lock().unwrap().clone()
+}
+
+#[tauri::command]
+pub fn set_binary_options(options: BinaryOptions, state: State<Arc<AppState>>) -> Result<(), String> {
+    options.validate()?;
+    *state.recording.binary_options.lock().unwrap() = options;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_binary_options(state: State<Arc<AppState>>) -> BinaryOptions {
+    state.recording.binary_options.lock().unwrap().clone()
+}
+
+/// Reads the header of a versioned binary recording: channels, sample rate, start time and device.
+#[tauri::command]
+pub fn read_binary_header(path: String) -> Result<BinaryHeader, String> {
+    BinaryReader::open(Path::new(&path)).map(|reader| reader.header().clone())
+}
+
+#[tauri::command]
+pub fn set_edf_options(options: EdfOptions, state: State<Arc<AppState>>) -> Result<(), String> {
+    options.validate()?;
+    *state.recording.edf_options.lock().unwrap() = options;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_edf_options(state: State<Arc<AppState>>) -> EdfOptions {
+    state.recording.edf_options.lock().unwrap().clone()
+}
+
+#[tauri::command]
+pub fn set_brainvision_options(options: BrainVisionOptions, state: State<Arc<AppState>>) -> Result<(), String> {
+    options.validate()?;
+    *state.recording.brainvision_options.lock().unwrap() = options;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_brainvision_options(state: State<Arc<AppState>>) -> BrainVisionOptions {
+    state.recording.brainvision_options.lock().unwrap().clone()
+}
+
+/// Checks a JSON array recording for the damage an interrupted recording leaves behind.
+#[tauri::command]
+pub fn validate_json_recording(path: String) -> Result<JsonValidation, String> {
+    jsonl::validate_json_recording(Path::new(&path))
+}
+
+/// Rewrites the intact entries of a JSON array recording as a valid file.
+/// Without `output_path` it is written to `<name>.repaired.json`. Returns the output path.
+#[tauri::command(async)]
+pub fn repair_json_recording(input_path: String, output_path: Option<String>) -> Result<String, String> {
+    let output = jsonl::repair_json_recording(Path::new(&input_path), output_path.as_deref().map(Path::new))?;
+    Ok(output.to_string_lossy().into_owned())
+}
+
+/// Channels, samples, start time and duration of a stored recording in any recording format
+#[tauri::command(async)]
+pub fn get_recording_metadata(path: String, state: State<Arc<AppState>>) -> Result<RecordingMetadata, String> {
+    let path = PathBuf::from(path);
+    let recording = recording_reader::open_recording(&state, &path)?;
+    recording_reader::recording_metadata(&path, &recording)
+}
+
+/// Samples of a stored recording for plotting: `duration_s` seconds from `offset_s`
+/// seconds after its first sample, optionally limited to some channels and thinned
+/// out to `max_points` samples
+#[tauri::command(async)]
+pub fn read_recording_window(
+    path: String,
+    offset_s: f64,
+    duration_s: f64,
+    channels: Option<Vec<usize>>,
+    max_points: Option<usize>,
+    state: State<Arc<AppState>>,
+) -> Result<RecordingWindow, String> {
+    let recording = recording_reader::open_recording(&state, Path::new(&path))?;
+    recording_reader::read_window(&recording, offset_s, duration_s, channels.as_deref(), max_points)
+}
+
+/// Converts a stored recording to EDF+ ("edf") or BDF+ ("bdf").
+/// Without `output_path` the file is written next to the input. Returns the output path.
+#[tauri::command(async)]
+pub fn export_recording_edf(
+    input_path: String,
+    format: String,
+    output_path: Option<String>,
+    sample_rate: Option<f64>,
+    state: State<Arc<AppState>>,
+) -> Result<String, String> {
+    if !matches!(format.as_str(), "edf" | "bdf") {
+        return Err("Export format must be 'edf' or 'bdf'".to_string());
+    }
+    let input = PathBuf::from(&input_path);
+    let output = output_path
+        .map(PathBuf::from)
+        .unwrap_or_else(|| compression::logical_path(&input).with_extension(&format));
+    let info = RecordingInfo {
+        edf: state.recording.edf_options.lock().unwrap().clone(),
+        ..Default::default()
+    };
+    let cancel = AtomicBool::new(false);
+    convert::convert_recording(&input, &output, &format, info, sample_rate, &cancel, |_| {})?;
+    Ok(output.to_string_lossy().into_owned())
+}
+
+/// Converts a stored recording into any recording format, keeping timestamps,
+/// channel labels and annotations, with the format options currently set for
+/// recording. Without `output_path` the file is written next to the input;
+/// without `compression` it follows a `.gz`/`.zst` suffix of the output.
+/// Reports `conversion_progress` events; `cancel_conversion` stops it.
+#[tauri::command(async)]
+pub fn convert_recording(
+    app_handle: AppHandle,
+    input_path: String,
+    format: String,
+    output_path: Option<String>,
+    sample_rate: Option<f64>,
+    compression: Option<CompressionConfig>,
+    state: State<Arc<AppState>>,
+) -> Result<ConversionSummary, String> {
+    let input = PathBuf::from(&input_path);
+    let (output, compression) = match (output_path, compression) {
+        (Some(output), Some(compression)) => (PathBuf::from(output), compression),
+        (Some(output), None) => {
+            let output = PathBuf::from(output);
+            let compression = convert::compression_of(&output);
+            (output, compression)
+        }
+        (None, compression) => {
+            let compression = compression.unwrap_or_default();
+            (convert::default_output(&input, &format, &compression), compression)
+        }
+    };
+    compression.validate()?;
+    let info = RecordingInfo {
+        binary: state.recording.binary_options.lock().unwrap().clone(),
+        edf: state.recording.edf_options.lock().unwrap().clone(),
+        brainvision: state.recording.brainvision_options.lock().unwrap().clone(),
+        compression,
+        ..Default::default()
+    };
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    {
+        let mut running = state.conversion.running.lock().unwrap();
+        if running.contains_key(&input) {
+            return Err("This recording is already being converted".to_string());
+        }
+        running.insert(input.clone(), cancel.clone());
+    }
+    let result = convert::convert_recording(&input, &output, &format, info, sample_rate, &cancel, |progress| {
+        let _ = app_handle.emit("conversion_progress", progress);
+    });
+    state.conversion.running.lock().unwrap().remove(&input);
+    result
+}
+
+/// Stops a running conversion of `input_path`; the partial output is removed
+#[tauri::command]
+pub fn cancel_conversion(input_path: String, state: State<Arc<AppState>>) -> Result<(), String> {
+    match state.conversion.running.lock().unwrap().get(Path::new(&input_path)) {
+        Some(cancel) => {
+            cancel.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+        None => Err("No conversion of this recording is running".to_string()),
+    }
+}
+
+/// Sets the output rate of recordings started from now on and of the UDP sample outputs;
+/// the live stream shown in the app keeps the device rate
+#[tauri::command]
+pub fn configure_resampling(config: ResampleConfig, state: State<Arc<AppState>>) -> Result<(), String> {
+    config.validate()?;
+    if config.enabled {
+        // Surface unsupported ratios now instead of at the next recording start
+        Resampler::new(state.stream.get_sample_rate(), &config)?;
+    }
+    state.sample_output.set_resampling(config.clone());
+    *state.recording.resample_config.lock().unwrap() = config;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_resampling_config(state: State<Arc<AppState>>) -> ResampleConfig {
+    state.recording.resample_config.lock().unwrap().clone()
+}
+
+#[tauri::command]
+pub fn list_montages(app_handle: AppHandle) -> Result<Vec<String>, String> {
+    montage::list_montages(&app_handle)
+}
+
+#[tauri::command]
+pub fn load_montage(app_handle: AppHandle, name: String) -> Result<Montage, String> {
+    montage::load_montage(&app_handle, &name)
+}
+
+#[tauri::command]
+pub fn save_montage(app_handle: AppHandle, montage: Montage) -> Result<(), String> {
+    montage::save_montage(&app_handle, &montage)
+}
+
+#[tauri::command]
+pub fn delete_montage(app_handle: AppHandle, name: String) -> Result<(), String> {
+    let state = app_handle.state::<Arc<AppState>>();
+    montage::delete_montage(&app_handle, &name)?;
+    // Deleting the active montage falls back to raw channels
+    let mut active = state.montage.active.lock().unwrap();
+    if active.as_ref().map(|m| m.name == name).unwrap_or(false) {
+        *active = None;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn set_active_montage(
+    app_handle: AppHandle,
+    name: Option<String>,
+    apply_to_recording: bool,
+) -> Result<Option<CompiledMontage>, String> {
+    let state = app_handle.state::<Arc<AppState>>();
+    // Passing no name clears the montage and shows raw channels again
+    let compiled = match name {
+        Some(name) => Some(montage::load_montage(&app_handle, &name)?.compile()?),
+        None => None,
+    };
+    *state.montage.active.lock().unwrap() = compiled.clone();
+    state.montage.apply_to_recording.store(apply_to_recording, Ordering::SeqCst);
+    // The buffered rows may have a different channel count than the new montage
+    state.buffer.active_buffer.lock().unwrap().clear();
+    state.buffer.read_buffer.lock().unwrap().clear();
+    Ok(compiled)
+}
+
 #[tauri::command]
 pub async fn start_video_recording(
     app_handle: AppHandle,
@@ -372,8 +1006,8 @@ pub async fn start_video_recording(
         return Err("Camera not streaming, start streaming first".to_string());
     }
     
-    // Create the full path with mp4 extension
-    let full_path = Path::new(&directory).join(format!("{}.mp4", filename));
+    // Create the full path with mp4 extension, next to the segments of a running session
+    let full_path = Path::new(&session::video_directory(&state, &directory)).join(format!("{}.mp4", filename));
     let path_str = full_path.to_string_lossy().to_string();
     
     println!("Starting video recording at {}", path_str);
@@ -383,6 +1017,10 @@ pub async fn start_video_recording(
         Ok(_) => {
             // Set the recording active flag
             state.recording.video_recording_active.store(true, Ordering::SeqCst);
+            let video_file = format!("{}.mp4", filename);
+            session::attach_video(&state, &video_file);
+            *state.recording.video_filename.lock().unwrap() = Some(video_file);
+            *state.recording.video_path.lock().unwrap() = Some(full_path);
             Ok(true)
         },
         Err(e) => Err(format!("Failed to start video recording: {}", e)),
@@ -407,9 +1045,11 @@ pub fn get_app_state(
         },
         "signal_quality" => match key.as_str() {
             "quality" => {
-                let quality = state.signal_quality.check_signal_quality();
+                let quality = state.signal_quality.get_signal_quality();
                 Ok(serde_json::to_value(quality).unwrap_or(serde_json::Value::Null))
             },
+            "report" => Ok(serde_json::to_value(state.signal_quality.latest_report()).unwrap_or(serde_json::Value::Null)),
+            "thresholds" => Ok(serde_json::to_value(state.signal_quality.engine.lock().unwrap().thresholds()).unwrap_or(serde_json::Value::Null)),
             _ => Err(format!("Invalid key '{}' for signal_quality category", key)),
         },
         "stream" => match key.as_str() {
@@ -418,6 +1058,8 @@ pub fn get_app_state(
             "fake_signal_enabled" => Ok(serde_json::json!(state.stream.fake_signal_enabled.load(Ordering::SeqCst))),
             "fake_camera_enabled" => Ok(serde_json::json!(state.stream.fake_camera_enabled.load(Ordering::SeqCst))),
             "default_stream_url" => Ok(serde_json::json!(state.stream.default_stream_url.lock().unwrap().clone())),
+            "sample_rate" => Ok(serde_json::json!(state.stream.get_sample_rate())),
+            "device" => Ok(serde_json::json!(*state.stream.device.lock().unwrap())),
             "all" => {
                 let signal_running = state.stream.signal_stream_running.load(Ordering::SeqCst);
                 let camera_running = state.stream.camera_stream_running.load(Ordering::SeqCst);
@@ -438,6 +1080,10 @@ pub fn get_app_state(
         "recording" => match key.as_str() {
             "active" => Ok(serde_json::json!(state.recording.recording_active.load(Ordering::SeqCst))),
             "video_active" => Ok(serde_json::json!(state.recording.video_recording_active.load(Ordering::SeqCst))),
+            "paused" => Ok(serde_json::json!(state.recording.paused.load(Ordering::SeqCst))),
+            "sample_rate" => Ok(serde_json::json!(*state.recording.recording_sample_rate.lock().unwrap())),
+            "resampling" => Ok(serde_json::json!(*state.recording.resample_config.lock().unwrap())),
+            "pipeline" => Ok(serde_json::json!(state.recording.pipeline.stats())),
             "filename" => {
                 let filename = state.recording.recording_filename.lock().unwrap().clone().unwrap_or_default();
                 Ok(serde_json::json!(filename))
@@ -449,6 +1095,7 @@ pub fn get_app_state(
                 
                 Ok(serde_json::json!({
                     "active": recording_active,
+                    "paused": state.recording.paused.load(Ordering::SeqCst),
                     "videoActive": video_active,
                     "filename": filename,
                     "isRecording": recording_active || video_active,
@@ -456,6 +1103,27 @@ pub fn get_app_state(
             },
             _ => Err(format!("Invalid key '{}' for recording category", key)),
         },
+        "spectrum" => match key.as_str() {
+            "config" => Ok(serde_json::to_value(state.spectrum.engine.lock().unwrap().config()).unwrap_or(serde_json::Value::Null)),
+            "latest" => Ok(serde_json::to_value(state.spectrum.engine.lock().unwrap().latest()).unwrap_or(serde_json::Value::Null)),
+            _ => Err(format!("Invalid key '{}' for spectrum category", key)),
+        },
+        "impedance" => match key.as_str() {
+            "active" => Ok(serde_json::json!(state.impedance.active.load(Ordering::SeqCst))),
+            "latest" => Ok(serde_json::to_value(state.impedance.engine.lock().unwrap().latest()).unwrap_or(serde_json::Value::Null)),
+            _ => Err(format!("Invalid key '{}' for impedance category", key)),
+        },
+        "montage" => match key.as_str() {
+            "active" => Ok(serde_json::to_value(state.montage.active.lock().unwrap().clone()).unwrap_or(serde_json::Value::Null)),
+            "apply_to_recording" => Ok(serde_json::json!(state.montage.apply_to_recording.load(Ordering::SeqCst))),
+            _ => Err(format!("Invalid key '{}' for montage category", key)),
+        },
+        "trigger" => match key.as_str() {
+            "armed" => Ok(serde_json::json!(state.trigger.armed.load(Ordering::SeqCst))),
+            "config" => Ok(serde_json::json!(*state.trigger.config.lock().unwrap())),
+            "session" => Ok(serde_json::to_value(state.trigger.session.lock().unwrap().clone()).unwrap_or(serde_json::Value::Null)),
+            _ => Err(format!("Invalid key '{}' for trigger category", key)),
+        },
         "mdns" => match key.as_str() {
             "active" => Ok(serde_json::json!(*state.mdns.active.lock().unwrap())),
             "host" => Ok(serde_json::json!(state.mdns.host.lock().unwrap().clone())),
@@ -0,0 +1,185 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+
+/// Compressed output is flushed at most this often so small batches don't ruin the ratio
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompressionConfig {
+    pub method: Compression,
+    pub level: Option<i32>, // None uses the method's default level
+}
+
+impl CompressionConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        let range = match self.method {
+            Compression::None => return Ok(()),
+            Compression::Gzip => 0..=9,
+            Compression::Zstd => 1..=19,
+        };
+        match self.level {
+            Some(level) if !range.contains(&level) => Err(format!(
+                "Compression level must be between {} and {}",
+                range.start(),
+                range.end()
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Appended to the file name of compressed recordings
+    pub fn suffix(&self) -> &'static str {
+        match self.method {
+            Compression::None => "",
+            Compression::Gzip => ".gz",
+            Compression::Zstd => ".zst",
+        }
+    }
+}
+
+enum Encoder {
+    Plain(File),
+    Gzip(GzEncoder<File>),
+    Zstd(zstd::Encoder<'static, File>),
+}
+
+/// A recording file, written plain or through a streaming compressor.
+///
+/// `finish` must be called to close the compressed stream; a file that was
+/// never finished (crash, power loss) is still readable up to its last flush.
+pub struct CompressedFile {
+    encoder: Option<Encoder>,
+    last_flush: Instant,
+}
+
+impl CompressedFile {
+    pub fn new(file: File, config: &CompressionConfig) -> io::Result<Self> {
+        let encoder = match config.method {
+            Compression::None => Encoder::Plain(file),
+            Compression::Gzip => {
+                let level = config.level.unwrap_or(6).clamp(0, 9) as u32;
+                Encoder::Gzip(GzEncoder::new(file, flate2::Compression::new(level)))
+            }
+            Compression::Zstd => Encoder::Zstd(zstd::Encoder::new(file, config.level.unwrap_or(3))?),
+        };
+        Ok(Self {
+            encoder: Some(encoder),
+            last_flush: Instant::now(),
+        })
+    }
+
+    /// Writes the end of the compressed stream; later writes fail
+    pub fn finish(&mut self) -> io::Result<()> {
+        match self.encoder.take() {
+            Some(Encoder::Plain(mut file)) => file.flush(),
+            Some(Encoder::Gzip(encoder)) => encoder.finish().and_then(|mut file| file.flush()),
+            Some(Encoder::Zstd(encoder)) => encoder.finish().and_then(|mut file| file.flush()),
+            None => Ok(()),
+        }
+    }
+}
+
+fn finished() -> io::Error {
+    io::Error::other("recording file is already finished")
+}
+
+impl Write for CompressedFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.encoder.as_mut().ok_or_else(finished)? {
+            Encoder::Plain(file) => file.write(buf),
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            Encoder::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let Some(encoder) = self.encoder.as_mut() else {
+            return Ok(());
+        };
+        if let Encoder::Plain(file) = encoder {
+            return file.flush();
+        }
+        if self.last_flush.elapsed() < FLUSH_INTERVAL {
+            return Ok(());
+        }
+        self.last_flush = Instant::now();
+        match encoder {
+            Encoder::Gzip(encoder) => encoder.flush(),
+            Encoder::Zstd(encoder) => encoder.flush(),
+            Encoder::Plain(_) => Ok(()),
+        }
+    }
+}
+
+/// The path without a `.gz` or `.zst` suffix, whose extension names the recording format
+pub fn logical_path(path: &Path) -> PathBuf {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("gz") | Some("zst") => path.with_extension(""),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Opens a recording, decompressing gzip and zstd files based on their first bytes.
+pub fn open_reader(path: &Path) -> io::Result<Box<dyn Read>> {
+    let mut file = File::open(path)?;
+    let mut magic = [0u8; 4];
+    let read = file.read(&mut magic)?;
+    let head = io::Cursor::new(magic[..read].to_vec());
+    let input = BufReader::new(head.chain(file));
+    Ok(if magic[..read].starts_with(&GZIP_MAGIC) {
+        Box::new(MultiGzDecoder::new(input))
+    } else if magic[..read].starts_with(&ZSTD_MAGIC) {
+        Box::new(zstd::Decoder::with_buffer(input)?)
+    } else {
+        Box::new(input)
+    })
+}
+
+/// Reads a whole recording, decompressing it if needed. A compressed stream
+/// cut short by an interrupted recording yields the data before the cut.
+pub fn read_file(path: &Path) -> Result<Vec<u8>, String> {
+    let mut input = open_reader(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut bytes = Vec::new();
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        match input.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => bytes.extend_from_slice(&buffer[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) if !bytes.is_empty() => {
+                eprintln!("[Recording] {} ends early: {}", path.display(), e);
+                break;
+            }
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        }
+    }
+    Ok(bytes)
+}
+
+/// Compresses a finished file into `target` and removes the original.
+pub fn compress_file(source: &Path, target: &Path, config: &CompressionConfig) -> io::Result<()> {
+    let mut input = File::open(source)?;
+    let mut output = CompressedFile::new(File::create(target)?, config)?;
+    io::copy(&mut input, &mut output)?;
+    output.finish()?;
+    fs::remove_file(source)
+}
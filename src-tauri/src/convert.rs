@@ -0,0 +1,309 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::Serialize;
+
+use crate::compression::{self, Compression, CompressionConfig};
+use crate::recording::Annotation;
+use crate::recording_reader;
+use crate::writer::{self, RecordingInfo, Row};
+
+/// Rows handed to the writer between progress reports and cancellation checks
+const BATCH_ROWS: usize = 4096;
+
+/// Progress is reported at most this often
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Progress of a running conversion
+#[derive(Debug, Clone, Serialize)]
+pub struct ConversionProgress {
+    pub input: String,
+    pub output: String,
+    pub samples_written: usize,
+    pub samples_total: usize,
+}
+
+/// Result of a finished conversion
+#[derive(Debug, Clone, Serialize)]
+pub struct ConversionSummary {
+    pub input: String,
+    pub output: String,
+    pub format: String,
+    pub samples: usize,
+    pub channels: usize,
+    pub sample_rate: f64,
+    pub annotations: usize,
+}
+
+/// Compression implied by a `.gz` or `.zst` suffix of the output name
+pub fn compression_of(path: &Path) -> CompressionConfig {
+    let method = match path.extension().and_then(|e| e.to_str()) {
+        Some("gz") => Compression::Gzip,
+        Some("zst") => Compression::Zstd,
+        _ => Compression::None,
+    };
+    CompressionConfig { method, level: None }
+}
+
+/// Output written next to the input when none is given: the input name with the
+/// extension of `format` and the compression suffix
+pub fn default_output(input: &Path, format: &str, compression: &CompressionConfig) -> PathBuf {
+    let logical = compression::logical_path(input);
+    let stem = logical.file_stem().and_then(|s| s.to_str()).unwrap_or("recording");
+    input.with_file_name(writer::file_name(stem, format, compression))
+}
+
+/// Files a writer for `format` creates at `output`, removed again when a conversion fails
+fn output_files(output: &Path, format: &str, info: &RecordingInfo) -> Vec<PathBuf> {
+    let mut files = vec![output.to_path_buf()];
+    match format {
+        // EDF/BDF are written plain and compressed when finished
+        "edf" | "bdf" => files.push(compression::logical_path(output)),
+        "brainvision" => {
+            files.push(output.with_extension("vmrk"));
+            files.push(output.with_extension(format!("eeg{}", info.compression.suffix())));
+        }
+        _ => {}
+    }
+    files
+}
+
+/// Converts a recording in any recording format into `format` at `output`,
+/// keeping sample times and indices, channel labels, annotations and the
+/// device and EDF+ patient and recording fields. Annotations go into the file
+/// when the format has a place for them and into a sidecar otherwise. `info`
+/// gives the format options; labels and the sample rate come from the input,
+/// the rate estimated from its timestamps when the input doesn't store it,
+/// unless `sample_rate` is given. `progress` is called as samples are written; setting `cancel`
+/// stops the conversion and removes the partial output.
+pub fn convert_recording(
+    input: &Path,
+    output: &Path,
+    format: &str,
+    mut info: RecordingInfo,
+    sample_rate: Option<f64>,
+    cancel: &AtomicBool,
+    mut progress: impl FnMut(&ConversionProgress),
+) -> Result<ConversionSummary, String> {
+    if !writer::FORMATS.contains(&format) {
+        return Err("Invalid format specified".to_string());
+    }
+    let same_file = fs::canonicalize(input).ok() == fs::canonicalize(output).ok() && output.exists();
+    if same_file {
+        return Err("Output must not be the input file".to_string());
+    }
+    let recording = recording_reader::load_recording(input)?;
+    let source = &recording.source;
+    info.sample_rate = match sample_rate {
+        Some(rate) if rate.is_finite() && rate > 0.0 => rate,
+        Some(_) => return Err("Sample rate must be positive".to_string()),
+        None => source
+            .sample_rate
+            .or_else(|| recording_reader::estimate_sample_rate(&recording.samples))
+            .ok_or("Cannot estimate the sample rate; please provide it")?,
+    };
+    info.labels = recording.labels.clone();
+    // What the input keeps about the recording carries over to the output
+    if info.device.is_empty() {
+        info.device = source.device.clone().unwrap_or_default();
+    }
+    if let Some(patient_id) = &source.patient_id {
+        info.edf.patient_id = patient_id.clone();
+    }
+    if let Some(recording_id) = &source.recording_id {
+        info.edf.recording_id = recording_id.clone();
+    }
+
+    let mut report = ConversionProgress {
+        input: input.to_string_lossy().into_owned(),
+        output: output.to_string_lossy().into_owned(),
+        samples_written: 0,
+        samples_total: recording.samples.len(),
+    };
+    let sidecar = recording_reader::annotation_path(output);
+    let mut files = output_files(output, format, &info);
+    let result = (|| -> Result<(), String> {
+        let mut writer = writer::create_writer(format, output, &info)?;
+        let io_error = |e: io::Error| format!("Failed to write {}: {}", output.display(), e);
+        if !recording.annotations.is_empty() && !writer.write_annotations(&recording.annotations).map_err(io_error)? {
+            files.push(sidecar.clone());
+            write_sidecar(&sidecar, &recording.annotations)
+                .map_err(|e| format!("Failed to write {}: {}", sidecar.display(), e))?;
+        }
+
+        // Stored session sample indices carry over. Formats that store samples by
+        // position number them from the sample times, so gaps stay gaps.
+        let mut previous: Option<(u64, SystemTime)> = None;
+        let mut reported = Instant::now();
+        for (batch, samples) in recording.samples.chunks(BATCH_ROWS).enumerate() {
+            if cancel.load(Ordering::SeqCst) {
+                return Err("Conversion cancelled".to_string());
+            }
+            let first = batch * BATCH_ROWS;
+            let rows: Vec<Row> = samples
+                .iter()
+                .map(|sample| {
+                    let index = sample.index.unwrap_or_else(|| match previous {
+                        Some((index, time)) => {
+                            let elapsed = sample.time.duration_since(time).unwrap_or_default().as_secs_f64();
+                            index + ((elapsed * info.sample_rate).round() as u64).max(1)
+                        }
+                        None => 0,
+                    });
+                    previous = Some((index, sample.time));
+                    (index, sample.time, sample.values.clone())
+                })
+                .collect();
+            writer.write_samples(&rows).map_err(io_error)?;
+            report.samples_written = first + rows.len();
+            if reported.elapsed() >= PROGRESS_INTERVAL {
+                reported = Instant::now();
+                progress(&report);
+            }
+        }
+        writer.finish().map_err(io_error)
+    })();
+
+    if let Err(e) = result {
+        for file in &files {
+            let _ = fs::remove_file(file);
+        }
+        return Err(e);
+    }
+    progress(&report);
+    println!(
+        "[Convert] Converted {} to {} ({}, {} Hz, {} channels)",
+        input.display(),
+        output.display(),
+        format,
+        info.sample_rate,
+        info.labels.len()
+    );
+    Ok(ConversionSummary {
+        input: report.input,
+        output: report.output,
+        format: format.to_string(),
+        samples: recording.samples.len(),
+        channels: info.labels.len(),
+        sample_rate: info.sample_rate,
+        annotations: recording.annotations.len(),
+    })
+}
+
+fn write_sidecar(path: &Path, annotations: &[Annotation]) -> io::Result<()> {
+    let mut file = fs::File::create(path)?;
+    writeln!(file, "{}", Annotation::SIDECAR_HEADER)?;
+    for annotation in annotations {
+        writeln!(file, "{}", annotation.sidecar_line())?;
+    }
+    file.flush()
+}
+
+/// `convert <input> <output> [--format <format>] [--rate <hz>]` on the command line.
+/// The format defaults to the one implied by the output name; format options
+/// are the defaults. Returns the process exit code.
+pub fn run_cli(args: &[String]) -> i32 {
+    let usage = "Usage: convert <input> <output> [--format <format>] [--rate <hz>]";
+    let mut paths = Vec::new();
+    let mut format = None;
+    let mut rate = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => format = args.next().cloned(),
+            "--rate" => match args.next().map(|r| r.parse::<f64>()) {
+                Some(Ok(value)) => rate = Some(value),
+                _ => {
+                    eprintln!("{}", usage);
+                    return 2;
+                }
+            },
+            "-h" | "--help" => {
+                println!("{}\nFormats: {}", usage, writer::FORMATS.join(", "));
+                return 0;
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    let [input, output] = paths.as_slice() else {
+        eprintln!("{}", usage);
+        return 2;
+    };
+    let format = match format {
+        Some(format) => format,
+        None => match recording_reader::format_of(output) {
+            Ok(format) => format.to_string(),
+            Err(e) => {
+                eprintln!("{}; use --format", e);
+                return 2;
+            }
+        },
+    };
+    let info = RecordingInfo {
+        compression: compression_of(output),
+        ..Default::default()
+    };
+    let cancel = AtomicBool::new(false);
+    let result = convert_recording(input, output, &format, info, rate, &cancel, |progress| {
+        eprintln!("{} / {} samples", progress.samples_written, progress.samples_total);
+    });
+    match result {
+        Ok(summary) => {
+            println!(
+                "{} samples, {} channels at {} Hz, {} annotations written to {}",
+                summary.samples, summary.channels, summary.sample_rate, summary.annotations, summary.output
+            );
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{rows, temp_dir};
+
+    fn convert(input: &Path, output: &Path, format: &str) -> recording_reader::LoadedRecording {
+        let cancel = AtomicBool::new(false);
+        convert_recording(input, output, format, RecordingInfo::default(), None, &cancel, |_| {}).unwrap();
+        recording_reader::load_recording(output).unwrap()
+    }
+
+    #[test]
+    fn keeps_sample_indices_times_and_source_details() {
+        let mut rows = rows(1000, 2, 250.0);
+        rows.drain(400..450);
+        let info = RecordingInfo {
+            labels: vec!["C3".to_string(), "C4".to_string()],
+            sample_rate: 250.0,
+            device: "Cyton".to_string(),
+            ..Default::default()
+        };
+        let directory = temp_dir("convert");
+        let input = directory.join("recording.bin");
+        let mut writer = writer::create_writer("binary", &input, &info).unwrap();
+        writer.write_samples(&rows).unwrap();
+        writer.finish().unwrap();
+
+        let binary = convert(&input, &directory.join("copy.bin"), "binary");
+        assert_eq!(binary.source.device.as_deref(), Some("Cyton"));
+        assert_eq!(binary.source.sample_rate, Some(250.0));
+        for (sample, (index, time, _)) in binary.samples.iter().zip(&rows) {
+            assert_eq!((sample.index, sample.time), (Some(*index), *time));
+        }
+
+        // EDF keeps samples by position; converting back numbers them from their times
+        let edf = directory.join("recording.edf");
+        convert(&input, &edf, "edf");
+        let from_edf = convert(&edf, &directory.join("from_edf.bin"), "binary");
+        let indices: Vec<u64> = from_edf.samples.iter().map(|s| s.index.unwrap()).collect();
+        assert_eq!(indices, rows.iter().map(|(index, _, _)| *index).collect::<Vec<_>>());
+    }
+}
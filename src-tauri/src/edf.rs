@@ -0,0 +1,874 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use serde::{Deserialize, Serialize};
+
+use crate::compression::{self, CompressionConfig};
+use crate::recording::Annotation;
+use crate::recording_reader::{time_from_millis, Contents, Sample, SourceInfo};
+use crate::types::FULL_SCALE_MICROVOLTS;
+use crate::writer::{self, RecordingInfo, RecordingWriter, Row, Timeline};
+
+/// Byte offset of the reserved header field, "EDF+C" or "EDF+D"
+const RESERVED_OFFSET: u64 = 192;
+
+/// Byte offset of the "number of data records" header field
+const RECORD_COUNT_OFFSET: u64 = 236;
+
+/// Longest whole-second data record; rates that need longer ones get fractional records
+const MAX_RECORD_SECONDS: u64 = 10;
+
+/// Annotation marking samples of a record that hold no data: the end of the
+/// last record, and gaps in the sample index too short to start a new record
+const PADDING_PREFIX: &str = "Padding: ";
+const PADDING_SUFFIX: &str = " samples without data";
+
+/// 16-bit EDF+ or 24-bit BDF+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EdfVariant {
+    Edf,
+    Bdf,
+}
+
+impl EdfVariant {
+    fn bytes_per_sample(&self) -> usize {
+        match self {
+            EdfVariant::Edf => 2,
+            EdfVariant::Bdf => 3,
+        }
+    }
+
+    fn digital_range(&self) -> (i32, i32) {
+        match self {
+            EdfVariant::Edf => (-32768, 32767),
+            EdfVariant::Bdf => (-8_388_608, 8_388_607),
+        }
+    }
+
+    /// Physical range used when none is configured, in microvolts. BDF spans
+    /// the device's full scale and keeps every count of the 24-bit converter;
+    /// EDF keeps 0.1 uV steps, which clips beyond +-3.2767 mV.
+    fn default_physical_range(&self) -> (f64, f64) {
+        match self {
+            EdfVariant::Edf => (-3276.7, 3276.7),
+            EdfVariant::Bdf => (-FULL_SCALE_MICROVOLTS, FULL_SCALE_MICROVOLTS),
+        }
+    }
+
+    fn annotation_label(&self) -> &'static str {
+        match self {
+            EdfVariant::Edf => "EDF Annotations",
+            EdfVariant::Bdf => "BDF Annotations",
+        }
+    }
+
+    /// Reserved header field of a continuous or discontinuous file
+    fn reserved(&self, discontinuous: bool) -> &'static str {
+        match (self, discontinuous) {
+            (EdfVariant::Edf, false) => "EDF+C",
+            (EdfVariant::Edf, true) => "EDF+D",
+            (EdfVariant::Bdf, false) => "BDF+C",
+            (EdfVariant::Bdf, true) => "BDF+D",
+        }
+    }
+}
+
+/// Header fields and scaling of EDF+/BDF+ files
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EdfOptions {
+    pub patient_id: String,           // EDF+ patient field: code, sex, birthdate, name ("X" when unknown)
+    pub recording_id: String,         // EDF+ recording field after the start date: admin code, technician, equipment
+    pub physical_dimension: String,
+    pub physical_min: Option<f64>,    // Defaults to +-3276.7 uV for EDF and the device's full scale for BDF
+    pub physical_max: Option<f64>,
+    pub annotation_bytes: usize,      // Room for annotations in every data record
+}
+
+impl Default for EdfOptions {
+    fn default() -> Self {
+        Self {
+            patient_id: "X X X X".to_string(),
+            recording_id: "X X serial-brain".to_string(),
+            physical_dimension: "uV".to_string(),
+            physical_min: None,
+            physical_max: None,
+            annotation_bytes: 240,
+        }
+    }
+}
+
+impl EdfOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if let (Some(min), Some(max)) = (self.physical_min, self.physical_max) {
+            if min >= max {
+                return Err("Physical minimum must be below the physical maximum".to_string());
+            }
+        }
+        if !(60..=4096).contains(&self.annotation_bytes) {
+            return Err("Annotation space must be between 60 and 4096 bytes".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Record duration in seconds and the whole number of samples it holds. The
+/// shortest whole-second duration is used when it is at most `MAX_RECORD_SECONDS`;
+/// other rates get records of about a second with a fractional duration.
+fn record_layout(sample_rate: f64) -> Result<(f64, usize), String> {
+    if !sample_rate.is_finite() || sample_rate * 1000.0 < 0.5 {
+        return Err("EDF requires a positive sample rate".to_string());
+    }
+    let millihertz = (sample_rate * 1000.0).round() as u64;
+    let divisor = gcd(millihertz, 1000);
+    let exact = (sample_rate * 1000.0 - millihertz as f64).abs() < 1e-6;
+    if exact && 1000 / divisor <= MAX_RECORD_SECONDS {
+        return Ok(((1000 / divisor) as f64, (millihertz / divisor) as usize));
+    }
+    let samples = sample_rate.round().max(1.0) as usize;
+    Ok((header_number(samples as f64 / sample_rate), samples))
+}
+
+/// Printable ASCII, space padded and cut to `len`
+fn ascii_field(value: &str, len: usize) -> String {
+    let mut field: String = value
+        .chars()
+        .map(|c| if (' '..='~').contains(&c) { c } else { '_' })
+        .take(len)
+        .collect();
+    while field.len() < len {
+        field.push(' ');
+    }
+    field
+}
+
+/// Number formatted to fit an 8-character header field
+fn number_field(value: f64) -> String {
+    for precision in (0..=7).rev() {
+        let text = format!("{:.*}", precision, value);
+        let text = if text.contains('.') {
+            text.trim_end_matches('0').trim_end_matches('.').to_string()
+        } else {
+            text
+        };
+        if text.len() <= 8 {
+            return ascii_field(&text, 8);
+        }
+    }
+    ascii_field(&format!("{:.0}", value), 8)
+}
+
+/// A number as it reads back from its header field, so writing and reading scale alike
+fn header_number(value: f64) -> f64 {
+    number_field(value).trim().parse().unwrap_or(value)
+}
+
+/// Number of padding samples an annotation label announces
+fn padding_samples(label: &str) -> Option<usize> {
+    label.strip_prefix(PADDING_PREFIX)?.strip_suffix(PADDING_SUFFIX)?.parse().ok()
+}
+
+/// Seconds relative to the file start in the "+1.25"/"-0.5" form used by TALs,
+/// to the microsecond so record onsets and padding locate single samples
+fn tal_time(seconds: f64) -> String {
+    let text = format!("{:.6}", seconds.abs());
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    format!("{}{}", if seconds < 0.0 { '-' } else { '+' }, text)
+}
+
+/// Streams samples into an EDF+ or BDF+ file with an annotation signal.
+/// The header is written with the first sample so the file starts at its timestamp;
+/// the record count is patched in on `finish`.
+///
+/// A gap in the sample index shorter than the rest of the current record is
+/// padded; a longer one ends the record, and the next one starts at the time
+/// of the sample after the gap. The file is then marked discontinuous (EDF+D),
+/// with each record onset giving the real time of its first sample.
+pub struct EdfWriter {
+    file: File,
+    variant: EdfVariant,
+    options: EdfOptions,
+    labels: Vec<String>,
+    record_seconds: f64, // As written to the header
+    samples_per_record: usize,
+    physical: (f64, f64),
+    start: Option<SystemTime>, // Start second written to the header
+    record_onset: f64,         // Seconds from the start to the first sample of the pending record
+    pending: Vec<Vec<f32>>,
+    padding: Vec<(f64, usize)>, // Onset and length of the padded stretches of the pending record
+    annotations: VecDeque<Annotation>,
+    timeline: Timeline,
+    discontinuous: bool,
+    records_written: u64,
+    compress_to: Option<(PathBuf, PathBuf, CompressionConfig)>, // Written file, compressed target and method
+}
+
+impl EdfWriter {
+    pub fn new(file: File, variant: EdfVariant, info: &RecordingInfo) -> Result<Self, String> {
+        info.edf.validate()?;
+        let (record_seconds, samples_per_record) = record_layout(info.sample_rate)?;
+        let (default_min, default_max) = variant.default_physical_range();
+        Ok(Self {
+            file,
+            variant,
+            options: info.edf.clone(),
+            labels: info.labels.clone(),
+            record_seconds,
+            samples_per_record,
+            physical: (
+                header_number(info.edf.physical_min.unwrap_or(default_min)),
+                header_number(info.edf.physical_max.unwrap_or(default_max)),
+            ),
+            start: None,
+            record_onset: 0.0,
+            pending: Vec::with_capacity(samples_per_record),
+            padding: Vec::new(),
+            annotations: VecDeque::new(),
+            timeline: Timeline::default(),
+            discontinuous: false,
+            records_written: 0,
+            compress_to: None,
+        })
+    }
+
+    /// Compresses the finished file at `written` into `target` when the segment closes,
+    /// since the record count in the header is only known then
+    pub fn with_compression(mut self, written: PathBuf, target: PathBuf, config: CompressionConfig) -> Self {
+        self.compress_to = Some((written, target, config));
+        self
+    }
+
+    fn annotation_samples(&self) -> usize {
+        self.options.annotation_bytes.div_ceil(self.variant.bytes_per_sample())
+    }
+
+    fn write_header(&mut self, first_sample: SystemTime) -> io::Result<()> {
+        let since_epoch = first_sample.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(since_epoch.as_secs());
+        self.start = Some(start);
+        self.record_onset = since_epoch.subsec_nanos() as f64 / 1e9;
+        let local: DateTime<Local> = start.into();
+
+        let signals = self.labels.len() + 1;
+        let (digital_min, digital_max) = self.variant.digital_range();
+        let mut header = String::new();
+        let version = match self.variant {
+            EdfVariant::Edf => ascii_field("0", 8),
+            // BDF marks its version with a leading 0xFF byte, patched in below
+            EdfVariant::Bdf => ascii_field(" BIOSEMI", 8),
+        };
+        header.push_str(&version);
+        header.push_str(&ascii_field(&self.options.patient_id, 80));
+        let recording = format!(
+            "Startdate {} {}",
+            local.format("%d-%b-%Y").to_string().to_uppercase(),
+            self.options.recording_id
+        );
+        header.push_str(&ascii_field(&recording, 80));
+        header.push_str(&local.format("%d.%m.%y").to_string());
+        header.push_str(&local.format("%H.%M.%S").to_string());
+        header.push_str(&ascii_field(&(256 * (signals + 1)).to_string(), 8));
+        // Patched to the discontinuous variant on `finish` when the index had gaps
+        header.push_str(&ascii_field(self.variant.reserved(false), 44));
+        header.push_str(&ascii_field("-1", 8));
+        header.push_str(&number_field(self.record_seconds));
+        header.push_str(&ascii_field(&signals.to_string(), 4));
+
+        // Per-signal fields are grouped field by field; the annotation signal comes last
+        let annotation = self.variant.annotation_label().to_string();
+        let labels: Vec<&String> = self.labels.iter().chain(std::iter::once(&annotation)).collect();
+        let is_annotation = |i: usize| i == signals - 1;
+        for label in &labels {
+            header.push_str(&ascii_field(label, 16));
+        }
+        for i in 0..signals {
+            header.push_str(&ascii_field(if is_annotation(i) { "" } else { "AgAgCl electrode" }, 80));
+        }
+        for i in 0..signals {
+            header.push_str(&ascii_field(if is_annotation(i) { "" } else { &self.options.physical_dimension }, 8));
+        }
+        for i in 0..signals {
+            header.push_str(&if is_annotation(i) { ascii_field("-1", 8) } else { number_field(self.physical.0) });
+        }
+        for i in 0..signals {
+            header.push_str(&if is_annotation(i) { ascii_field("1", 8) } else { number_field(self.physical.1) });
+        }
+        for _ in 0..signals {
+            header.push_str(&ascii_field(&digital_min.to_string(), 8));
+        }
+        for _ in 0..signals {
+            header.push_str(&ascii_field(&digital_max.to_string(), 8));
+        }
+        for _ in 0..signals {
+            header.push_str(&ascii_field("", 80));
+        }
+        for i in 0..signals {
+            let samples = if is_annotation(i) { self.annotation_samples() } else { self.samples_per_record };
+            header.push_str(&ascii_field(&samples.to_string(), 8));
+        }
+        for _ in 0..signals {
+            header.push_str(&ascii_field("", 32));
+        }
+
+        let mut bytes = header.into_bytes();
+        if self.variant == EdfVariant::Bdf {
+            bytes[0] = 0xFF;
+        }
+        self.file.write_all(&bytes)
+    }
+
+    fn encode_sample(&self, value: f32, out: &mut Vec<u8>) {
+        let (physical_min, physical_max) = self.physical;
+        let (digital_min, digital_max) = self.variant.digital_range();
+        // EDF has no missing-value code; gaps are written as zero
+        let value = if value.is_finite() { value as f64 } else { 0.0 };
+        let scaled = (value - physical_min) / (physical_max - physical_min)
+            * (digital_max - digital_min) as f64
+            + digital_min as f64;
+        let digital = scaled.round().clamp(digital_min as f64, digital_max as f64) as i32;
+        out.extend_from_slice(&digital.to_le_bytes()[..self.variant.bytes_per_sample()]);
+    }
+
+    /// Seconds from the start second written to the header to `time`
+    fn seconds_since_start(&self, time: SystemTime) -> f64 {
+        let start = self.start.unwrap_or(SystemTime::UNIX_EPOCH);
+        match time.duration_since(start) {
+            Ok(after) => after.as_secs_f64(),
+            Err(e) => -e.duration().as_secs_f64(),
+        }
+    }
+
+    /// Fills `count` samples of the pending record with no data
+    fn pad(&mut self, count: usize) {
+        let period = self.record_seconds / self.samples_per_record as f64;
+        self.padding.push((self.record_onset + self.pending.len() as f64 * period, count));
+        let len = self.pending.len() + count;
+        self.pending.resize(len, vec![f32::NAN; self.labels.len()]);
+    }
+
+    /// Accounts for `missing` samples before the row at `index`: padded when
+    /// they fit into the pending record, otherwise the row starts a new record
+    fn skip(&mut self, missing: u64, index: u64, timestamp: SystemTime) -> io::Result<()> {
+        let room = (self.samples_per_record - self.pending.len()) as u64;
+        if !self.pending.is_empty() && missing < room {
+            self.pad(missing as usize);
+        } else {
+            if !self.pending.is_empty() {
+                self.pad(room as usize);
+                self.write_record()?;
+            }
+            // Records can't overlap, whatever the stamps around the gap say
+            self.record_onset = self.record_onset.max(self.seconds_since_start(timestamp));
+            self.discontinuous = true;
+        }
+        self.annotations.push_back(Annotation {
+            onset_ms: timestamp.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_millis() as u64,
+            duration_ms: 0.0,
+            channel: None,
+            label: writer::index_label(index, missing),
+        });
+        Ok(())
+    }
+
+    /// Time-stamped annotation lists for one record: its timekeeping TAL, its
+    /// padding, then as many queued annotations as fit
+    fn annotation_block(&mut self) -> Vec<u8> {
+        let capacity = self.annotation_samples() * self.variant.bytes_per_sample();
+        let mut block = format!("{}\x14\x14\0", tal_time(self.record_onset)).into_bytes();
+        let period = self.record_seconds / self.samples_per_record as f64;
+        for (onset, count) in std::mem::take(&mut self.padding) {
+            let tal = format!(
+                "{}\x15{}\x14{}{}{}\x14\0",
+                tal_time(onset),
+                tal_time(count as f64 * period).trim_start_matches('+'),
+                PADDING_PREFIX,
+                count,
+                PADDING_SUFFIX
+            );
+            if block.len() + tal.len() <= capacity {
+                block.extend_from_slice(tal.as_bytes());
+            } else {
+                eprintln!("[EDF] No room to annotate {} padding samples", count);
+            }
+        }
+
+        let start_ms = self
+            .start
+            .and_then(|s| s.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .unwrap_or_default()
+            .as_millis() as f64;
+        while let Some(annotation) = self.annotations.front() {
+            let onset = (annotation.onset_ms as f64 - start_ms) / 1000.0;
+            let label: String = annotation
+                .label
+                .chars()
+                .filter(|c| !matches!(c, '\x14' | '\x15' | '\0'))
+                .collect();
+            let label = match annotation.channel {
+                Some(channel) => format!("{} ch{}", label, channel + 1),
+                None => label,
+            };
+            let mut tal = tal_time(onset);
+            if annotation.duration_ms > 0.0 {
+                tal.push('\x15');
+                tal.push_str(tal_time(annotation.duration_ms / 1000.0).trim_start_matches('+'));
+            }
+            let mut tal = format!("{}\x14{}\x14\0", tal, label).into_bytes();
+            if block.len() + tal.len() > capacity {
+                if block.iter().filter(|&&b| b == 0).count() > 1 {
+                    break; // Wait for the next record
+                }
+                // Too long even for an empty record; shorten the label
+                tal.truncate(capacity - block.len() - 2);
+                tal.extend_from_slice(b"\x14\0");
+            }
+            block.extend_from_slice(&tal);
+            self.annotations.pop_front();
+        }
+        block.resize(capacity, 0);
+        block
+    }
+
+    fn write_record(&mut self) -> io::Result<()> {
+        let mut record = Vec::with_capacity(
+            self.labels.len() * self.samples_per_record * self.variant.bytes_per_sample()
+                + self.options.annotation_bytes,
+        );
+        let rows = std::mem::take(&mut self.pending);
+        for channel in 0..self.labels.len() {
+            for row in &rows {
+                self.encode_sample(row.get(channel).copied().unwrap_or(f32::NAN), &mut record);
+            }
+        }
+        record.extend(self.annotation_block());
+        self.file.write_all(&record)?;
+        self.records_written += 1;
+        self.record_onset += self.record_seconds;
+        Ok(())
+    }
+}
+
+impl RecordingWriter for EdfWriter {
+    fn write_samples(&mut self, rows: &[Row]) -> io::Result<()> {
+        for (index, timestamp, row) in rows {
+            if self.start.is_none() {
+                self.write_header(*timestamp)?;
+                // Samples are positional; this gives the session sample index of the first one
+                self.annotations.push_front(Annotation {
+                    onset_ms: timestamp.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_millis() as u64,
+                    duration_ms: 0.0,
+                    channel: None,
+                    label: writer::index_label(*index, 0),
+                });
+            }
+            let missing = self.timeline.add(*index, *timestamp);
+            if missing > 0 {
+                self.skip(missing, *index, *timestamp)?;
+            }
+            self.pending.push(row.clone());
+            if self.pending.len() == self.samples_per_record {
+                self.write_record()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_annotations(&mut self, annotations: &[Annotation]) -> io::Result<bool> {
+        self.annotations.extend(annotations.iter().cloned());
+        Ok(true)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self.start.is_none() {
+            self.write_header(SystemTime::now())?;
+        }
+        // Records have a fixed length; the rest of the last one is filled with
+        // zeros and annotated as holding no data, so readers can drop it
+        if !self.pending.is_empty() {
+            let missing = self.samples_per_record - self.pending.len();
+            if missing > 0 {
+                self.pad(missing);
+            }
+            self.write_record()?;
+        }
+        if !self.annotations.is_empty() {
+            eprintln!("[EDF] {} annotations did not fit into the last record", self.annotations.len());
+        }
+        if self.discontinuous {
+            self.file.seek(SeekFrom::Start(RESERVED_OFFSET))?;
+            self.file.write_all(self.variant.reserved(true).as_bytes())?;
+        }
+        self.file.seek(SeekFrom::Start(RECORD_COUNT_OFFSET))?;
+        self.file.write_all(ascii_field(&self.records_written.to_string(), 8).as_bytes())?;
+        self.file.seek(SeekFrom::End(0))?;
+        self.file.flush()?;
+        if let Some((written, target, config)) = self.compress_to.take() {
+            compression::compress_file(&written, &target, &config)?;
+        }
+        Ok(())
+    }
+}
+
+/// Trimmed text of a fixed-width header field
+fn header_text(bytes: &[u8], offset: usize, len: usize) -> String {
+    String::from_utf8_lossy(&bytes[offset..offset + len]).trim().to_string()
+}
+
+/// Start of the file from the `dd.mm.yy` and `hh.mm.ss` header fields, in local time
+fn header_start(date: &str, time: &str) -> Result<SystemTime, String> {
+    let numbers = |text: &str| -> Option<Vec<u32>> { text.split('.').map(|n| n.parse().ok()).collect() };
+    let (Some(date), Some(time)) = (numbers(date), numbers(time)) else {
+        return Err("Invalid EDF start date or time".to_string());
+    };
+    if date.len() != 3 || time.len() != 3 {
+        return Err("Invalid EDF start date or time".to_string());
+    }
+    // Two-digit years: 85-99 are 1985-1999, the rest are 20xx
+    let year = if date[2] >= 85 { 1900 + date[2] } else { 2000 + date[2] };
+    NaiveDate::from_ymd_opt(year as i32, date[1], date[0])
+        .and_then(|day| day.and_hms_opt(time[0], time[1], time[2]))
+        .and_then(|start| Local.from_local_datetime(&start).earliest())
+        .map(SystemTime::from)
+        .ok_or_else(|| "Invalid EDF start date or time".to_string())
+}
+
+/// Time-stamped annotation lists of one record: the record onset from its
+/// timekeeping TAL, and the onset, duration and text of every annotation,
+/// in seconds from the file start
+fn parse_tals(block: &[u8]) -> (Option<f64>, Vec<(f64, f64, String)>) {
+    let mut record_onset = None;
+    let mut annotations = Vec::new();
+    for tal in block.split(|&b| b == 0).filter(|tal| !tal.is_empty()) {
+        let text = String::from_utf8_lossy(tal);
+        let mut parts = text.split('\x14');
+        let Some(time) = parts.next() else {
+            continue;
+        };
+        let (onset, duration) = match time.split_once('\x15') {
+            Some((onset, duration)) => (onset, duration.parse().unwrap_or(0.0)),
+            None => (time, 0.0),
+        };
+        let Ok(onset) = onset.parse::<f64>() else {
+            continue;
+        };
+        let texts: Vec<&str> = parts.filter(|text| !text.is_empty()).collect();
+        if texts.is_empty() {
+            record_onset.get_or_insert(onset);
+        }
+        annotations.extend(texts.into_iter().map(|text| (onset, duration, text.to_string())));
+    }
+    (record_onset, annotations)
+}
+
+/// An ordinary signal of a stored file and how to scale it
+struct StoredSignal {
+    offset: usize, // Byte offset of its samples within a record
+    physical: (f64, f64),
+    digital: (f64, f64),
+}
+
+impl StoredSignal {
+    fn value(&self, record: &[u8], sample: usize, width: usize) -> f32 {
+        let at = self.offset + sample * width;
+        let digital = if width == 2 {
+            i16::from_le_bytes([record[at], record[at + 1]]) as f64
+        } else {
+            // Sign-extend the 24-bit value
+            (i32::from_le_bytes([0, record[at], record[at + 1], record[at + 2]]) >> 8) as f64
+        };
+        let (physical_min, physical_max) = self.physical;
+        let (digital_min, digital_max) = self.digital;
+        ((digital - digital_min) / (digital_max - digital_min) * (physical_max - physical_min) + physical_min) as f32
+    }
+}
+
+/// Reads an EDF(+) or BDF(+) file. All ordinary signals must share one sample
+/// rate. Only complete data records are read, so a file left with a record
+/// count of -1 by an interrupted recording still opens. Samples are timed from
+/// the record onsets, which EDF+D files need, and padding is dropped.
+pub fn read_edf(bytes: &[u8]) -> Result<Contents, String> {
+    if bytes.len() < 256 {
+        return Err("File is too short for an EDF header".to_string());
+    }
+    let variant = if bytes[0] == 0xFF { EdfVariant::Bdf } else { EdfVariant::Edf };
+    let width = variant.bytes_per_sample();
+    let number = |offset: usize, len: usize| {
+        header_text(bytes, offset, len)
+            .parse::<f64>()
+            .map_err(|_| format!("Invalid EDF header field at byte {}", offset))
+    };
+    let header_bytes = number(184, 8)? as usize;
+    let declared_records = number(236, 8)?;
+    let record_seconds = number(244, 8)?;
+    let signals = number(252, 4)? as usize;
+    if header_bytes != 256 * (signals + 1) || bytes.len() < header_bytes {
+        return Err("Inconsistent EDF header size".to_string());
+    }
+    let start = header_start(&header_text(bytes, 168, 8), &header_text(bytes, 176, 8))?;
+
+    // Per-signal fields are grouped field by field: `base` is the width of the fields before
+    let signal_field = |base: usize, width: usize, i: usize| header_text(bytes, 256 + base * signals + width * i, width);
+    let signal_number = |base: usize, i: usize| {
+        signal_field(base, 8, i)
+            .parse::<f64>()
+            .map_err(|_| format!("Invalid EDF header field of signal {}", i + 1))
+    };
+    let mut labels = Vec::new();
+    let mut stored = Vec::new();
+    let mut annotation_signal = None;
+    let mut samples_per_record = None;
+    let mut record_bytes = 0;
+    for i in 0..signals {
+        let label = signal_field(0, 16, i);
+        let samples = signal_number(216, i)? as usize;
+        let offset = record_bytes;
+        record_bytes += samples * width;
+        if label == EdfVariant::Edf.annotation_label() || label == EdfVariant::Bdf.annotation_label() {
+            annotation_signal.get_or_insert((offset, samples * width));
+            continue;
+        }
+        if *samples_per_record.get_or_insert(samples) != samples {
+            return Err("EDF signals with different sample rates are not supported".to_string());
+        }
+        stored.push(StoredSignal {
+            offset,
+            physical: (signal_number(104, i)?, signal_number(112, i)?),
+            digital: (signal_number(120, i)?, signal_number(128, i)?),
+        });
+        labels.push(label);
+    }
+    if record_bytes == 0 {
+        return Err("EDF file without samples".to_string());
+    }
+    let mut records = (bytes.len() - header_bytes) / record_bytes;
+    if declared_records >= 0.0 {
+        records = records.min(declared_records as usize);
+    }
+
+    let start_ms = start.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs_f64() * 1000.0;
+    let samples_per_record = samples_per_record.unwrap_or(0);
+    let period = record_seconds / samples_per_record.max(1) as f64;
+    let mut samples = Vec::with_capacity(records * samples_per_record);
+    let mut annotations = Vec::new();
+    for number in 0..records {
+        let record = &bytes[header_bytes + number * record_bytes..header_bytes + (number + 1) * record_bytes];
+        let mut onset = number as f64 * record_seconds;
+        // Samples of this record that hold no data
+        let mut padded = vec![false; samples_per_record];
+        if let Some((offset, len)) = annotation_signal {
+            let (record_onset, found) = parse_tals(&record[offset..offset + len]);
+            onset = record_onset.unwrap_or(onset);
+            for (annotation_onset, duration, text) in found {
+                if let Some(count) = padding_samples(&text) {
+                    let first = ((annotation_onset - onset) / period).round().max(0.0) as usize;
+                    for sample in padded.iter_mut().skip(first).take(count) {
+                        *sample = true;
+                    }
+                    continue;
+                }
+                // Channel annotations are written as "<label> ch<number>"
+                let (label, channel) = match text.rsplit_once(" ch").map(|(l, c)| (l, c.parse::<usize>())) {
+                    Some((label, Ok(channel))) if channel > 0 => (label.to_string(), Some(channel - 1)),
+                    _ => (text, None),
+                };
+                annotations.push(Annotation {
+                    onset_ms: (start_ms + annotation_onset * 1000.0).round().max(0.0) as u64,
+                    duration_ms: duration * 1000.0,
+                    channel,
+                    label,
+                });
+            }
+        }
+        for sample in (0..samples_per_record).filter(|&sample| !padded[sample]) {
+            let seconds = onset + sample as f64 * period;
+            samples.push(Sample {
+                time: time_from_millis(start_ms + seconds * 1000.0),
+                index: None,
+                values: stored.iter().map(|signal| signal.value(record, sample, width)).collect(),
+            });
+        }
+    }
+
+    // EDF+ keeps the start date in front of the recording field
+    let recording_field = header_text(bytes, 88, 80);
+    let recording_id = match recording_field.split_once(' ') {
+        Some(("Startdate", rest)) => rest.split_once(' ').map(|(_, id)| id.to_string()).unwrap_or_default(),
+        _ => recording_field,
+    };
+    let source = SourceInfo {
+        sample_rate: (samples_per_record > 0 && record_seconds > 0.0)
+            .then(|| samples_per_record as f64 / record_seconds),
+        device: None,
+        patient_id: Some(header_text(bytes, 8, 80)).filter(|id| !id.is_empty()),
+        recording_id: Some(recording_id).filter(|id| !id.is_empty()),
+    };
+    Ok(Contents {
+        labels,
+        samples,
+        annotations,
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{micros_apart, millis, rows, temp_dir};
+    use crate::writer;
+
+    fn info(labels: &[&str], sample_rate: f64) -> RecordingInfo {
+        RecordingInfo {
+            labels: labels.iter().map(|l| l.to_string()).collect(),
+            sample_rate,
+            ..Default::default()
+        }
+    }
+
+    /// Writes `rows` and `annotations` in `format` and reads the file back
+    fn round_trip(
+        format: &str,
+        info: &RecordingInfo,
+        rows: &[Row],
+        annotations: &[Annotation],
+    ) -> Contents {
+        let path = temp_dir("edf").join(format!("recording.{}", format));
+        let mut writer = writer::create_writer(format, &path, info).unwrap();
+        assert!(writer.write_annotations(annotations).unwrap());
+        writer.write_samples(rows).unwrap();
+        writer.finish().unwrap();
+        read_edf(&std::fs::read(&path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn lays_out_records_with_whole_samples() {
+        assert_eq!(record_layout(250.0), Ok((1.0, 250)));
+        assert_eq!(record_layout(250.5), Ok((2.0, 501)));
+        assert_eq!(record_layout(0.1), Ok((10.0, 1)));
+        let (seconds, samples) = record_layout(256.123).unwrap();
+        assert_eq!(samples, 256);
+        assert!((samples as f64 / seconds - 256.123).abs() < 1e-3, "{}", seconds);
+        assert!(record_layout(0.0).is_err());
+        assert!(record_layout(f64::NAN).is_err());
+    }
+
+    #[test]
+    fn edf_round_trip_trims_padding() {
+        let rows = rows(600, 2, 250.0);
+        let marker = Annotation {
+            onset_ms: millis(rows[300].1),
+            duration_ms: 500.0,
+            channel: Some(1),
+            label: "blink".to_string(),
+        };
+        let contents = round_trip("edf", &info(&["C3", "C4"], 250.0), &rows, &[marker]);
+        let (samples, annotations) = (contents.samples, contents.annotations);
+
+        assert_eq!(contents.labels, ["C3", "C4"]);
+        assert_eq!(contents.source.sample_rate, Some(250.0));
+        assert_eq!(contents.source.patient_id.as_deref(), Some("X X X X"));
+        assert_eq!(contents.source.recording_id.as_deref(), Some("X X serial-brain"));
+        assert_eq!(samples.len(), rows.len());
+        // 16 bits over the default +-3276.7 uV resolve 0.1 uV
+        let resolution = 0.1;
+        for (sample, (_, time, expected)) in samples.iter().zip(&rows) {
+            assert!(micros_apart(sample.time, *time) <= 1);
+            for (value, expected) in sample.values.iter().zip(expected) {
+                assert!((value - expected).abs() as f64 <= resolution, "{} vs {}", value, expected);
+            }
+        }
+        let blink = annotations.iter().find(|a| a.label == "blink").unwrap();
+        assert_eq!((blink.onset_ms, blink.duration_ms, blink.channel), (millis(rows[300].1), 500.0, Some(1)));
+        assert!(annotations.iter().any(|a| a.label == "Sample index 0"));
+        assert!(!annotations.iter().any(|a| a.label.starts_with(PADDING_PREFIX)));
+    }
+
+    #[test]
+    fn gaps_are_padded_or_start_discontinuous_records() {
+        let mut rows = rows(1500, 1, 250.0);
+        rows.drain(900..1200); // Longer than the rest of its record
+        rows.drain(100..110); // Fits into its record
+        let path = temp_dir("edf").join("recording.edf");
+        let mut writer = writer::create_writer("edf", &path, &info(&["Cz"], 250.0)).unwrap();
+        let after_gap = Annotation {
+            onset_ms: millis(rows[1000].1),
+            duration_ms: 0.0,
+            channel: None,
+            label: "blink".to_string(),
+        };
+        writer.write_annotations(std::slice::from_ref(&after_gap)).unwrap();
+        writer.write_samples(&rows).unwrap();
+        writer.finish().unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(&bytes[192..197], b"EDF+D");
+
+        let Contents { samples, annotations, .. } = read_edf(&bytes).unwrap();
+        assert_eq!(samples.len(), rows.len());
+        for (sample, (_, time, _)) in samples.iter().zip(&rows) {
+            assert!(micros_apart(sample.time, *time) <= 1);
+        }
+        let labels: Vec<&str> = annotations.iter().map(|a| a.label.as_str()).collect();
+        assert!(labels.contains(&"Sample index 110 after 10 missing samples"), "{:?}", labels);
+        assert!(labels.contains(&"Sample index 1200 after 300 missing samples"), "{:?}", labels);
+        // Record onsets keep real time, so annotations after a gap stay on their sample
+        let blink = annotations.iter().find(|a| a.label == "blink").unwrap();
+        assert_eq!(blink.onset_ms, after_gap.onset_ms);
+        assert!(samples.iter().any(|sample| sample.millis() == blink.onset_ms));
+    }
+
+    #[test]
+    fn bdf_keeps_device_resolution_and_offsets() {
+        let mut rows = rows(500, 1, 500.0);
+        for (_, _, values) in rows.iter_mut() {
+            values[0] += 300_000.0; // Electrode offset far beyond the EEG amplitude
+        }
+        let samples = round_trip("bdf", &info(&["Fp1"], 500.0), &rows, &[]).samples;
+        assert_eq!(samples.len(), rows.len());
+        for (sample, (_, _, expected)) in samples.iter().zip(&rows) {
+            assert!((sample.values[0] - expected[0]).abs() < 0.05, "{} vs {}", sample.values[0], expected[0]);
+        }
+    }
+
+    #[test]
+    fn fractional_rate_keeps_sample_times() {
+        let rate = 256.123;
+        let rows = rows(1000, 1, rate);
+        let samples = round_trip("bdf", &info(&["Cz"], rate), &rows, &[]).samples;
+        assert_eq!(samples.len(), rows.len());
+        for (sample, (_, time, _)) in samples.iter().zip(&rows) {
+            assert!(micros_apart(sample.time, *time) <= 1000, "{:?} vs {:?}", sample.time, time);
+        }
+    }
+
+    #[test]
+    fn reads_interrupted_files_and_rejects_malformed_ones() {
+        let rows = rows(1000, 1, 250.0);
+        let path = temp_dir("edf").join("recording.edf");
+        let mut writer = writer::create_writer("edf", &path, &info(&["Cz"], 250.0)).unwrap();
+        writer.write_samples(&rows).unwrap();
+        drop(writer);
+        // Unfinished: record count -1 and no padded last record
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(read_edf(&bytes).unwrap().samples.len(), 1000);
+        assert_eq!(read_edf(&bytes[..bytes.len() - 100]).unwrap().samples.len(), 750);
+
+        assert!(read_edf(&bytes[..200]).is_err());
+        let mut inconsistent = bytes.clone();
+        inconsistent[184..192].copy_from_slice(b"9999    ");
+        assert!(read_edf(&inconsistent).is_err());
+        let mut garbled = bytes.clone();
+        garbled[252..256].copy_from_slice(b"xx  ");
+        assert!(read_edf(&garbled).is_err());
+    }
+}
@@ -0,0 +1,505 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::ChannelData;
+
+/// A named derived channel as configured by the user, e.g. `{"name": "C3-C4", "expression": "C3 - C4"}`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DerivedExpression {
+    pub name: String,
+    pub expression: String,
+}
+
+/// Deepest nesting of parentheses, calls and unary minus the parser accepts
+const MAX_DEPTH: usize = 64;
+
+/// Longest expression in tokens; also bounds how deep a chain like `a+b+c+...`
+/// nests when it is evaluated
+const MAX_TOKENS: usize = 1024;
+
+/// Longest window a function like `rms(C3, 2s)` may keep, in milliseconds
+const MAX_WINDOW_MS: f64 = 10_000.0;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Duration(f64), // Milliseconds, written as `200ms` or `1.5s`
+    Ident(String),
+    Op(char),
+    LParen,
+    RParen,
+    Comma,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let value: f64 = text.parse().map_err(|_| format!("Invalid number '{}'", text))?;
+            let unit_start = i;
+            while i < chars.len() && chars[i].is_ascii_alphabetic() {
+                i += 1;
+            }
+            let unit: String = chars[unit_start..i].iter().collect();
+            tokens.push(match unit.as_str() {
+                "" => Token::Number(value),
+                "ms" => Token::Duration(value),
+                "s" => Token::Duration(value * 1000.0),
+                _ => return Err(format!("Unknown unit '{}'", unit)),
+            });
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else {
+            tokens.push(match c {
+                '+' | '-' | '*' | '/' | '^' => Token::Op(c),
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                ',' => Token::Comma,
+                _ => return Err(format!("Unexpected character '{}'", c)),
+            });
+            i += 1;
+        }
+    }
+    Ok(tokens)
+}
+
+/// Functions that keep a sliding window of their argument
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum WindowOp {
+    Mean,
+    Rms,
+    Std,
+    MaxAbs,
+}
+
+/// Sliding window of a function argument with running sums, so each sample costs O(1)
+#[derive(Debug, Clone, Default)]
+struct SlidingWindow {
+    values: VecDeque<f64>,
+    peaks: VecDeque<f64>, // Non-increasing magnitudes that can still become the window maximum
+    sum: f64,
+    sum_sq: f64,
+    since_refresh: usize, // Samples since the sums were last recomputed exactly
+}
+
+impl SlidingWindow {
+    fn push(&mut self, value: f64, capacity: usize) {
+        while self.values.len() >= capacity {
+            let Some(old) = self.values.pop_front() else { break };
+            self.sum -= old;
+            self.sum_sq -= old * old;
+            if self.peaks.front() == Some(&old.abs()) {
+                self.peaks.pop_front();
+            }
+        }
+        self.values.push_back(value);
+        self.sum += value;
+        self.sum_sq += value * value;
+        if !value.is_nan() {
+            while self.peaks.back().is_some_and(|&peak| peak < value.abs()) {
+                self.peaks.pop_back();
+            }
+            self.peaks.push_back(value.abs());
+        }
+
+        // Recompute once per window so rounding errors of the running sums cannot build up,
+        // and right away once a non-finite value has left them poisoned
+        self.since_refresh += 1;
+        if self.since_refresh >= capacity || !self.sum_sq.is_finite() {
+            self.sum = self.values.iter().sum();
+            self.sum_sq = self.values.iter().map(|v| v * v).sum();
+            self.since_refresh = 0;
+        }
+    }
+
+    fn value(&self, op: WindowOp) -> f64 {
+        let n = self.values.len() as f64;
+        let mean = self.sum / n;
+        match op {
+            WindowOp::Mean => mean,
+            WindowOp::Rms => (self.sum_sq / n).max(0.0).sqrt(),
+            WindowOp::Std => (self.sum_sq / n - mean * mean).max(0.0).sqrt(),
+            WindowOp::MaxAbs => self.peaks.front().copied().unwrap_or(0.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Number(f64),
+    Variable(String),
+    Negate(Box<Node>),
+    Binary(char, Box<Node>, Box<Node>),
+    Call(String, Vec<Node>),
+    Window {
+        op: WindowOp,
+        arg: Box<Node>,
+        window_ms: f64,
+        window: SlidingWindow,
+    },
+    Diff {
+        arg: Box<Node>,
+        previous: Option<f64>,
+    },
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize, // Nesting level of the rule being parsed
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some(ref token) if *token == expected => Ok(()),
+            other => Err(format!("Expected {:?}, found {:?}", expected, other)),
+        }
+    }
+
+    // expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<Node, String> {
+        let mut node = self.term()?;
+        while let Some(Token::Op(op @ ('+' | '-'))) = self.peek().cloned() {
+            self.pos += 1;
+            node = Node::Binary(op, Box::new(node), Box::new(self.term()?));
+        }
+        Ok(node)
+    }
+
+    // term := unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<Node, String> {
+        let mut node = self.unary()?;
+        while let Some(Token::Op(op @ ('*' | '/'))) = self.peek().cloned() {
+            self.pos += 1;
+            node = Node::Binary(op, Box::new(node), Box::new(self.unary()?));
+        }
+        Ok(node)
+    }
+
+    // unary := '-' unary | power
+    fn unary(&mut self) -> Result<Node, String> {
+        // Every level of nesting passes through here, so this bounds the recursion
+        if self.depth == MAX_DEPTH {
+            return Err(format!("Expression nested deeper than {} levels", MAX_DEPTH));
+        }
+        self.depth += 1;
+        let node = if let Some(Token::Op('-')) = self.peek() {
+            self.pos += 1;
+            self.unary().map(|inner| Node::Negate(Box::new(inner)))
+        } else {
+            self.power()
+        };
+        self.depth -= 1;
+        node
+    }
+
+    // power := primary ('^' unary)?
+    fn power(&mut self) -> Result<Node, String> {
+        let base = self.primary()?;
+        if let Some(Token::Op('^')) = self.peek() {
+            self.pos += 1;
+            return Ok(Node::Binary('^', Box::new(base), Box::new(self.unary()?)));
+        }
+        Ok(base)
+    }
+
+    fn primary(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Node::Number(value)),
+            Some(Token::Ident(name)) => {
+                if let Some(Token::LParen) = self.peek() {
+                    self.pos += 1;
+                    self.call(name)
+                } else {
+                    Ok(Node::Variable(name))
+                }
+            }
+            Some(Token::LParen) => {
+                let node = self.expr()?;
+                self.expect(Token::RParen)?;
+                Ok(node)
+            }
+            Some(Token::Duration(_)) => Err("Durations are only allowed as window arguments".to_string()),
+            other => Err(format!("Unexpected token {:?}", other)),
+        }
+    }
+
+    fn call(&mut self, name: String) -> Result<Node, String> {
+        let window_op = match name.as_str() {
+            "mean" => Some(WindowOp::Mean),
+            "rms" => Some(WindowOp::Rms),
+            "std" => Some(WindowOp::Std),
+            "maxabs" => Some(WindowOp::MaxAbs),
+            _ => None,
+        };
+        if let Some(op) = window_op {
+            let arg = self.expr()?;
+            self.expect(Token::Comma)?;
+            let window_ms = match self.next() {
+                Some(Token::Duration(ms)) if ms > MAX_WINDOW_MS => {
+                    return Err(format!("{}() window is longer than {} s", name, MAX_WINDOW_MS / 1000.0))
+                }
+                Some(Token::Duration(ms)) if ms > 0.0 => ms,
+                other => return Err(format!("{}() expects a window like 200ms, found {:?}", name, other)),
+            };
+            self.expect(Token::RParen)?;
+            return Ok(Node::Window {
+                op,
+                arg: Box::new(arg),
+                window_ms,
+                window: SlidingWindow::default(),
+            });
+        }
+
+        let mut args = Vec::new();
+        if let Some(Token::RParen) = self.peek() {
+            self.pos += 1;
+        } else {
+            loop {
+                args.push(self.expr()?);
+                match self.next() {
+                    Some(Token::Comma) => continue,
+                    Some(Token::RParen) => break,
+                    other => return Err(format!("Expected ',' or ')', found {:?}", other)),
+                }
+            }
+        }
+
+        let arity = match name.as_str() {
+            "abs" | "sqrt" | "log" | "exp" | "diff" => 1,
+            "min" | "max" | "pow" => 2,
+            _ => return Err(format!("Unknown function '{}'", name)),
+        };
+        if args.len() != arity {
+            return Err(format!("{}() expects {} argument(s)", name, arity));
+        }
+        if name == "diff" {
+            return Ok(Node::Diff {
+                arg: Box::new(args.remove(0)),
+                previous: None,
+            });
+        }
+        Ok(Node::Call(name, args))
+    }
+}
+
+/// Values an expression can refer to while evaluating one sample
+pub struct EvalContext<'a> {
+    pub raw: &'a ChannelData,  // Raw device channels, addressed as ch1..ch8
+    pub display: &'a [f32],    // Montage output, addressed by label
+    pub labels: &'a [String],
+    pub sample_rate: f64,
+}
+
+impl EvalContext<'_> {
+    fn variable(&self, name: &str) -> Result<f64, String> {
+        if let Some(position) = self.labels.iter().position(|l| l == name) {
+            return Ok(self.display[position] as f64);
+        }
+        if let Some(index) = name.strip_prefix("ch").and_then(|n| n.parse::<usize>().ok()) {
+            if (1..=self.raw.len()).contains(&index) {
+                return Ok(self.raw[index - 1] as f64);
+            }
+        }
+        Err(format!("Unknown channel '{}'", name))
+    }
+}
+
+impl Node {
+    fn eval(&mut self, ctx: &EvalContext) -> Result<f64, String> {
+        Ok(match self {
+            Node::Number(value) => *value,
+            Node::Variable(name) => ctx.variable(name)?,
+            Node::Negate(inner) => -inner.eval(ctx)?,
+            Node::Binary(op, lhs, rhs) => {
+                let (a, b) = (lhs.eval(ctx)?, rhs.eval(ctx)?);
+                match op {
+                    '+' => a + b,
+                    '-' => a - b,
+                    '*' => a * b,
+                    '/' => a / b,
+                    _ => a.powf(b),
+                }
+            }
+            Node::Call(name, args) => {
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args.iter_mut() {
+                    values.push(arg.eval(ctx)?);
+                }
+                match name.as_str() {
+                    "abs" => values[0].abs(),
+                    "sqrt" => values[0].sqrt(),
+                    "log" => values[0].ln(),
+                    "exp" => values[0].exp(),
+                    "min" => values[0].min(values[1]),
+                    "max" => values[0].max(values[1]),
+                    _ => values[0].powf(values[1]),
+                }
+            }
+            Node::Window { op, arg, window_ms, window } => {
+                let value = arg.eval(ctx)?;
+                let capacity = ((*window_ms * ctx.sample_rate / 1000.0).round() as usize).max(1);
+                window.push(value, capacity);
+                window.value(*op)
+            }
+            Node::Diff { arg, previous } => {
+                let value = arg.eval(ctx)?;
+                let delta = previous.map(|p| value - p).unwrap_or(0.0);
+                *previous = Some(value);
+                delta
+            }
+        })
+    }
+}
+
+/// A parsed expression together with the state of its windowed functions
+#[derive(Debug, Clone)]
+pub struct CompiledExpression {
+    pub name: String,
+    root: Node,
+}
+
+impl CompiledExpression {
+    pub fn parse(definition: &DerivedExpression) -> Result<Self, String> {
+        if definition.name.trim().is_empty() {
+            return Err("Derived channel name must not be empty".to_string());
+        }
+        let tokens = tokenize(&definition.expression)
+            .map_err(|e| format!("{}: {}", definition.name, e))?;
+        if tokens.len() > MAX_TOKENS {
+            return Err(format!("{}: expression longer than {} tokens", definition.name, MAX_TOKENS));
+        }
+        let mut parser = Parser { tokens, pos: 0, depth: 0 };
+        let root = parser.expr().map_err(|e| format!("{}: {}", definition.name, e))?;
+        if parser.pos < parser.tokens.len() {
+            return Err(format!(
+                "{}: unexpected trailing input {:?}",
+                definition.name, parser.tokens[parser.pos]
+            ));
+        }
+        Ok(Self {
+            name: definition.name.clone(),
+            root,
+        })
+    }
+
+    /// Evaluate one sample; unresolved channels yield NaN
+    pub fn evaluate(&mut self, ctx: &EvalContext) -> f32 {
+        self.root.eval(ctx).map(|v| v as f32).unwrap_or(f32::NAN)
+    }
+
+    /// Evaluate once against a context to surface unknown channels early
+    pub fn check(&self, ctx: &EvalContext) -> Result<(), String> {
+        self.root
+            .clone()
+            .eval(ctx)
+            .map(|_| ())
+            .map_err(|e| format!("{}: {}", self.name, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile(expression: &str) -> Result<CompiledExpression, String> {
+        CompiledExpression::parse(&DerivedExpression {
+            name: "test".to_string(),
+            expression: expression.to_string(),
+        })
+    }
+
+    fn evaluate(expression: &str, raw: &ChannelData) -> f32 {
+        let labels = vec!["C3".to_string(), "C4".to_string()];
+        let display = [raw[0], raw[1]];
+        let ctx = EvalContext {
+            raw,
+            display: &display,
+            labels: &labels,
+            sample_rate: 250.0,
+        };
+        compile(expression).unwrap().evaluate(&ctx)
+    }
+
+    #[test]
+    fn evaluates_precedence_and_functions() {
+        let raw = [1.0, 3.0, 0.0, 0.0, 0.0, 0.0, 0.0, 4.0];
+        assert_eq!(evaluate("C3 - C4 * 2", &raw), -5.0);
+        assert_eq!(evaluate("-(C3 + C4) ^ 2", &raw), -16.0);
+        assert_eq!(evaluate("max(abs(C3 - C4), sqrt(ch8))", &raw), 2.0);
+        assert!(evaluate("C5", &raw).is_nan());
+    }
+
+    #[test]
+    fn window_functions_need_a_duration() {
+        assert!(compile("mean(C3, 200ms)").is_ok());
+        assert!(compile("mean(C3, 200)").is_err());
+        assert!(compile("C3 + 200ms").is_err());
+        assert!(compile("rms(C3, 10s)").is_ok());
+        assert!(compile("rms(C3, 60s)").is_err());
+    }
+
+    #[test]
+    fn running_window_matches_a_full_rescan() {
+        let values: Vec<f64> = (0..500).map(|i| ((i * 37) % 101) as f64 - 50.0).collect();
+        let capacity = 25;
+        let mut window = SlidingWindow::default();
+        for (i, &value) in values.iter().enumerate() {
+            window.push(value, capacity);
+            let recent = &values[(i + 1).saturating_sub(capacity)..=i];
+            let n = recent.len() as f64;
+            let mean = recent.iter().sum::<f64>() / n;
+            let rms = (recent.iter().map(|v| v * v).sum::<f64>() / n).sqrt();
+            let std = (recent.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n).sqrt();
+            let max_abs = recent.iter().fold(0.0f64, |m, v| m.max(v.abs()));
+            assert!((window.value(WindowOp::Mean) - mean).abs() < 1e-9);
+            assert!((window.value(WindowOp::Rms) - rms).abs() < 1e-9);
+            assert!((window.value(WindowOp::Std) - std).abs() < 1e-9);
+            assert_eq!(window.value(WindowOp::MaxAbs), max_abs);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        for expression in ["", "C3 +", "(C3", "C3)", "pow(C3)", "foo(C3)", "C3 $ C4"] {
+            assert!(compile(expression).is_err(), "{:?} was accepted", expression);
+        }
+    }
+
+    #[test]
+    fn limits_nesting_depth() {
+        let nested = |depth: usize| format!("{}C3{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(compile(&nested(MAX_DEPTH - 1)).is_ok());
+        assert!(compile(&nested(MAX_DEPTH)).is_err());
+        // Deep enough to overflow the stack without the limit
+        assert!(compile(&nested(3000)).is_err());
+        assert!(compile(&"-".repeat(3000)).is_err());
+        assert!(compile(&vec!["C3"; 2000].join("+")).is_err());
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::VecDeque;
+use std::sync::{atomic::Ordering, Arc};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::state::AppState;
+use crate::types::ChannelData;
+
+/// Device parameters of the impedance test
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImpedanceConfig {
+    pub enable_command: String,      // Sent to the device to switch the test current on
+    pub disable_command: String,     // Sent to the device to switch it off again
+    pub test_frequency: f64,         // Frequency of the injected AC current in Hz
+    pub drive_current_na: f64,       // Peak amplitude of the injected current in nA
+    pub series_resistance_kohm: f64, // On-board resistance in the measurement path
+    pub volts_per_unit: f64,         // Conversion of sample values to volts
+    pub window_seconds: f64,         // Length of the demodulation window, rounded to whole test periods
+    pub interval_ms: u64,            // How often an `impedance_update` event is published
+}
+
+impl Default for ImpedanceConfig {
+    fn default() -> Self {
+        Self {
+            enable_command: "IMP ON\r\n".to_string(),
+            disable_command: "IMP OFF\r\n".to_string(),
+            test_frequency: 31.25,
+            drive_current_na: 6.0,
+            series_resistance_kohm: 0.0,
+            // Samples are already converted to microvolts by the reader
+            volts_per_unit: 1e-6,
+            window_seconds: 1.0,
+            interval_ms: 500,
+        }
+    }
+}
+
+impl ImpedanceConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.test_frequency <= 0.0 {
+            return Err("Impedance test frequency must be positive".to_string());
+        }
+        if self.drive_current_na <= 0.0 || self.volts_per_unit <= 0.0 {
+            return Err("Impedance drive current and scale must be positive".to_string());
+        }
+        if self.window_seconds <= 0.0 || self.interval_ms == 0 {
+            return Err("Impedance window and interval must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    /// Demodulation window in samples, spanning a whole number of test-signal periods
+    /// so the lock-in sums carry no leakage from a partial cycle
+    pub fn window_samples(&self, sample_rate: f64) -> usize {
+        let periods = (self.window_seconds * self.test_frequency).round().max(1.0);
+        ((periods * sample_rate / self.test_frequency).round() as usize).max(1)
+    }
+
+    /// Peak amplitude in sample units produced by an electrode of `kohm`
+    pub fn amplitude_for(&self, kohm: f64) -> f64 {
+        let volts = self.drive_current_na * 1e-9 * (kohm + self.series_resistance_kohm) * 1e3;
+        volts / self.volts_per_unit
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelImpedance {
+    pub channel: usize,
+    pub amplitude: f64,      // Peak amplitude at the test frequency, in sample units
+    pub impedance_kohm: f64,
+}
+
+/// Payload of the `impedance_update` event
+#[derive(Debug, Clone, Serialize)]
+pub struct ImpedanceReport {
+    pub test_frequency: f64,
+    pub window_samples: usize,
+    pub channels: Vec<ChannelImpedance>,
+}
+
+/// Lock-in demodulator of the test-frequency response on every channel
+pub struct ImpedanceEngine {
+    history: VecDeque<ChannelData>,
+    samples_since_update: usize,
+    latest: Option<ImpedanceReport>,
+}
+
+impl ImpedanceEngine {
+    pub fn new() -> Self {
+        Self {
+            history: VecDeque::new(),
+            samples_since_update: 0,
+            latest: None,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.samples_since_update = 0;
+        self.latest = None;
+    }
+
+    pub fn latest(&self) -> Option<&ImpedanceReport> {
+        self.latest.as_ref()
+    }
+
+    /// Feed one sample; returns a report whenever the publish interval elapses
+    pub fn add_data(
+        &mut self,
+        data: ChannelData,
+        sample_rate: f64,
+        config: &ImpedanceConfig,
+    ) -> Option<ImpedanceReport> {
+        let capacity = config.window_samples(sample_rate);
+        while self.history.len() >= capacity {
+            self.history.pop_front();
+        }
+        self.history.push_back(data);
+
+        self.samples_since_update += 1;
+        let interval = ((config.interval_ms as f64 * sample_rate / 1000.0).round() as usize).max(1);
+        if self.samples_since_update < interval || self.history.len() < capacity {
+            return None;
+        }
+        self.samples_since_update = 0;
+
+        let report = self.demodulate(sample_rate, config);
+        self.latest = Some(report.clone());
+        Some(report)
+    }
+
+    fn demodulate(&self, sample_rate: f64, config: &ImpedanceConfig) -> ImpedanceReport {
+        let n = self.history.len();
+        let omega = 2.0 * std::f64::consts::PI * config.test_frequency / sample_rate;
+        let channels = (0..8)
+            .map(|channel| {
+                let mean = self.history.iter().map(|d| d[channel] as f64).sum::<f64>() / n as f64;
+                let (mut i_sum, mut q_sum) = (0.0, 0.0);
+                for (k, d) in self.history.iter().enumerate() {
+                    let x = d[channel] as f64 - mean;
+                    i_sum += x * (omega * k as f64).cos();
+                    q_sum += x * (omega * k as f64).sin();
+                }
+                // Peak amplitude of the in-phase/quadrature components
+                let amplitude = 2.0 * (i_sum * i_sum + q_sum * q_sum).sqrt() / n as f64;
+                let volts = amplitude * config.volts_per_unit;
+                let ohms = volts / (config.drive_current_na * 1e-9);
+                ChannelImpedance {
+                    channel,
+                    amplitude,
+                    impedance_kohm: (ohms / 1e3 - config.series_resistance_kohm).max(0.0),
+                }
+            })
+            .collect();
+        ImpedanceReport {
+            test_frequency: config.test_frequency,
+            window_samples: n,
+            channels,
+        }
+    }
+}
+
+/// Switches the device into impedance mode and starts demodulating the test signal.
+/// The fake reader simulates the test current itself, so no command is sent to it.
+/// A new config while active restarts the demodulation, and a changed enable command is re-sent.
+pub fn start_impedance(app_handle: AppHandle, config: Option<ImpedanceConfig>) -> Result<(), String> {
+    let state = app_handle.state::<Arc<AppState>>();
+    let active = state.impedance.active.load(Ordering::SeqCst);
+    let (mut changed, mut command_changed) = (false, false);
+    if let Some(config) = config {
+        config.validate()?;
+        let mut current = state.impedance.config.lock().unwrap();
+        changed = config != *current;
+        command_changed = config.enable_command != current.enable_command;
+        *current = config;
+    }
+    if active {
+        if changed {
+            // Windows demodulated under the old settings no longer apply
+            state.impedance.engine.lock().unwrap().reset();
+            println!("[Impedance] Configuration changed, measurement restarted");
+        }
+        if command_changed {
+            send_enable_command(&state)?;
+        }
+        return Ok(());
+    }
+
+    send_enable_command(&state)?;
+    state.impedance.engine.lock().unwrap().reset();
+    state.impedance.active.store(true, Ordering::SeqCst);
+    println!("[Impedance] Measurement started");
+    Ok(())
+}
+
+fn send_enable_command(state: &AppState) -> Result<(), String> {
+    if state.stream.fake_signal_enabled.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+    let command = state.impedance.config.lock().unwrap().enable_command.clone();
+    match state.communication.outbound_tx.lock().unwrap().as_ref() {
+        Some(tx) => tx
+            .send(command)
+            .map_err(|e| format!("Failed to send impedance command: {}", e)),
+        None => Err("Serial port is not connected.".into()),
+    }
+}
+
+/// Leaves impedance mode and switches the device test current off.
+pub fn stop_impedance(app_handle: AppHandle) -> Result<(), String> {
+    let state = app_handle.state::<Arc<AppState>>();
+    if !state.impedance.active.swap(false, Ordering::SeqCst) {
+        return Ok(());
+    }
+
+    if !state.stream.fake_signal_enabled.load(Ordering::SeqCst) {
+        let command = state.impedance.config.lock().unwrap().disable_command.clone();
+        if let Some(tx) = state.communication.outbound_tx.lock().unwrap().as_ref() {
+            tx.send(command)
+                .map_err(|e| format!("Failed to send impedance command: {}", e))?;
+        }
+    }
+    println!("[Impedance] Measurement stopped");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_spans_whole_test_periods() {
+        let config = ImpedanceConfig::default();
+        // 31.25 Hz at 250 Hz is 8 samples per period; 1 s rounds to 31 periods
+        assert_eq!(config.window_samples(250.0), 248);
+
+        let mut engine = ImpedanceEngine::new();
+        let amplitude = config.amplitude_for(10.0);
+        let omega = 2.0 * std::f64::consts::PI * config.test_frequency / 250.0;
+        let mut report = None;
+        for k in 0..1000 {
+            let value = (amplitude * (omega * k as f64).sin()) as f32;
+            report = engine.add_data([value; 8], 250.0, &config).or(report);
+        }
+        let report = report.expect("a report after a full window");
+        assert_eq!(report.window_samples, 248);
+        for channel in &report.channels {
+            assert!((channel.impedance_kohm - 10.0).abs() < 0.01, "{}", channel.impedance_kohm);
+        }
+    }
+}
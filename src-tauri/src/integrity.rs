@@ -0,0 +1,558 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Manager};
+
+use crate::recording_reader;
+use crate::session;
+
+/// Name of the checksum manifest kept in each session directory
+pub const CHECKSUM_FILE: &str = "checksums.json";
+
+/// Version of the checksum manifest layout; version 2 added the signature
+const CHECKSUM_VERSION: u32 = 2;
+
+/// Name of the signing key file in the app data directory
+const KEY_FILE: &str = "integrity.key";
+
+/// A video is sealed once its size stops changing for this long, since the
+/// video plugin may still be finishing the file when recording stops
+const VIDEO_SETTLE: Duration = Duration::from_millis(500);
+
+/// Longest wait for a stopped video to settle
+const VIDEO_SETTLE_LIMIT: Duration = Duration::from_secs(30);
+
+/// Serializes updates of checksum manifests, which segments and videos seal from different threads
+static MANIFEST_LOCK: Mutex<()> = Mutex::new(());
+
+/// Per-installation key that checksum manifests are signed with
+type SigningKey = [u8; 32];
+
+/// Signing key of this installation, loaded at startup by `load_signing_key`
+static SIGNING_KEY: Mutex<Option<SigningKey>> = Mutex::new(None);
+
+/// Size and SHA-256 of a file as it was when sealed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChecksumEntry {
+    pub file: String,
+    pub size_bytes: u64,
+    pub sha256: String, // Lowercase hex
+    pub sealed_ms: u64,
+}
+
+/// Checksums of the finished files of a session directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChecksumManifest {
+    pub version: u32,
+    pub algorithm: String,
+    pub files: Vec<ChecksumEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<ManifestSignature>,
+}
+
+/// Signature of a checksum manifest by the installation that sealed it, so a
+/// manifest rewritten to match edited files no longer verifies
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestSignature {
+    pub key_id: String,      // Start of the SHA-256 of the key; names it without revealing it
+    pub hmac_sha256: String, // Lowercase hex, over the version, algorithm and file entries
+}
+
+impl Default for ChecksumManifest {
+    fn default() -> Self {
+        Self {
+            version: CHECKSUM_VERSION,
+            algorithm: "sha256".to_string(),
+            files: Vec::new(),
+            signature: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileStatus {
+    Ok,
+    Missing,
+    Truncated, // Shorter than when sealed
+    Altered,   // Same length or longer, but different content
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureStatus {
+    Valid,
+    Unsigned,          // Sealed without a signing key or by a version before signing
+    OtherInstallation, // Signed with a key this installation doesn't hold
+    Invalid,           // Changed after it was signed
+}
+
+/// Result of checking one sealed file
+#[derive(Debug, Clone, Serialize)]
+pub struct FileCheck {
+    pub file: String,
+    pub status: FileStatus,
+    pub expected_size: u64,
+    pub actual_size: Option<u64>,
+}
+
+/// Result of `verify_recording`
+#[derive(Debug, Clone, Serialize)]
+pub struct VerificationReport {
+    pub directory: String,
+    pub verified: bool,             // Every sealed file is unchanged and the manifest is signed by this installation
+    pub signature: SignatureStatus, // Of the checksum manifest
+    pub files: Vec<FileCheck>,      // One check per sealed file
+    pub unsealed: Vec<String>, // Files without a checksum: an interrupted segment or files added later
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok()).collect()
+}
+
+fn key_from_hex(text: &str) -> Option<SigningKey> {
+    from_hex(text)?.try_into().ok()
+}
+
+/// Reads the signing key at `path`, creating a random one readable only by the user if there is none
+fn read_or_create_key(path: &Path) -> Result<SigningKey, String> {
+    match fs::read_to_string(path) {
+        Ok(text) => key_from_hex(text.trim()).ok_or_else(|| format!("Invalid signing key {}", path.display())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+            }
+            let key: SigningKey = rand::random();
+            let mut options = fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            options
+                .open(path)
+                .and_then(|mut file| file.write_all(to_hex(&key).as_bytes()))
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+            println!("[Integrity] Created signing key {}", path.display());
+            Ok(key)
+        }
+        Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+    }
+}
+
+/// Loads the key this installation signs checksum manifests with from the app
+/// data directory, creating it on first start. Without it manifests are left unsigned.
+pub fn load_signing_key(app_handle: &AppHandle) {
+    let result = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to resolve data directory: {}", e))
+        .and_then(|directory| read_or_create_key(&directory.join(KEY_FILE)));
+    match result {
+        Ok(key) => *SIGNING_KEY.lock().unwrap() = Some(key),
+        Err(e) => eprintln!("[Integrity] {}; checksum manifests will not be signed", e),
+    }
+}
+
+fn signing_key() -> Option<SigningKey> {
+    *SIGNING_KEY.lock().unwrap()
+}
+
+fn key_id(key: &SigningKey) -> String {
+    to_hex(&Sha256::digest(key)[..8])
+}
+
+/// HMAC-SHA256 of everything in the manifest but the signature itself
+fn manifest_mac(manifest: &ChecksumManifest, key: &SigningKey) -> Hmac<Sha256> {
+    let signed = serde_json::to_vec(&(manifest.version, &manifest.algorithm, &manifest.files)).unwrap_or_default();
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(&signed);
+    mac
+}
+
+fn manifest_hmac(manifest: &ChecksumManifest, key: &SigningKey) -> String {
+    to_hex(&manifest_mac(manifest, key).finalize().into_bytes())
+}
+
+fn signature_status(manifest: &ChecksumManifest, key: Option<&SigningKey>) -> SignatureStatus {
+    let Some(signature) = &manifest.signature else {
+        return SignatureStatus::Unsigned;
+    };
+    match key {
+        Some(key) if signature.key_id == key_id(key) => {
+            // Verified in constant time on the decoded tag
+            let valid = from_hex(&signature.hmac_sha256)
+                .map(|tag| manifest_mac(manifest, key).verify_slice(&tag).is_ok())
+                .unwrap_or(false);
+            if valid {
+                SignatureStatus::Valid
+            } else {
+                SignatureStatus::Invalid
+            }
+        }
+        _ => SignatureStatus::OtherInstallation,
+    }
+}
+
+/// Size and lowercase hex SHA-256 of a file
+fn hash_file(path: &Path) -> io::Result<(u64, String)> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 256 * 1024];
+    let mut size = 0;
+    loop {
+        let read = match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        hasher.update(&buffer[..read]);
+        size += read as u64;
+    }
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
+
+fn read_manifest(path: &Path) -> Result<ChecksumManifest, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    serde_json::from_str(&text).map_err(|e| format!("Invalid checksum manifest {}: {}", path.display(), e))
+}
+
+/// Hashes `files` of session directory `directory` and records them in its
+/// checksum manifest, replacing earlier entries of the same files. The manifest
+/// is signed with `key` unless it held entries that don't verify against it,
+/// which are not vouched for after the fact.
+fn seal(directory: &Path, files: &[PathBuf], key: Option<&SigningKey>) {
+    if !session::is_session_directory(directory) {
+        eprintln!("[Integrity] {} is not a session directory; not sealing", directory.display());
+        return;
+    }
+    let mut entries = Vec::new();
+    for path in files {
+        match hash_file(path) {
+            Ok((size_bytes, sha256)) => entries.push(ChecksumEntry {
+                file: path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+                size_bytes,
+                sha256,
+                sealed_ms: now_ms(),
+            }),
+            Err(e) => eprintln!("[Integrity] Failed to hash {}: {}", path.display(), e),
+        }
+    }
+    if entries.is_empty() {
+        return;
+    }
+
+    let _guard = MANIFEST_LOCK.lock().unwrap();
+    let path = directory.join(CHECKSUM_FILE);
+    let mut manifest = if path.exists() {
+        read_manifest(&path).unwrap_or_else(|e| {
+            eprintln!("[Integrity] {}; starting a new manifest", e);
+            ChecksumManifest::default()
+        })
+    } else {
+        ChecksumManifest::default()
+    };
+    let trusted = manifest.files.is_empty() || signature_status(&manifest, key) == SignatureStatus::Valid;
+    for entry in &entries {
+        manifest.files.retain(|existing| existing.file != entry.file);
+        println!("[Integrity] Sealed {} ({} bytes)", entry.file, entry.size_bytes);
+    }
+    manifest.files.extend(entries);
+    manifest.version = CHECKSUM_VERSION;
+    match key {
+        Some(key) if trusted => {
+            manifest.signature = Some(ManifestSignature {
+                key_id: key_id(key),
+                hmac_sha256: manifest_hmac(&manifest, key),
+            })
+        }
+        Some(_) => eprintln!("[Integrity] {} is not signed by this installation; not signing it", path.display()),
+        None => {}
+    }
+    let result = serde_json::to_string_pretty(&manifest)
+        .map_err(|e| e.to_string())
+        .and_then(|json| {
+            // Replace the manifest in one step so it is never left half written
+            let temporary = path.with_extension("json.tmp");
+            fs::write(&temporary, json)
+                .and_then(|_| fs::rename(&temporary, &path))
+                .map_err(|e| e.to_string())
+        });
+    if let Err(e) = result {
+        eprintln!("[Integrity] Error writing {}: {}", path.display(), e);
+    }
+}
+
+/// Seals a finished segment: the signal file and the files that share its name
+/// (BrainVision data and markers, the annotation sidecar). Runs on a thread of
+/// its own unless `wait` is set, so rotation doesn't hold up the next segment.
+pub fn seal_segment(directory: &Path, filename: &str, wait: bool) {
+    let files: Vec<PathBuf> = recording_reader::recording_files(&directory.join(filename))
+        .into_iter()
+        .map(|(path, _)| path)
+        .filter(|path| !path.extension().map(|e| e == "tmp").unwrap_or(false))
+        .collect();
+    let directory = directory.to_path_buf();
+    let key = signing_key();
+    if wait {
+        seal(&directory, &files, key.as_ref());
+    } else {
+        thread::spawn(move || seal(&directory, &files, key.as_ref()));
+    }
+}
+
+/// Seals a stopped video once the video plugin has finished writing it. Videos
+/// recorded outside a session directory are left unsealed.
+pub fn seal_video(path: PathBuf) {
+    let Some(directory) = path.parent().map(Path::to_path_buf) else {
+        return;
+    };
+    if !session::is_session_directory(&directory) {
+        println!("[Integrity] Video {} was not recorded into a session; not sealing", path.display());
+        return;
+    }
+    let key = signing_key();
+    thread::spawn(move || {
+        let mut waited = Duration::ZERO;
+        let mut size = fs::metadata(&path).map(|m| m.len()).ok();
+        while waited < VIDEO_SETTLE_LIMIT {
+            thread::sleep(VIDEO_SETTLE);
+            waited += VIDEO_SETTLE;
+            let current = fs::metadata(&path).map(|m| m.len()).ok();
+            if current.is_some() && current == size {
+                break;
+            }
+            size = current;
+        }
+        if size.is_none() {
+            eprintln!("[Integrity] Video {} was not written", path.display());
+            return;
+        }
+        seal(&directory, &[path], key.as_ref());
+    });
+}
+
+/// Checks the sealed files of session `id` in `directory` against their checksums
+/// and the checksum manifest against the signing key of this installation.
+/// The session manifest is not sealed, since its details can be edited afterwards.
+pub fn verify_recording(directory: &Path, id: &str) -> Result<VerificationReport, String> {
+    let path = session::session_directory(directory, id)?;
+    if !path.join(CHECKSUM_FILE).is_file() {
+        return Err(format!("Session {} has no checksum manifest", id));
+    }
+    let report = verify_directory(&path, signing_key().as_ref())?;
+    println!(
+        "[Integrity] Verified session {}: {} files, {} not intact, {} unsealed, signature {:?}",
+        id,
+        report.files.len(),
+        report.files.iter().filter(|check| check.status != FileStatus::Ok).count(),
+        report.unsealed.len(),
+        report.signature
+    );
+    Ok(report)
+}
+
+fn verify_directory(path: &Path, key: Option<&SigningKey>) -> Result<VerificationReport, String> {
+    let manifest_path = path.join(CHECKSUM_FILE);
+    let manifest = read_manifest(&manifest_path)?;
+    if manifest.algorithm != "sha256" {
+        return Err(format!("Unsupported checksum algorithm {}", manifest.algorithm));
+    }
+
+    let mut files = Vec::with_capacity(manifest.files.len());
+    for entry in &manifest.files {
+        let file = path.join(&entry.file);
+        let (status, actual_size) = match fs::metadata(&file) {
+            Err(_) => (FileStatus::Missing, None),
+            Ok(metadata) if metadata.len() < entry.size_bytes => (FileStatus::Truncated, Some(metadata.len())),
+            Ok(metadata) => {
+                let (size, sha256) = hash_file(&file).map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+                let status = if size == entry.size_bytes && sha256 == entry.sha256 {
+                    FileStatus::Ok
+                } else {
+                    FileStatus::Altered
+                };
+                (status, Some(metadata.len()))
+            }
+        };
+        files.push(FileCheck {
+            file: entry.file.clone(),
+            status,
+            expected_size: entry.size_bytes,
+            actual_size,
+        });
+    }
+
+    let mut unsealed: Vec<String> = fs::read_dir(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+        .flatten()
+        .filter(|entry| entry.metadata().map(|m| m.is_file()).unwrap_or(false))
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| name != session::MANIFEST_FILE && name != CHECKSUM_FILE && !name.ends_with(".tmp"))
+        .filter(|name| !manifest.files.iter().any(|entry| &entry.file == name))
+        .collect();
+    unsealed.sort();
+
+    let signature = signature_status(&manifest, key);
+    let verified = signature == SignatureStatus::Valid && files.iter().all(|check| check.status == FileStatus::Ok);
+    Ok(VerificationReport {
+        directory: path.to_string_lossy().into_owned(),
+        verified,
+        signature,
+        files,
+        unsealed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_dir;
+
+    const KEY: SigningKey = [7; 32];
+
+    /// A session directory holding `files`, each with its name as content
+    fn session_with(files: &[&str]) -> (PathBuf, Vec<PathBuf>) {
+        let directory = temp_dir("integrity").join("session_20240101-000000-0001");
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join(session::MANIFEST_FILE), "{}").unwrap();
+        let paths = files
+            .iter()
+            .map(|name| {
+                let path = directory.join(name);
+                fs::write(&path, name.repeat(100)).unwrap();
+                path
+            })
+            .collect();
+        (directory, paths)
+    }
+
+    fn status_of(report: &VerificationReport, file: &str) -> FileStatus {
+        report.files.iter().find(|check| check.file == file).unwrap().status
+    }
+
+    #[test]
+    fn hmac_matches_rfc_4231() {
+        let mac = || {
+            let mut mac = Hmac::<Sha256>::new_from_slice(b"Jefe").unwrap();
+            mac.update(b"what do ya want for nothing?");
+            mac
+        };
+        let tag = "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843";
+        assert_eq!(to_hex(&mac().finalize().into_bytes()), tag);
+        assert!(mac().verify_slice(&from_hex(tag).unwrap()).is_ok());
+        assert!(mac().verify_slice(&[0u8; 32]).is_err());
+        assert_eq!(key_from_hex(&to_hex(&KEY)), Some(KEY));
+        assert_eq!(key_from_hex("not a key"), None);
+    }
+
+    #[test]
+    fn sealed_files_verify_until_they_change() {
+        let (directory, paths) = session_with(&["a.csv", "b.csv", "c.csv"]);
+        seal(&directory, &paths, Some(&KEY));
+        let report = verify_directory(&directory, Some(&KEY)).unwrap();
+        assert!(report.verified);
+        assert_eq!(report.signature, SignatureStatus::Valid);
+        assert_eq!(report.files.len(), 3);
+        assert!(report.unsealed.is_empty());
+
+        fs::write(&paths[0], "a.csv".repeat(101)).unwrap();
+        fs::write(&paths[1], "b.csv").unwrap();
+        fs::remove_file(&paths[2]).unwrap();
+        fs::write(directory.join("d.csv"), "d").unwrap();
+        let report = verify_directory(&directory, Some(&KEY)).unwrap();
+        assert!(!report.verified);
+        assert_eq!(report.signature, SignatureStatus::Valid);
+        assert_eq!(status_of(&report, "a.csv"), FileStatus::Altered);
+        assert_eq!(status_of(&report, "b.csv"), FileStatus::Truncated);
+        assert_eq!(status_of(&report, "c.csv"), FileStatus::Missing);
+        assert_eq!(report.unsealed, vec!["d.csv".to_string()]);
+    }
+
+    #[test]
+    fn manifests_rewritten_to_match_edits_fail_the_signature() {
+        let (directory, paths) = session_with(&["a.csv"]);
+        seal(&directory, &paths, Some(&KEY));
+        fs::write(&paths[0], "edited").unwrap();
+        let manifest_path = directory.join(CHECKSUM_FILE);
+        let mut manifest = read_manifest(&manifest_path).unwrap();
+        let (size_bytes, sha256) = hash_file(&paths[0]).unwrap();
+        manifest.files[0].size_bytes = size_bytes;
+        manifest.files[0].sha256 = sha256;
+        fs::write(&manifest_path, serde_json::to_string(&manifest).unwrap()).unwrap();
+
+        let report = verify_directory(&directory, Some(&KEY)).unwrap();
+        assert_eq!(status_of(&report, "a.csv"), FileStatus::Ok);
+        assert_eq!(report.signature, SignatureStatus::Invalid);
+        assert!(!report.verified);
+
+        // Sealing another file doesn't sign over the edit
+        let other = directory.join("b.csv");
+        fs::write(&other, "b").unwrap();
+        seal(&directory, &[other], Some(&KEY));
+        let report = verify_directory(&directory, Some(&KEY)).unwrap();
+        assert_eq!(report.files.len(), 2);
+        assert_eq!(report.signature, SignatureStatus::Invalid);
+        assert!(!report.verified);
+    }
+
+    #[test]
+    fn only_this_installation_verifies_its_signatures() {
+        let (directory, paths) = session_with(&["a.csv"]);
+        seal(&directory, &paths, Some(&KEY));
+        let report = verify_directory(&directory, Some(&[8; 32])).unwrap();
+        assert_eq!(report.signature, SignatureStatus::OtherInstallation);
+        assert!(!report.verified);
+        assert_eq!(verify_directory(&directory, None).unwrap().signature, SignatureStatus::OtherInstallation);
+
+        let (directory, paths) = session_with(&["a.csv"]);
+        seal(&directory, &paths, None);
+        let report = verify_directory(&directory, Some(&KEY)).unwrap();
+        assert_eq!(report.signature, SignatureStatus::Unsigned);
+        assert_eq!(status_of(&report, "a.csv"), FileStatus::Ok);
+        assert!(!report.verified);
+    }
+
+    #[test]
+    fn only_session_directories_are_sealed() {
+        let directory = temp_dir("integrity-outside");
+        let path = directory.join("video.mp4");
+        fs::write(&path, "video").unwrap();
+        seal(&directory, std::slice::from_ref(&path), Some(&KEY));
+        assert!(!directory.join(CHECKSUM_FILE).exists());
+
+        // Named like a session but without a session manifest
+        let named = directory.join("session_20240101-000000-0001");
+        fs::create_dir_all(&named).unwrap();
+        let path = named.join("video.mp4");
+        fs::write(&path, "video").unwrap();
+        seal(&named, &[path], Some(&KEY));
+        assert!(!named.join(CHECKSUM_FILE).exists());
+    }
+
+    #[test]
+    fn signing_keys_are_created_once() {
+        let path = temp_dir("integrity-key").join("data").join(KEY_FILE);
+        let key = read_or_create_key(&path).unwrap();
+        assert_eq!(read_or_create_key(&path).unwrap(), key);
+        fs::write(&path, "garbage").unwrap();
+        assert!(read_or_create_key(&path).is_err());
+    }
+}
@@ -0,0 +1,429 @@
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::compression::{self, CompressedFile};
+use crate::recording::Annotation;
+use crate::recording_reader::{Contents, Sample};
+use crate::writer::{RecordingInfo, RecordingWriter, Row};
+
+/// Version written in the header line of JSON Lines recordings
+pub const VERSION: u32 = 1;
+
+fn timestamp_ms(timestamp: &SystemTime) -> u64 {
+    timestamp
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_else(|_| Duration::from_secs(0))
+        .as_millis() as u64
+}
+
+/// One JSON object per line: a `{"type":"header",...}` line describing the
+/// channels, then `{"timestamp","sample_index","values"}` samples and `{"type":"annotation",...}`
+/// lines. Every batch is flushed, so a crash costs at most the last line.
+pub struct JsonlWriter {
+    file: BufWriter<CompressedFile>,
+}
+
+impl JsonlWriter {
+    pub fn new(file: CompressedFile, info: &RecordingInfo) -> Result<Self, String> {
+        let mut file = BufWriter::new(file);
+        let header = json!({
+            "type": "header",
+            "version": VERSION,
+            "channels": info.labels,
+            "sample_rate": info.sample_rate,
+            "device": info.device,
+            "created_ms": timestamp_ms(&SystemTime::now()),
+        });
+        writeln!(file, "{}", header)
+            .and_then(|_| file.flush())
+            .map_err(|e| format!("Failed to write JSON Lines header: {}", e))?;
+        Ok(Self { file })
+    }
+}
+
+impl RecordingWriter for JsonlWriter {
+    fn write_samples(&mut self, rows: &[Row]) -> io::Result<()> {
+        for (index, timestamp, values) in rows {
+            // NaN has no JSON form and is written as null
+            let line = json!({ "timestamp": timestamp_ms(timestamp), "sample_index": index, "values": values });
+            writeln!(self.file, "{}", line)?;
+        }
+        self.file.flush()
+    }
+
+    fn write_annotations(&mut self, annotations: &[Annotation]) -> io::Result<bool> {
+        for annotation in annotations {
+            let line = json!({
+                "type": "annotation",
+                "onset_ms": annotation.onset_ms,
+                "duration_ms": annotation.duration_ms,
+                "channel": annotation.channel,
+                "label": annotation.label,
+            });
+            writeln!(self.file, "{}", line)?;
+        }
+        self.file.flush()?;
+        Ok(true)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.file.get_mut().finish()
+    }
+}
+
+/// Reads a JSON Lines recording, or what was appended to one, into `contents`
+/// and returns the bytes read. A last line without its line break is still
+/// being written, or was torn by a crash, and is left unread.
+pub fn read_jsonl(bytes: &[u8], contents: &mut Contents) -> Result<usize, String> {
+    let complete = bytes.iter().rposition(|&b| b == b'\n').map(|p| p + 1).unwrap_or(0);
+    let text = String::from_utf8_lossy(&bytes[..complete]);
+    for (number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: Value =
+            serde_json::from_str(line).map_err(|e| format!("Invalid JSON on line {}: {}", number + 1, e))?;
+        match entry["type"].as_str() {
+            Some("header") => {
+                contents.labels = entry["channels"]
+                    .as_array()
+                    .map(|c| c.iter().filter_map(|l| l.as_str().map(str::to_string)).collect())
+                    .unwrap_or_default();
+                contents.source.sample_rate = entry["sample_rate"].as_f64().filter(|rate| *rate > 0.0);
+                contents.source.device = entry["device"].as_str().filter(|d| !d.is_empty()).map(str::to_string);
+            }
+            Some("annotation") => contents.annotations.push(Annotation {
+                onset_ms: entry["onset_ms"].as_u64().unwrap_or(0),
+                duration_ms: entry["duration_ms"].as_f64().unwrap_or(0.0),
+                channel: entry["channel"].as_u64().map(|c| c as usize),
+                label: entry["label"].as_str().unwrap_or_default().to_string(),
+            }),
+            _ => contents
+                .samples
+                .push(sample_from(&entry).ok_or_else(|| format!("Invalid sample on line {}", number + 1))?),
+        }
+    }
+    Ok(complete)
+}
+
+/// `{"timestamp", "sample_index", "values"}` entry shared by the JSON and JSON Lines
+/// formats; recordings from before the session clock have no index
+pub fn sample_from(entry: &Value) -> Option<Sample> {
+    let timestamp = entry["timestamp"].as_u64()?;
+    let values = entry["values"]
+        .as_array()?
+        .iter()
+        .map(|v| v.as_f64().map(|v| v as f32).unwrap_or(f32::NAN))
+        .collect();
+    Some(Sample {
+        time: SystemTime::UNIX_EPOCH + Duration::from_millis(timestamp),
+        index: entry["sample_index"].as_u64(),
+        values,
+    })
+}
+
+/// Result of checking a JSON array recording
+#[derive(Debug, Clone, Serialize)]
+pub struct JsonValidation {
+    pub valid: bool,
+    pub samples: usize,       // Complete, well-formed entries found
+    pub issues: Vec<String>,  // Problems in file order; empty when valid
+}
+
+/// Byte ranges of the well-formed entries of a JSON array recording, plus what is wrong with it
+struct JsonScan {
+    entries: Vec<(usize, usize)>,
+    issues: Vec<String>,
+}
+
+/// Walks a JSON array recording entry by entry so files left behind by a crash
+/// (no closing bracket, torn last entry) or an extra `]` can still be read.
+fn scan_json_array(bytes: &[u8]) -> JsonScan {
+    let mut scan = JsonScan {
+        entries: Vec::new(),
+        issues: Vec::new(),
+    };
+    let skip_whitespace = |mut i: usize| {
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        i
+    };
+
+    let mut i = skip_whitespace(0);
+    if bytes.get(i) == Some(&b'[') {
+        i += 1;
+    } else {
+        scan.issues.push("Missing opening [".to_string());
+    }
+    let mut expect_entry = true;
+    loop {
+        i = skip_whitespace(i);
+        match bytes.get(i) {
+            None => {
+                scan.issues.push("Missing closing ] (recording was not finished)".to_string());
+                break;
+            }
+            Some(b']') => {
+                if expect_entry && !scan.entries.is_empty() {
+                    scan.issues.push(format!("Trailing comma before ] at byte {}", i));
+                }
+                let rest = skip_whitespace(i + 1);
+                if rest < bytes.len() {
+                    scan.issues.push(format!("Unexpected data after closing ] at byte {}", rest));
+                }
+                break;
+            }
+            Some(b',') => {
+                if expect_entry {
+                    scan.issues.push(format!("Empty entry at byte {}", i));
+                }
+                expect_entry = true;
+                i += 1;
+            }
+            Some(b'{') => {
+                if !expect_entry {
+                    scan.issues.push(format!("Missing comma before byte {}", i));
+                }
+                let Some(end) = object_end(bytes, i) else {
+                    scan.issues.push(format!("Truncated entry at byte {}", i));
+                    break;
+                };
+                let well_formed = serde_json::from_slice::<Value>(&bytes[i..end])
+                    .ok()
+                    .and_then(|entry| sample_from(&entry))
+                    .is_some();
+                if well_formed {
+                    scan.entries.push((i, end));
+                } else {
+                    scan.issues.push(format!("Invalid entry at byte {}", i));
+                }
+                expect_entry = false;
+                i = end;
+            }
+            Some(_) => {
+                scan.issues.push(format!("Unexpected data at byte {}", i));
+                break;
+            }
+        }
+    }
+    scan
+}
+
+/// Index just past the object starting at `start`, or None when the input ends inside it
+fn object_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (offset, &byte) in bytes[start..].iter().enumerate() {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(start + offset + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Samples of a JSON array recording, skipping whatever a crash damaged
+pub fn read_json_entries(bytes: &[u8]) -> Vec<Sample> {
+    scan_json_array(bytes)
+        .entries
+        .iter()
+        .filter_map(|&(start, end)| serde_json::from_slice::<Value>(&bytes[start..end]).ok())
+        .filter_map(|entry| sample_from(&entry))
+        .collect()
+}
+
+/// Checks a JSON array recording without changing it.
+pub fn validate_json_recording(path: &Path) -> Result<JsonValidation, String> {
+    let bytes = compression::read_file(path)?;
+    let scan = scan_json_array(&bytes);
+    Ok(JsonValidation {
+        valid: scan.issues.is_empty(),
+        samples: scan.entries.len(),
+        issues: scan.issues,
+    })
+}
+
+/// Writes the well-formed entries of a JSON array recording to a valid file.
+/// Without `output` the result goes to `<name>.repaired.json` next to the input.
+/// Returns the output path.
+pub fn repair_json_recording(input: &Path, output: Option<&Path>) -> Result<PathBuf, String> {
+    let bytes = compression::read_file(input)?;
+    let scan = scan_json_array(&bytes);
+    let output = match output {
+        Some(path) => path.to_path_buf(),
+        None => {
+            let stem = compression::logical_path(input);
+            let stem = stem.file_stem().and_then(|s| s.to_str()).unwrap_or("recording");
+            input.with_file_name(format!("{}.repaired.json", stem))
+        }
+    };
+
+    let mut repaired = Vec::with_capacity(bytes.len() + 1);
+    repaired.push(b'[');
+    for (n, &(start, end)) in scan.entries.iter().enumerate() {
+        if n > 0 {
+            repaired.push(b',');
+        }
+        repaired.extend_from_slice(&bytes[start..end]);
+    }
+    repaired.push(b']');
+
+    // Write beside the target and rename, so repairing in place never loses the original
+    let temporary = output.with_extension("json.tmp");
+    fs::write(&temporary, &repaired)
+        .and_then(|_| fs::rename(&temporary, &output))
+        .map_err(|e| format!("Failed to write {}: {}", output.display(), e))?;
+    println!(
+        "[JSON] Repaired {} ({} samples, {} issues) into {}",
+        input.display(),
+        scan.entries.len(),
+        scan.issues.len(),
+        output.display()
+    );
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{millis, rows, temp_dir};
+    use crate::writer;
+
+    #[test]
+    fn jsonl_round_trips_and_leaves_torn_lines_unread() {
+        let mut rows = rows(100, 2, 250.0);
+        rows[10].2[1] = f32::NAN;
+        let info = RecordingInfo {
+            labels: vec!["C3".to_string(), "C4".to_string()],
+            sample_rate: 250.0,
+            device: "Board \"A\"".to_string(),
+            ..Default::default()
+        };
+        let path = temp_dir("jsonl").join("recording.jsonl");
+        let mut writer = writer::create_writer("jsonl", &path, &info).unwrap();
+        writer.write_samples(&rows).unwrap();
+        let blink = Annotation {
+            onset_ms: millis(rows[20].1),
+            duration_ms: 250.0,
+            channel: Some(1),
+            label: "blink, \"left\"".to_string(),
+        };
+        writer.write_annotations(std::slice::from_ref(&blink)).unwrap();
+        writer.finish().unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        let written = bytes.len();
+        bytes.extend_from_slice(b"{\"timestamp\": 1700000000400,\"sample_index\": 100,\"val");
+
+        let mut contents = Contents::default();
+        assert_eq!(read_jsonl(&bytes, &mut contents).unwrap(), written);
+        assert_eq!(contents.labels, info.labels);
+        assert_eq!(contents.source.sample_rate, Some(250.0));
+        assert_eq!(contents.source.device.as_deref(), Some("Board \"A\""));
+        assert_eq!(contents.samples.len(), rows.len());
+        for (sample, (index, time, values)) in contents.samples.iter().zip(&rows) {
+            assert_eq!(sample.index, Some(*index));
+            assert_eq!(millis(sample.time), millis(*time));
+            for (read, written) in sample.values.iter().zip(values) {
+                assert!(read == written || (read.is_nan() && written.is_nan()));
+            }
+        }
+        let annotation = &contents.annotations[0];
+        assert_eq!(
+            (annotation.onset_ms, annotation.duration_ms, annotation.channel, annotation.label.as_str()),
+            (blink.onset_ms, 250.0, Some(1), blink.label.as_str())
+        );
+    }
+
+    #[test]
+    fn jsonl_rejects_malformed_lines() {
+        let header = "{\"type\":\"header\",\"version\":1,\"channels\":[\"C3\"],\"sample_rate\":0,\"device\":\"\"}\n";
+        let mut contents = Contents::default();
+        let error = read_jsonl(format!("{}{{\"timestamp\": 1,\n", header).as_bytes(), &mut contents).unwrap_err();
+        assert!(error.contains("Invalid JSON on line 2"), "{}", error);
+
+        let mut contents = Contents::default();
+        let error = read_jsonl(format!("{}{{\"values\": [1]}}\n", header).as_bytes(), &mut contents).unwrap_err();
+        assert!(error.contains("Invalid sample on line 2"), "{}", error);
+        assert_eq!(contents.source.sample_rate, None);
+        assert_eq!(contents.source.device, None);
+    }
+
+    #[test]
+    fn repairs_json_recordings_cut_short_by_a_crash() {
+        let rows = rows(50, 2, 250.0);
+        let path = temp_dir("json").join("recording.json");
+        let mut writer = writer::create_writer("json", &path, &RecordingInfo::default()).unwrap();
+        writer.write_samples(&rows).unwrap();
+        writer.finish().unwrap();
+        let bytes = fs::read(&path).unwrap();
+        assert!(validate_json_recording(&path).unwrap().valid);
+
+        // Cut inside the last entry, as a crash leaves the file
+        fs::write(&path, &bytes[..bytes.len() - 10]).unwrap();
+        let validation = validate_json_recording(&path).unwrap();
+        assert!(!validation.valid);
+        assert_eq!(validation.samples, 49);
+        assert!(validation.issues[0].starts_with("Truncated entry"), "{:?}", validation.issues);
+
+        let repaired = repair_json_recording(&path, None).unwrap();
+        assert_eq!(repaired, path.with_file_name("recording.repaired.json"));
+        assert!(validate_json_recording(&repaired).unwrap().valid);
+        let samples = read_json_entries(&fs::read(&repaired).unwrap());
+        assert_eq!(samples.len(), 49);
+        for (sample, (index, _, values)) in samples.iter().zip(&rows) {
+            assert_eq!((sample.index, &sample.values), (Some(*index), values));
+        }
+    }
+
+    #[test]
+    fn reports_every_kind_of_damage() {
+        let entry = |n: u64| format!("{{\"timestamp\": {},\"sample_index\": {},\"values\": [{}]}}", n, n, n);
+        let cases = [
+            (format!("{}]", entry(1)), "Missing opening ["),
+            (format!("[{},{}]", entry(1), entry(2)), ""),
+            (format!("[{},]", entry(1)), "Trailing comma"),
+            (format!("[{},,{}]", entry(1), entry(2)), "Empty entry"),
+            (format!("[{}{}]", entry(1), entry(2)), "Missing comma"),
+            (format!("[{},{{\"values\": [2]}}]", entry(1)), "Invalid entry"),
+            (format!("[{}]]", entry(1)), "Unexpected data after closing ]"),
+            (format!("[{},x]", entry(1)), "Unexpected data at byte"),
+            (format!("[{}", entry(1)), "Missing closing ]"),
+        ];
+        for (text, issue) in cases {
+            let scan = scan_json_array(text.as_bytes());
+            match issue {
+                "" => assert!(scan.issues.is_empty(), "{}: {:?}", text, scan.issues),
+                _ => assert!(scan.issues.iter().any(|i| i.starts_with(issue)), "{}: {:?}", text, scan.issues),
+            }
+            // Whatever is damaged, the well-formed entries are still read
+            assert!(!read_json_entries(text.as_bytes()).is_empty(), "{}", text);
+        }
+        // Braces inside strings don't end an entry
+        let text = b"[{\"timestamp\": 1,\"values\": [1],\"note\": \"}{\\\"\"}]";
+        assert!(scan_json_array(text).issues.is_empty());
+    }
+}
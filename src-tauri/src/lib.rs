@@ -8,9 +8,52 @@ mod file_utils;
 mod streaming;
 mod recording;
 mod mdns;
+mod spectrum;
+mod montage;
+mod quality;
+mod impedance;
+mod artifact;
+mod expression;
+mod resample;
+mod trigger;
+mod writer;
+mod compression;
+mod binary;
+mod jsonl;
+mod edf;
+mod brainvision;
+mod recording_reader;
+mod xdf;
+mod session;
+mod rotation;
+mod pipeline;
+mod clock;
+mod markers;
+mod sample_output;
+mod convert;
+mod integrity;
+mod storage;
+#[cfg(test)]
+mod test_utils;
 use commands::{
-    connect_serial, connect_socket, discover_streaming_devices, get_available_ports, get_app_state,
-    set_default_stream_url, get_recording_filename, push_video_frame, 
+    configure_spectrum, connect_serial, connect_socket, discover_streaming_devices, get_available_ports,
+    get_app_state, get_band_power, get_spectrum_config, set_default_stream_url, set_sample_rate,
+    list_montages, load_montage, save_montage, delete_montage, set_active_montage,
+    set_quality_thresholds, get_quality_thresholds, start_impedance, stop_impedance,
+    get_impedance_config, configure_artifact_detection, get_artifact_config, set_derived_channels,
+    get_derived_channels, configure_resampling, get_resampling_config, configure_recording_trigger,
+    get_recording_trigger, disarm_recording_trigger, send_marker, set_edf_options,
+    get_edf_options, export_recording_edf, set_brainvision_options, get_brainvision_options,
+    set_binary_options, get_binary_options, read_binary_header, validate_json_recording,
+    repair_json_recording, set_recording_compression, get_recording_compression,
+    update_session_metadata, get_session_metadata, get_session_details,
+    list_sessions, inspect_session, delete_session, set_rotation_options, get_rotation_options,
+    get_recording_pipeline_stats, pause_recording, resume_recording, add_marker, get_markers,
+    set_marker_outputs, get_marker_outputs, set_sample_outputs, get_sample_outputs, get_recording_metadata,
+    read_recording_window,
+    convert_recording, cancel_conversion, verify_recording, set_storage_options, get_storage_options,
+    get_storage_status, apply_retention,
+    get_recording_filename, push_video_frame, 
     record_video_stream, send_serial, start_fake_data, start_recording, start_stream_recording, start_streaming, 
     start_video_recording, stop_data_acquisition, stop_recording, stop_stream_recording, stop_streaming, 
     stop_video_recording, toggle_fake_data, toggle_fake_signal,
@@ -34,6 +77,7 @@ pub fn run() {
             let app_handle = app.handle();
             // Store in communication state for event emission
             *app_state.communication.app_handle.lock().unwrap() = Some(app_handle.clone());
+            integrity::load_signing_key(app_handle);
             Ok(())
         })
         // Removed automatic frame stream on startup; streaming controlled via commands
@@ -60,8 +104,76 @@ pub fn run() {
             start_recording,
             stop_recording,
             toggle_fake_data,
-            toggle_fake_signal
+            toggle_fake_signal,
+            set_sample_rate,
+            configure_spectrum,
+            get_spectrum_config,
+            get_band_power,
+            list_montages,
+            load_montage,
+            save_montage,
+            delete_montage,
+            set_active_montage,
+            set_quality_thresholds,
+            get_quality_thresholds,
+            start_impedance,
+            stop_impedance,
+            get_impedance_config,
+            configure_artifact_detection,
+            get_artifact_config,
+            set_derived_channels,
+            get_derived_channels,
+            configure_resampling,
+            get_resampling_config,
+            configure_recording_trigger,
+            get_recording_trigger,
+            disarm_recording_trigger,
+            send_marker,
+            set_edf_options,
+            get_edf_options,
+            export_recording_edf,
+            set_brainvision_options,
+            get_brainvision_options,
+            set_binary_options,
+            get_binary_options,
+            read_binary_header,
+            validate_json_recording,
+            repair_json_recording,
+            set_recording_compression,
+            get_recording_compression,
+            update_session_metadata,
+            get_session_metadata,
+            get_session_details,
+            list_sessions,
+            inspect_session,
+            delete_session,
+            set_rotation_options,
+            get_rotation_options,
+            get_recording_pipeline_stats,
+            pause_recording,
+            resume_recording,
+            add_marker,
+            get_markers,
+            set_marker_outputs,
+            get_marker_outputs,
+            set_sample_outputs,
+            get_sample_outputs,
+            get_recording_metadata,
+            read_recording_window,
+            convert_recording,
+            cancel_conversion,
+            verify_recording,
+            set_storage_options,
+            get_storage_options,
+            get_storage_status,
+            apply_retention
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+/// Runs the `convert` command-line subcommand with the arguments after it;
+/// returns the process exit code
+pub fn convert_cli(args: &[String]) -> i32 {
+    convert::run_cli(args)
+}
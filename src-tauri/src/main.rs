@@ -1,7 +1,30 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(|arg| arg == "convert").unwrap_or(false) {
+        attach_parent_console();
+        std::process::exit(serial_brain_rs_lib::convert_cli(&args[2..]));
+    }
     let platform = tauri_plugin_os::platform();
     println!("Platform: {}", platform);
     serial_brain_rs_lib::run();
 }
+
+/// Release builds on Windows start without a console, so the command line
+/// tools write to the console of the shell they were started from
+#[cfg(windows)]
+fn attach_parent_console() {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    // Fails when there is no parent console, e.g. when started from Explorer
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(windows))]
+fn attach_parent_console() {}
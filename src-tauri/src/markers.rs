@@ -0,0 +1,160 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::recording::Annotation;
+use crate::sample_output::{self, UdpTargets};
+use crate::state::AppState;
+use crate::trigger;
+
+/// Markers kept for `get_markers`
+const MAX_RECENT: usize = 1000;
+
+/// Marker as requested by the UI or an external source
+#[derive(Debug, Clone, Deserialize)]
+pub struct MarkerRequest {
+    pub label: String,
+    #[serde(default)]
+    pub duration_ms: f64,
+    #[serde(default)]
+    pub channel: Option<usize>,
+    #[serde(default)]
+    pub source: Option<String>, // Who placed the marker, e.g. "ui" or a stimulus program
+}
+
+/// A marker stamped on the acquisition clock
+#[derive(Debug, Clone, Serialize)]
+pub struct Marker {
+    pub id: u64,
+    pub label: String,
+    pub duration_ms: f64,
+    pub channel: Option<usize>,
+    pub source: String,
+    pub onset_ms: u64,               // Nominal time of `sample_index`, milliseconds since the Unix epoch
+    pub sample_index: Option<u64>,   // Session sample acquired when the marker arrived; None without data
+    pub session_time_s: Option<f64>, // `sample_index` divided by the acquisition rate
+}
+
+impl From<&Marker> for Annotation {
+    fn from(marker: &Marker) -> Self {
+        Self {
+            onset_ms: marker.onset_ms,
+            duration_ms: marker.duration_ms,
+            channel: marker.channel,
+            label: format!("marker:{}", marker.label),
+        }
+    }
+}
+
+/// Marker bus: stamps markers, keeps the recent ones and sends each one as a
+/// JSON datagram to the configured UDP outputs
+pub struct MarkerBus {
+    next_id: AtomicU64,
+    recent: Mutex<VecDeque<Marker>>,
+    outputs: Mutex<Vec<String>>,       // "host:port" targets as configured
+    socket: Mutex<Option<UdpTargets>>, // Bound socket and resolved targets
+}
+
+impl MarkerBus {
+    pub fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            recent: Mutex::new(VecDeque::new()),
+            outputs: Mutex::new(Vec::new()),
+            socket: Mutex::new(None),
+        }
+    }
+
+    /// Replaces the UDP outputs; every target must resolve
+    pub fn set_outputs(&self, targets: Vec<String>) -> Result<(), String> {
+        let socket = sample_output::open_udp_targets(&targets, "marker")?;
+        *self.socket.lock().unwrap() = socket;
+        *self.outputs.lock().unwrap() = targets;
+        Ok(())
+    }
+
+    pub fn outputs(&self) -> Vec<String> {
+        self.outputs.lock().unwrap().clone()
+    }
+
+    /// Markers placed so far, oldest first; only those after `after_id` when given
+    pub fn recent(&self, after_id: Option<u64>) -> Vec<Marker> {
+        self.recent
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|marker| after_id.map(|id| marker.id > id).unwrap_or(true))
+            .cloned()
+            .collect()
+    }
+
+    fn send(&self, marker: &Marker) {
+        let socket = self.socket.lock().unwrap();
+        let Some((socket, targets)) = socket.as_ref() else {
+            return;
+        };
+        let Ok(payload) = serde_json::to_vec(marker) else {
+            return;
+        };
+        for target in targets {
+            if let Err(e) = socket.send_to(&payload, target) {
+                eprintln!("[Markers] Failed to send marker to {}: {}", target, e);
+            }
+        }
+    }
+}
+
+/// Places a marker: stamps it against the acquisition clock, annotates the
+/// active recording (in-band where the format has an annotation channel, in the
+/// sidecar otherwise), fires an armed marker trigger and publishes it to the UI
+/// (`marker` event) and the network outputs.
+pub fn add_marker(app_handle: &AppHandle, request: MarkerRequest) -> Result<Marker, String> {
+    let label = request.label.trim().to_string();
+    if label.is_empty() {
+        return Err("Marker label must not be empty".to_string());
+    }
+    if !request.duration_ms.is_finite() || request.duration_ms < 0.0 {
+        return Err("Marker duration must not be negative".to_string());
+    }
+
+    let state = app_handle.state::<Arc<AppState>>();
+    let channels = state.recording.channel_labels().len();
+    if request.channel.map(|c| c >= channels).unwrap_or(false) {
+        return Err(format!("Marker channel must be between 0 and {}", channels.saturating_sub(1)));
+    }
+    let (sample_index, onset, session_time_s) = {
+        let clock = state.recording.clock.lock().unwrap();
+        match clock.now() {
+            Some((index, time)) => (Some(index), time, Some(index as f64 / clock.rate())),
+            None => (None, SystemTime::now(), None),
+        }
+    };
+    let marker = Marker {
+        id: state.markers.next_id.fetch_add(1, Ordering::SeqCst),
+        label,
+        duration_ms: request.duration_ms,
+        channel: request.channel,
+        source: request.source.unwrap_or_else(|| "ui".to_string()),
+        onset_ms: onset.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_millis() as u64,
+        sample_index,
+        session_time_s,
+    };
+
+    trigger::check_marker(app_handle, &marker.label);
+    state.recording.add_annotation(Annotation::from(&marker));
+    {
+        let mut recent = state.markers.recent.lock().unwrap();
+        if recent.len() == MAX_RECENT {
+            recent.pop_front();
+        }
+        recent.push_back(marker.clone());
+    }
+    state.markers.send(&marker);
+    let _ = app_handle.emit("marker", &marker);
+    println!("[Markers] {} from {} at sample {:?}", marker.label, marker.source, marker.sample_index);
+    Ok(marker)
+}
@@ -0,0 +1,235 @@
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::types::ChannelData;
+
+/// Number of raw channels delivered by the device
+pub const RAW_CHANNELS: usize = 8;
+
+/// Default label of a raw channel, matching the historical CSV header
+pub fn default_label(index: usize) -> String {
+    format!("channel_{}", index)
+}
+
+pub fn default_labels() -> Vec<String> {
+    (0..RAW_CHANNELS).map(default_label).collect()
+}
+
+/// Re-reference applied to every raw channel of a montage
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Reference {
+    #[default]
+    None,
+    /// Subtract the mean of all channels not listed in `exclude`
+    CommonAverage {
+        #[serde(default)]
+        exclude: Vec<String>,
+    },
+    /// Subtract the mean of the two mastoid electrodes
+    LinkedMastoids { left: String, right: String },
+}
+
+/// Extra channel derived from the raw (unreferenced) channels
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DerivedChannel {
+    /// `anode - cathode`
+    Bipolar {
+        label: String,
+        anode: String,
+        cathode: String,
+    },
+    /// Arbitrary weighted sum of raw channels
+    Linear {
+        label: String,
+        terms: Vec<LinearTerm>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinearTerm {
+    pub channel: String,
+    pub weight: f32,
+}
+
+/// User-defined montage as persisted in a montage file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Montage {
+    pub name: String,
+    #[serde(default = "default_labels")]
+    pub labels: Vec<String>, // Label of each raw channel, by device index
+    #[serde(default)]
+    pub reference: Reference,
+    #[serde(default = "default_true")]
+    pub include_raw: bool, // Output the (re-referenced) raw channels before the derived ones
+    #[serde(default)]
+    pub derived: Vec<DerivedChannel>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Montage {
+    fn index_of(&self, label: &str) -> Result<usize, String> {
+        self.labels
+            .iter()
+            .position(|l| l == label)
+            .ok_or_else(|| format!("Unknown channel '{}' in montage '{}'", label, self.name))
+    }
+
+    /// Resolve labels and reduce the montage to a weight matrix
+    pub fn compile(&self) -> Result<CompiledMontage, String> {
+        if self.labels.len() != RAW_CHANNELS {
+            return Err(format!(
+                "Montage '{}' must label exactly {} channels",
+                self.name, RAW_CHANNELS
+            ));
+        }
+
+        // Weights subtracted from every raw channel by the reference
+        let mut reference = [0f32; RAW_CHANNELS];
+        match &self.reference {
+            Reference::None => {}
+            Reference::CommonAverage { exclude } => {
+                let excluded = exclude
+                    .iter()
+                    .map(|l| self.index_of(l))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let included: Vec<usize> =
+                    (0..RAW_CHANNELS).filter(|i| !excluded.contains(i)).collect();
+                if included.is_empty() {
+                    return Err("Common average reference excludes every channel".to_string());
+                }
+                for &i in &included {
+                    reference[i] = 1.0 / included.len() as f32;
+                }
+            }
+            Reference::LinkedMastoids { left, right } => {
+                reference[self.index_of(left)?] += 0.5;
+                reference[self.index_of(right)?] += 0.5;
+            }
+        }
+
+        let mut labels = Vec::new();
+        let mut weights = Vec::new();
+        if self.include_raw {
+            for (i, label) in self.labels.iter().enumerate() {
+                let mut row = [0f32; RAW_CHANNELS];
+                row[i] = 1.0;
+                for (w, r) in row.iter_mut().zip(reference.iter()) {
+                    *w -= r;
+                }
+                labels.push(label.clone());
+                weights.push(row);
+            }
+        }
+        for derived in &self.derived {
+            let mut row = [0f32; RAW_CHANNELS];
+            let label = match derived {
+                DerivedChannel::Bipolar { label, anode, cathode } => {
+                    row[self.index_of(anode)?] += 1.0;
+                    row[self.index_of(cathode)?] -= 1.0;
+                    label
+                }
+                DerivedChannel::Linear { label, terms } => {
+                    for term in terms {
+                        row[self.index_of(&term.channel)?] += term.weight;
+                    }
+                    label
+                }
+            };
+            labels.push(label.clone());
+            weights.push(row);
+        }
+
+        if labels.is_empty() {
+            return Err(format!("Montage '{}' produces no channels", self.name));
+        }
+        Ok(CompiledMontage {
+            name: self.name.clone(),
+            labels,
+            weights,
+        })
+    }
+}
+
+/// Montage reduced to one weight row per output channel
+#[derive(Debug, Clone, Serialize)]
+pub struct CompiledMontage {
+    pub name: String,
+    pub labels: Vec<String>,
+    #[serde(skip)]
+    weights: Vec<[f32; RAW_CHANNELS]>,
+}
+
+impl CompiledMontage {
+    pub fn apply(&self, data: &ChannelData) -> Vec<f32> {
+        self.weights
+            .iter()
+            .map(|row| row.iter().zip(data.iter()).map(|(w, v)| w * v).sum())
+            .collect()
+    }
+}
+
+/// Directory holding the persisted montage files
+fn montage_dir(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app_handle
+        .path()
+        .app_config_dir()
+        .map_err(|e| format!("Failed to resolve config directory: {}", e))?
+        .join("montages");
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create montage directory: {}", e))?;
+    Ok(dir)
+}
+
+fn montage_path(app_handle: &AppHandle, name: &str) -> Result<PathBuf, String> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == ' ');
+    if !valid {
+        return Err(format!("Invalid montage name '{}'", name));
+    }
+    Ok(montage_dir(app_handle)?.join(format!("{}.json", name)))
+}
+
+/// Lists the names of all saved montages.
+pub fn list_montages(app_handle: &AppHandle) -> Result<Vec<String>, String> {
+    let entries = fs::read_dir(montage_dir(app_handle)?)
+        .map_err(|e| format!("Failed to read montage directory: {}", e))?;
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().map(|ext| ext == "json").unwrap_or(false))
+        .filter_map(|path| path.file_stem().map(|s| s.to_string_lossy().to_string()))
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// Loads a saved montage by name.
+pub fn load_montage(app_handle: &AppHandle, name: &str) -> Result<Montage, String> {
+    let path = montage_path(app_handle, name)?;
+    let content = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read montage '{}': {}", name, e))?;
+    serde_json::from_str(&content).map_err(|e| format!("Invalid montage file '{}': {}", name, e))
+}
+
+/// Validates and saves a montage, replacing any montage with the same name.
+pub fn save_montage(app_handle: &AppHandle, montage: &Montage) -> Result<(), String> {
+    montage.compile()?;
+    let path = montage_path(app_handle, &montage.name)?;
+    let content = serde_json::to_string_pretty(montage).map_err(|e| e.to_string())?;
+    fs::write(&path, content).map_err(|e| format!("Failed to save montage '{}': {}", montage.name, e))
+}
+
+/// Deletes a saved montage.
+pub fn delete_montage(app_handle: &AppHandle, name: &str) -> Result<(), String> {
+    let path = montage_path(app_handle, name)?;
+    fs::remove_file(&path).map_err(|e| format!("Failed to delete montage '{}': {}", name, e))
+}
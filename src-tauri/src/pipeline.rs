@@ -0,0 +1,575 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TryRecvError, TrySendError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+use serde::Serialize;
+
+use crate::writer::Row;
+
+
+/// Batches held in memory between acquisition and the writer; a batch is usually one row
+const CHANNEL_CAPACITY: usize = 65536;
+
+/// Batches held in memory on their way to the spill thread
+const SPILL_HANDOFF_CAPACITY: usize = 65536;
+
+/// Rows read back from the spill file per batch
+const SPILL_BATCH: usize = 4096;
+
+/// The spill file stops growing past this size; further rows are dropped and counted
+const MAX_SPILL_BYTES: u64 = 1 << 30;
+
+/// Numbers spill files, so two created in the same millisecond get names of their own
+static NEXT_SPILL: AtomicUsize = AtomicUsize::new(0);
+
+/// Counters of the recording pipeline, reset when a recording starts
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PipelineStats {
+    pub queued: u64,        // Rows waiting in memory
+    pub max_queued: u64,    // Highest number of rows waiting in memory
+    pub spilled: u64,       // Rows that went through the spill file
+    pub spill_pending: u64, // Rows routed to the spill file not written yet
+    pub written: u64,       // Rows handed to the format writer
+    pub dropped: u64,       // Rows lost because the spill file could not take them
+}
+
+/// Counters shared by acquisition, the spill thread and the writer
+#[derive(Default)]
+struct Counters {
+    queued: AtomicU64,
+    max_queued: AtomicU64,
+    spilled: AtomicU64,
+    written: AtomicU64,
+    dropped: AtomicU64,
+    handed: AtomicU64,   // Rows handed to the spill thread
+    settled: AtomicU64,  // Rows the spill thread stored or dropped
+    in_file: AtomicU64,  // Rows stored and flushed to the spill file
+    read: AtomicU64,     // Rows read back from the spill file
+    overrun: AtomicBool, // Set when spilling starts or rows are dropped, until reported
+}
+
+impl Counters {
+    fn drop_rows(&self, count: u64, reason: &str) {
+        if self.dropped.fetch_add(count, Ordering::SeqCst) == 0 {
+            eprintln!("[Recording] Dropping rows: {}", reason);
+        }
+        self.overrun.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Message to the spill thread
+enum SpillMessage {
+    Rows(Vec<Row>),
+    Rewind, // Everything spilled has been read back; start the file over
+}
+
+/// Spill file as seen by the spill thread. Each row is the sample index, the timestamp
+/// in nanoseconds since the Unix epoch, the value count and the values, all little-endian.
+struct SpillWriter {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    bytes: u64,
+    limit: u64,
+}
+
+impl SpillWriter {
+    /// Creates the spill file along with a reader for the writer side
+    fn create(limit: u64) -> io::Result<(Self, SpillReader)> {
+        let path = std::env::temp_dir().join(format!(
+            "serial-brain-spill-{}-{}-{}.bin",
+            std::process::id(),
+            SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_millis(),
+            NEXT_SPILL.fetch_add(1, Ordering::SeqCst)
+        ));
+        let file = OpenOptions::new().create(true).truncate(true).write(true).open(&path)?;
+        let reader = SpillReader {
+            path: path.clone(),
+            reader: BufReader::new(File::open(&path)?),
+        };
+        println!("[Recording] Writer is behind, spilling rows to {}", path.display());
+        let writer = Self {
+            path,
+            writer: BufWriter::new(file),
+            bytes: 0,
+            limit,
+        };
+        Ok((writer, reader))
+    }
+
+    fn write(&mut self, rows: &[Row]) -> io::Result<()> {
+        if self.bytes >= self.limit {
+            return Err(io::Error::other("spill file is full"));
+        }
+        for (index, timestamp, values) in rows {
+            let nanos = timestamp.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_nanos() as u64;
+            self.writer.write_all(&index.to_le_bytes())?;
+            self.writer.write_all(&nanos.to_le_bytes())?;
+            self.writer.write_all(&(values.len() as u32).to_le_bytes())?;
+            for value in values {
+                self.writer.write_all(&value.to_le_bytes())?;
+            }
+            self.bytes += 20 + values.len() as u64 * 4;
+        }
+        Ok(())
+    }
+
+    fn rewind(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_mut().set_len(0)?;
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.bytes = 0;
+        Ok(())
+    }
+}
+
+impl Drop for SpillWriter {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Spill file as seen by the writer side
+struct SpillReader {
+    path: PathBuf,
+    reader: BufReader<File>,
+}
+
+fn read_rows(reader: &mut BufReader<File>, count: usize) -> io::Result<Vec<Row>> {
+    let mut rows = Vec::with_capacity(count);
+    for _ in 0..count {
+        let mut head = [0u8; 20];
+        reader.read_exact(&mut head)?;
+        let index = u64::from_le_bytes(head[..8].try_into().unwrap());
+        let nanos = u64::from_le_bytes(head[8..16].try_into().unwrap());
+        let len = u32::from_le_bytes(head[16..].try_into().unwrap()) as usize;
+        let mut bytes = vec![0u8; len * 4];
+        reader.read_exact(&mut bytes)?;
+        let values = bytes.chunks_exact(4).map(|b| f32::from_le_bytes(b.try_into().unwrap())).collect();
+        rows.push((index, SystemTime::UNIX_EPOCH + Duration::from_nanos(nanos), values));
+    }
+    Ok(rows)
+}
+
+/// Writes handed-off rows to the spill file, flushing after every burst so the
+/// writer can read them back. The file is created on the first rows and removed
+/// when the pipeline closes.
+fn run_spill_thread(
+    receiver: Receiver<SpillMessage>,
+    counters: Arc<Counters>,
+    reader: Arc<Mutex<Option<SpillReader>>>,
+    limit: u64,
+) {
+    let mut spill: Option<SpillWriter> = None;
+    while let Ok(message) = receiver.recv() {
+        let mut burst = vec![message];
+        burst.extend(receiver.try_iter().take(SPILL_BATCH));
+
+        let mut stored = 0;
+        let mut settled = 0;
+        for message in burst {
+            let rows = match message {
+                SpillMessage::Rows(rows) => rows,
+                SpillMessage::Rewind => {
+                    if let Some(Err(e)) = spill.as_mut().map(|spill| spill.rewind()) {
+                        eprintln!("[Recording] Failed to rewind spill file: {}", e);
+                    }
+                    continue;
+                }
+            };
+            let count = rows.len() as u64;
+            if spill.is_none() {
+                match SpillWriter::create(limit) {
+                    Ok((created, created_reader)) => {
+                        *reader.lock().unwrap() = Some(created_reader);
+                        spill = Some(created);
+                    }
+                    Err(e) => eprintln!("[Recording] Failed to create spill file: {}", e),
+                }
+            }
+            let result = match spill.as_mut() {
+                Some(spill) => spill.write(&rows).map_err(|e| e.to_string()),
+                None => Err("no spill file".to_string()),
+            };
+            match result {
+                Ok(()) => stored += count,
+                Err(e) => counters.drop_rows(count, &e),
+            }
+            settled += count;
+        }
+        if let Some(Err(e)) = spill.as_mut().map(|spill| spill.writer.flush()) {
+            eprintln!("[Recording] Failed to flush spill file: {}", e);
+        }
+        // Stored rows become readable only once they are flushed
+        counters.spilled.fetch_add(stored, Ordering::SeqCst);
+        counters.in_file.fetch_add(stored, Ordering::SeqCst);
+        counters.settled.fetch_add(settled, Ordering::SeqCst);
+    }
+}
+
+/// Hands rows from acquisition to the recording thread without ever blocking
+/// acquisition on the disk. Rows go through a bounded channel; when the writer
+/// falls behind and the channel fills up, they are handed to a spill thread
+/// that writes them to a temporary file, and read back in order. Rows neither
+/// the channel nor the spill thread can take are dropped, counted and reported
+/// with a `recording_overrun` event.
+pub struct RecordingPipeline {
+    sender: Mutex<Option<SyncSender<Vec<Row>>>>,
+    receiver: Mutex<Option<Receiver<Vec<Row>>>>,
+    spilling: Mutex<bool>, // Set while rows go to the spill file; new rows follow them there to keep the order
+    spill_sender: Mutex<Option<SyncSender<SpillMessage>>>,
+    spill_reader: Arc<Mutex<Option<SpillReader>>>, // Read side of the spill file, once it exists
+    spill_thread: Mutex<Option<JoinHandle<()>>>,
+    spill_limit: u64,
+    counters: Arc<Counters>,
+}
+
+impl RecordingPipeline {
+    pub fn new() -> Self {
+        Self {
+            sender: Mutex::new(None),
+            receiver: Mutex::new(None),
+            spilling: Mutex::new(false),
+            spill_sender: Mutex::new(None),
+            spill_reader: Arc::new(Mutex::new(None)),
+            spill_thread: Mutex::new(None),
+            spill_limit: MAX_SPILL_BYTES,
+            counters: Arc::new(Counters::default()),
+        }
+    }
+
+    /// Starts a fresh pipeline and spill thread for a new recording
+    pub fn open(&self) {
+        self.close();
+        let (sender, receiver) = mpsc::sync_channel(CHANNEL_CAPACITY);
+        *self.sender.lock().unwrap() = Some(sender);
+        *self.receiver.lock().unwrap() = Some(receiver);
+
+        let counters = &self.counters;
+        for counter in [
+            &counters.queued,
+            &counters.max_queued,
+            &counters.spilled,
+            &counters.written,
+            &counters.dropped,
+            &counters.handed,
+            &counters.settled,
+            &counters.in_file,
+            &counters.read,
+        ] {
+            counter.store(0, Ordering::SeqCst);
+        }
+        counters.overrun.store(false, Ordering::SeqCst);
+
+        let (spill_sender, spill_receiver) = mpsc::sync_channel(SPILL_HANDOFF_CAPACITY);
+        let (counters, reader, limit) = (self.counters.clone(), self.spill_reader.clone(), self.spill_limit);
+        let handle = thread::spawn(move || run_spill_thread(spill_receiver, counters, reader, limit));
+        *self.spill_sender.lock().unwrap() = Some(spill_sender);
+        *self.spill_thread.lock().unwrap() = Some(handle);
+    }
+
+    /// Drops the channel and stops the spill thread, which removes the spill file
+    pub fn close(&self) {
+        *self.sender.lock().unwrap() = None;
+        *self.receiver.lock().unwrap() = None;
+        *self.spill_sender.lock().unwrap() = None;
+        if let Some(handle) = self.spill_thread.lock().unwrap().take() {
+            let _ = handle.join();
+        }
+        *self.spill_reader.lock().unwrap() = None;
+        *self.spilling.lock().unwrap() = false;
+    }
+
+    /// Queues rows for the writer; called from acquisition, and never touches the disk
+    pub fn push(&self, rows: Vec<Row>) {
+        if rows.is_empty() {
+            return;
+        }
+        let counters = &self.counters;
+        let count = rows.len() as u64;
+        let mut spilling = self.spilling.lock().unwrap();
+        let rows = if *spilling {
+            rows
+        } else {
+            let sender = self.sender.lock().unwrap();
+            let Some(sender) = sender.as_ref() else {
+                return;
+            };
+            // Counted before sending so the receiver never sees more rows than were counted
+            let queued = counters.queued.fetch_add(count, Ordering::SeqCst) + count;
+            match sender.try_send(rows) {
+                Ok(()) => {
+                    counters.max_queued.fetch_max(queued, Ordering::SeqCst);
+                    return;
+                }
+                Err(TrySendError::Full(rows)) => {
+                    counters.queued.fetch_sub(count, Ordering::SeqCst);
+                    *spilling = true;
+                    counters.overrun.store(true, Ordering::SeqCst);
+                    rows
+                }
+                Err(TrySendError::Disconnected(_)) => {
+                    counters.queued.fetch_sub(count, Ordering::SeqCst);
+                    return;
+                }
+            }
+        };
+
+        // Counted before sending so the spill thread never settles more rows than were handed
+        counters.handed.fetch_add(count, Ordering::SeqCst);
+        let handed = match self.spill_sender.lock().unwrap().as_ref() {
+            Some(sender) => sender.try_send(SpillMessage::Rows(rows)).is_ok(),
+            None => false,
+        };
+        if !handed {
+            counters.handed.fetch_sub(count, Ordering::SeqCst);
+            counters.drop_rows(count, "spill thread is behind");
+        }
+    }
+
+    /// Waits up to `timeout` for rows and returns everything available, oldest first.
+    /// Rows in memory are older than spilled ones, so the spill file is only read
+    /// once the channel is empty.
+    pub fn receive(&self, timeout: Duration) -> Vec<Row> {
+        let receiver = self.receiver.lock().unwrap();
+        let Some(receiver) = receiver.as_ref() else {
+            std::thread::sleep(timeout);
+            return Vec::new();
+        };
+        let mut rows = Vec::new();
+        loop {
+            match receiver.try_recv() {
+                Ok(batch) => {
+                    self.counters.queued.fetch_sub(batch.len() as u64, Ordering::SeqCst);
+                    rows.extend(batch);
+                    if rows.len() >= SPILL_BATCH {
+                        return rows;
+                    }
+                }
+                Err(TryRecvError::Empty) if !rows.is_empty() => return rows,
+                Err(TryRecvError::Empty) => {
+                    if let Some(spilled) = self.read_spill() {
+                        return spilled;
+                    }
+                    match receiver.recv_timeout(timeout) {
+                        Ok(batch) => {
+                            self.counters.queued.fetch_sub(batch.len() as u64, Ordering::SeqCst);
+                            rows.extend(batch);
+                        }
+                        Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => return rows,
+                    }
+                }
+                Err(TryRecvError::Disconnected) => return rows,
+            }
+        }
+    }
+
+    /// Next rows from the spill file, None when nothing is spilled. Waits for rows
+    /// still on their way to the file, so none is left behind when a recording ends.
+    /// Once everything has been read back, new rows go through the channel again.
+    fn read_spill(&self) -> Option<Vec<Row>> {
+        let counters = &self.counters;
+        loop {
+            let available = counters.in_file.load(Ordering::SeqCst) - counters.read.load(Ordering::SeqCst);
+            if available > 0 {
+                return Some(self.read_spilled(available));
+            }
+            let spilling = self.spilling.lock().unwrap();
+            if !*spilling {
+                return None;
+            }
+            // Pushes wait for the route, so nothing is handed off while this is decided
+            let settled = counters.settled.load(Ordering::SeqCst) == counters.handed.load(Ordering::SeqCst);
+            if settled && counters.in_file.load(Ordering::SeqCst) == counters.read.load(Ordering::SeqCst) {
+                self.end_spilling(spilling);
+                return None;
+            }
+            drop(spilling);
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn read_spilled(&self, available: u64) -> Vec<Row> {
+        let count = (available as usize).min(SPILL_BATCH);
+        let mut reader = self.spill_reader.lock().unwrap();
+        let result = match reader.as_mut() {
+            Some(spill) => read_rows(&mut spill.reader, count).map_err(|e| format!("{}: {}", spill.path.display(), e)),
+            None => Err("no spill file".to_string()),
+        };
+        match result {
+            Ok(rows) => {
+                self.counters.read.fetch_add(count as u64, Ordering::SeqCst);
+                rows
+            }
+            Err(e) => {
+                // The position in the file is lost; skip everything stored so far
+                let lost = self.counters.in_file.load(Ordering::SeqCst) - self.counters.read.load(Ordering::SeqCst);
+                eprintln!("[Recording] Failed to read spill file {}, {} rows lost", e, lost);
+                self.counters.read.fetch_add(lost, Ordering::SeqCst);
+                self.counters.dropped.fetch_add(lost, Ordering::SeqCst);
+                self.counters.overrun.store(true, Ordering::SeqCst);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Routes rows through the channel again and starts the spill file over;
+    /// called with the route locked once every spilled row has been read back
+    fn end_spilling(&self, mut spilling: MutexGuard<bool>) {
+        *spilling = false;
+        let rewound = match self.spill_sender.lock().unwrap().as_ref() {
+            Some(sender) => sender.try_send(SpillMessage::Rewind).is_ok(),
+            None => false,
+        };
+        if let (true, Some(spill)) = (rewound, self.spill_reader.lock().unwrap().as_mut()) {
+            if let Err(e) = spill.reader.seek(SeekFrom::Start(0)) {
+                eprintln!("[Recording] Failed to rewind spill file: {}", e);
+            }
+        }
+        println!("[Recording] Writer caught up with the spill file");
+    }
+
+    /// Counts rows handed to the format writer
+    pub fn add_written(&self, rows: usize) {
+        self.counters.written.fetch_add(rows as u64, Ordering::SeqCst);
+    }
+
+    /// Clears the overrun flag, returning whether it was set
+    pub fn take_overrun(&self) -> bool {
+        self.counters.overrun.swap(false, Ordering::SeqCst)
+    }
+
+    pub fn stats(&self) -> PipelineStats {
+        let counters = &self.counters;
+        // Each count is loaded before the one it trails
+        let (settled, read) = (counters.settled.load(Ordering::SeqCst), counters.read.load(Ordering::SeqCst));
+        let (handed, in_file) = (counters.handed.load(Ordering::SeqCst), counters.in_file.load(Ordering::SeqCst));
+        PipelineStats {
+            queued: counters.queued.load(Ordering::SeqCst),
+            max_queued: counters.max_queued.load(Ordering::SeqCst),
+            spilled: counters.spilled.load(Ordering::SeqCst),
+            spill_pending: handed.saturating_sub(settled) + in_file.saturating_sub(read),
+            written: counters.written.load(Ordering::SeqCst),
+            dropped: counters.dropped.load(Ordering::SeqCst),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One row per index, with a NaN and a value count that changes from row to row
+    fn row(index: u64) -> Row {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_nanos(1_700_000_000_123_456_789 + index * 4_000_000);
+        let mut values: Vec<f32> = (0..1 + index % 3).map(|c| index as f32 * 0.5 + c as f32).collect();
+        if index.is_multiple_of(7) {
+            values[0] = f32::NAN;
+        }
+        (index, time, values)
+    }
+
+    fn assert_in_order(rows: &[Row], first: u64) {
+        for (offset, (index, time, values)) in rows.iter().enumerate() {
+            let (expected_index, expected_time, expected_values) = row(first + offset as u64);
+            assert_eq!((*index, *time), (expected_index, expected_time));
+            assert_eq!(values.len(), expected_values.len());
+            for (value, expected) in values.iter().zip(&expected_values) {
+                assert!(value == expected || (value.is_nan() && expected.is_nan()));
+            }
+        }
+    }
+
+    /// Waits until the spill thread has stored or dropped every row handed to it
+    fn settle(pipeline: &RecordingPipeline) {
+        let counters = &pipeline.counters;
+        while counters.settled.load(Ordering::SeqCst) < counters.handed.load(Ordering::SeqCst) {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// Everything the pipeline holds, oldest first
+    fn drain(pipeline: &RecordingPipeline) -> Vec<Row> {
+        let mut rows = Vec::new();
+        loop {
+            let batch = pipeline.receive(Duration::from_millis(1));
+            if batch.is_empty() {
+                return rows;
+            }
+            rows.extend(batch);
+        }
+    }
+
+    #[test]
+    fn rows_spilled_while_the_channel_is_full_come_back_in_order() {
+        let pipeline = RecordingPipeline::new();
+        pipeline.open();
+        let total = CHANNEL_CAPACITY as u64 + 10_000;
+        for index in 0..total {
+            pipeline.push(vec![row(index)]);
+        }
+        settle(&pipeline);
+        let stats = pipeline.stats();
+        assert_eq!((stats.queued, stats.max_queued), (CHANNEL_CAPACITY as u64, CHANNEL_CAPACITY as u64));
+        assert_eq!((stats.spilled, stats.spill_pending, stats.dropped), (10_000, 10_000, 0));
+        assert!(pipeline.take_overrun());
+        assert!(!pipeline.take_overrun());
+
+        // Rows pushed while the backlog drains queue up behind the spilled ones
+        let mut rows = pipeline.receive(Duration::from_millis(1));
+        for index in total..total + 100 {
+            pipeline.push(vec![row(index)]);
+        }
+        rows.extend(drain(&pipeline));
+        assert_eq!(rows.len() as u64, total + 100);
+        assert_in_order(&rows, 0);
+
+        let stats = pipeline.stats();
+        assert_eq!((stats.queued, stats.spill_pending, stats.spilled), (0, 0, 10_100));
+        assert!(!*pipeline.spilling.lock().unwrap());
+
+        // Once caught up, rows go through the channel again
+        pipeline.push(vec![row(0)]);
+        assert_eq!(pipeline.stats().queued, 1);
+        assert_eq!(drain(&pipeline).len(), 1);
+
+        // A later backlog starts the spill file over
+        for index in 0..CHANNEL_CAPACITY as u64 + 50 {
+            pipeline.push(vec![row(index)]);
+        }
+        let rows = drain(&pipeline);
+        assert_eq!(rows.len(), CHANNEL_CAPACITY + 50);
+        assert_in_order(&rows, 0);
+        assert_eq!(pipeline.stats().spilled, 10_150);
+    }
+
+    #[test]
+    fn rows_the_spill_file_cannot_take_are_dropped_and_reported() {
+        // Room for a single row
+        let pipeline = RecordingPipeline {
+            spill_limit: 1,
+            ..RecordingPipeline::new()
+        };
+        pipeline.open();
+        for index in 0..CHANNEL_CAPACITY as u64 + 3 {
+            pipeline.push(vec![row(index)]);
+        }
+        settle(&pipeline);
+        let stats = pipeline.stats();
+        assert_eq!((stats.spilled, stats.spill_pending, stats.dropped), (1, 1, 2));
+        assert!(pipeline.take_overrun());
+        let path = pipeline.spill_reader.lock().unwrap().as_ref().unwrap().path.clone();
+        assert!(path.exists());
+
+        // What was spilled is still written; closing removes the spill file
+        assert_eq!(drain(&pipeline).len(), CHANNEL_CAPACITY + 1);
+        pipeline.close();
+        assert!(!path.exists());
+        pipeline.push(vec![row(0)]);
+        assert!(pipeline.receive(Duration::from_millis(1)).is_empty());
+    }
+}
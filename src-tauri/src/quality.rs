@@ -0,0 +1,297 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use rustfft::{Fft, FftPlanner};
+use serde::{Deserialize, Serialize};
+
+use crate::spectrum::{band_power, frequency_bins, welch_psd, Taper};
+use crate::types::{ChannelData, FULL_SCALE_MICROVOLTS};
+
+/// Segment length used for the spectral quality metrics
+const QUALITY_FFT_SIZE: usize = 256;
+
+/// User-tunable thresholds of the quality engine
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QualityThresholds {
+    pub window_seconds: f64,     // Length of the analysed window
+    pub interval_ms: u64,        // How often a `signal_quality` event is published
+    pub rms_max: f64,            // Maximum AC RMS before the channel counts as noisy
+    pub dc_offset_max: f64,      // Maximum absolute mean (electrode offset)
+    pub line_frequency: f64,     // Mains frequency, 50 or 60 Hz
+    pub line_noise_ratio_max: f64, // Maximum fraction of power around the mains frequency
+    pub flatline_std_min: f64,   // Standard deviation below which the channel is flat
+    pub saturation_level: f64,   // Absolute value treated as amplifier rail
+    pub saturation_ratio_max: f64, // Maximum fraction of samples at the rail
+    pub emg_low: f64,            // Lower edge of the EMG band in Hz
+    pub emg_ratio_max: f64,      // Maximum fraction of power in the EMG band
+    pub drift_max: f64,          // Maximum baseline change across the window
+}
+
+impl Default for QualityThresholds {
+    fn default() -> Self {
+        Self {
+            window_seconds: 2.0,
+            interval_ms: 1000,
+            rms_max: 2.235e4,
+            dc_offset_max: 1.341e5,
+            line_frequency: 50.0,
+            line_noise_ratio_max: 0.5,
+            flatline_std_min: 0.5,
+            saturation_level: FULL_SCALE_MICROVOLTS,
+            saturation_ratio_max: 0.01,
+            emg_low: 30.0,
+            emg_ratio_max: 0.4,
+            drift_max: 5.0e4,
+        }
+    }
+}
+
+impl QualityThresholds {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.window_seconds <= 0.0 || self.window_seconds > 30.0 {
+            return Err("Quality window must be between 0 and 30 seconds".to_string());
+        }
+        if self.interval_ms == 0 {
+            return Err("Quality interval must be positive".to_string());
+        }
+        if self.line_frequency <= 0.0 || self.emg_low <= 0.0 {
+            return Err("Quality frequencies must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Reason a channel lost quality points
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QualityReason {
+    InsufficientData,
+    Flatline,
+    Saturation,
+    HighRms,
+    DcOffset,
+    LineNoise,
+    Emg,
+    Drift,
+}
+
+impl QualityReason {
+    /// Points deducted from the 100-point score
+    fn penalty(&self) -> f64 {
+        match self {
+            QualityReason::InsufficientData => 0.0,
+            // Each of these marked a channel bad under the mean/std thresholds, and still does
+            QualityReason::Flatline
+            | QualityReason::Saturation
+            | QualityReason::HighRms
+            | QualityReason::DcOffset => 100.0,
+            QualityReason::LineNoise | QualityReason::Emg | QualityReason::Drift => 25.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QualityGrade {
+    Good,
+    Fair,
+    Poor,
+}
+
+/// Metrics, score and reasons for one channel
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelQuality {
+    pub channel: usize,
+    pub rms: f64,
+    pub mean: f64,
+    pub line_noise_ratio: f64,
+    pub saturation_ratio: f64,
+    pub emg_ratio: f64,
+    pub drift: f64,
+    pub score: f64,
+    pub grade: QualityGrade,
+    pub reasons: Vec<QualityReason>,
+}
+
+/// Payload of the `signal_quality` event
+#[derive(Debug, Clone, Serialize)]
+pub struct QualityReport {
+    pub window_samples: usize,
+    pub channels: Vec<ChannelQuality>,
+}
+
+impl QualityReport {
+    /// Per-channel good/bad flags, as previously returned by polling
+    pub fn flags(&self) -> Vec<bool> {
+        self.channels.iter().map(|c| c.grade != QualityGrade::Poor).collect()
+    }
+}
+
+fn grade(score: f64) -> QualityGrade {
+    if score >= 80.0 {
+        QualityGrade::Good
+    } else if score >= 50.0 {
+        QualityGrade::Fair
+    } else {
+        QualityGrade::Poor
+    }
+}
+
+/// Computes graded per-channel quality over a sliding window of the live stream
+pub struct QualityEngine {
+    thresholds: QualityThresholds,
+    history: VecDeque<ChannelData>,
+    samples_since_update: usize,
+    window: Vec<f64>,
+    fft: Arc<dyn Fft<f64>>,
+    latest: Option<QualityReport>,
+}
+
+impl QualityEngine {
+    pub fn new(thresholds: QualityThresholds) -> Self {
+        Self {
+            thresholds,
+            history: VecDeque::new(),
+            samples_since_update: 0,
+            window: Taper::Hann.coefficients(QUALITY_FFT_SIZE),
+            fft: FftPlanner::new().plan_fft_forward(QUALITY_FFT_SIZE),
+            latest: None,
+        }
+    }
+
+    pub fn thresholds(&self) -> &QualityThresholds {
+        &self.thresholds
+    }
+
+    pub fn set_thresholds(&mut self, thresholds: QualityThresholds) {
+        self.thresholds = thresholds;
+        self.samples_since_update = 0;
+    }
+
+    pub fn latest(&self) -> Option<&QualityReport> {
+        self.latest.as_ref()
+    }
+
+    /// Feed one sample; returns a report whenever the publish interval elapses
+    pub fn add_data(&mut self, data: ChannelData, sample_rate: f64) -> Option<QualityReport> {
+        let capacity = ((self.thresholds.window_seconds * sample_rate).round() as usize).max(10);
+        while self.history.len() >= capacity {
+            self.history.pop_front();
+        }
+        self.history.push_back(data);
+
+        self.samples_since_update += 1;
+        let interval = ((self.thresholds.interval_ms as f64 * sample_rate / 1000.0).round()
+            as usize)
+            .max(1);
+        if self.samples_since_update < interval {
+            return None;
+        }
+        self.samples_since_update = 0;
+
+        let report = self.evaluate(sample_rate);
+        self.latest = Some(report.clone());
+        Some(report)
+    }
+
+    fn evaluate(&self, sample_rate: f64) -> QualityReport {
+        let channels = (0..8)
+            .map(|channel| {
+                let values: Vec<f64> = self.history.iter().map(|d| d[channel] as f64).collect();
+                self.evaluate_channel(channel, &values, sample_rate)
+            })
+            .collect();
+        QualityReport {
+            window_samples: self.history.len(),
+            channels,
+        }
+    }
+
+    fn evaluate_channel(&self, channel: usize, values: &[f64], sample_rate: f64) -> ChannelQuality {
+        let t = &self.thresholds;
+        let mut quality = ChannelQuality {
+            channel,
+            rms: 0.0,
+            mean: 0.0,
+            line_noise_ratio: 0.0,
+            saturation_ratio: 0.0,
+            emg_ratio: 0.0,
+            drift: 0.0,
+            score: 100.0,
+            grade: QualityGrade::Good,
+            reasons: Vec::new(),
+        };
+        if values.len() < 10 {
+            quality.reasons.push(QualityReason::InsufficientData);
+            return quality;
+        }
+
+        let n = values.len() as f64;
+        quality.mean = values.iter().sum::<f64>() / n;
+        quality.rms = (values.iter().map(|v| (v - quality.mean).powi(2)).sum::<f64>() / n).sqrt();
+        quality.saturation_ratio =
+            values.iter().filter(|v| v.abs() >= t.saturation_level).count() as f64 / n;
+
+        // Baseline drift: change of the least-squares trend line across the window
+        let x_mean = (n - 1.0) / 2.0;
+        let (mut sxy, mut sxx) = (0.0, 0.0);
+        for (i, v) in values.iter().enumerate() {
+            let dx = i as f64 - x_mean;
+            sxy += dx * (v - quality.mean);
+            sxx += dx * dx;
+        }
+        quality.drift = if sxx > 0.0 { (sxy / sxx * (n - 1.0)).abs() } else { 0.0 };
+
+        if values.len() >= QUALITY_FFT_SIZE && sample_rate > 0.0 {
+            let psd = welch_psd(values, sample_rate, &self.window, QUALITY_FFT_SIZE / 2, self.fft.as_ref());
+            let frequencies = frequency_bins(QUALITY_FFT_SIZE, sample_rate);
+            let nyquist = sample_rate / 2.0;
+            let total = band_power(&frequencies, &psd, 0.5, nyquist + f64::EPSILON);
+            if total > 0.0 {
+                // Mains fundamental and harmonics below Nyquist; the share inside the
+                // EMG band is left out of the EMG metric so mains is not counted twice
+                let (mut line, mut line_in_emg) = (0.0, 0.0);
+                let mut harmonic = t.line_frequency;
+                while harmonic < nyquist {
+                    let power = band_power(&frequencies, &psd, harmonic - 1.0, harmonic + 1.0);
+                    line += power;
+                    if harmonic - 1.0 >= t.emg_low {
+                        line_in_emg += power;
+                    }
+                    harmonic += t.line_frequency;
+                }
+                let emg = band_power(&frequencies, &psd, t.emg_low, nyquist + f64::EPSILON) - line_in_emg;
+                quality.line_noise_ratio = line / total;
+                quality.emg_ratio = (emg / total).max(0.0);
+            }
+        }
+
+        if quality.rms < t.flatline_std_min {
+            quality.reasons.push(QualityReason::Flatline);
+        }
+        if quality.saturation_ratio > t.saturation_ratio_max {
+            quality.reasons.push(QualityReason::Saturation);
+        }
+        if quality.rms > t.rms_max {
+            quality.reasons.push(QualityReason::HighRms);
+        }
+        if quality.mean.abs() > t.dc_offset_max {
+            quality.reasons.push(QualityReason::DcOffset);
+        }
+        if quality.line_noise_ratio > t.line_noise_ratio_max {
+            quality.reasons.push(QualityReason::LineNoise);
+        }
+        if quality.emg_ratio > t.emg_ratio_max {
+            quality.reasons.push(QualityReason::Emg);
+        }
+        if quality.drift > t.drift_max {
+            quality.reasons.push(QualityReason::Drift);
+        }
+
+        let penalty: f64 = quality.reasons.iter().map(|r| r.penalty()).sum();
+        quality.score = (100.0 - penalty).max(0.0);
+        quality.grade = grade(quality.score);
+        quality
+    }
+}
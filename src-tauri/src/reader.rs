@@ -1,4 +1,7 @@
+use crate::impedance::ImpedanceConfig;
+use crate::recording::Annotation;
 use crate::state::AppState;
+use crate::trigger;
 use crate::types::FakeDataConfig;
 use encoding_rs::GBK;
 use rand::Rng;
@@ -10,7 +13,7 @@ use std::{
     sync::{
         atomic::{AtomicBool, Ordering},
         mpsc::Receiver,
-        Arc,
+        Arc, Mutex,
     },
     thread,
     time::Duration,
@@ -235,15 +238,47 @@ impl DataReader for SocketBinaryReader {
     }
 }
 
+// Simulated electrode impedances in kOhm, one per channel
+const FAKE_IMPEDANCES_KOHM: [f64; 8] = [4.0, 7.5, 12.0, 3.0, 25.0, 9.0, 55.0, 6.5];
+
 // Fake data reader
 pub struct FakeBinaryReader {
     config: FakeDataConfig,
     t: f64,
+    sample_index: u64,
+    impedance_active: Option<Arc<AtomicBool>>,
+    impedance_config: Option<Arc<Mutex<ImpedanceConfig>>>,
 }
 
 impl FakeBinaryReader {
     pub fn new(config: FakeDataConfig) -> Self {
-        Self { config, t: 0.0 }
+        Self {
+            config,
+            t: 0.0,
+            sample_index: 0,
+            impedance_active: None,
+            impedance_config: None,
+        }
+    }
+
+    // Simulate the impedance test current while impedance mode is active
+    pub fn with_impedance(mut self, active: Arc<AtomicBool>, config: Arc<Mutex<ImpedanceConfig>>) -> Self {
+        self.impedance_active = Some(active);
+        self.impedance_config = Some(config);
+        self
+    }
+
+    // Test-signal value of a channel for the current sample, if impedance mode is on
+    fn impedance_value(&self, channel: usize) -> Option<f64> {
+        let active = self.impedance_active.as_ref()?;
+        if !active.load(Ordering::SeqCst) {
+            return None;
+        }
+        let config = self.impedance_config.as_ref()?.lock().unwrap();
+        let amplitude = config.amplitude_for(FAKE_IMPEDANCES_KOHM[channel]);
+        let phase = 2.0 * std::f64::consts::PI * config.test_frequency * self.sample_index as f64
+            / self.config.frequency;
+        Some(self.config.min_value as f64 + amplitude * phase.sin())
     }
 }
 
@@ -258,6 +293,10 @@ impl DataReader for FakeBinaryReader {
             let phase = self.t + (i as f64 * 0.2);
             let amplitude = (self.config.max_value - self.config.min_value) as f64;
             let offset = self.config.min_value as f64;
+            if let Some(raw_value) = self.impedance_value(i) {
+                packet.extend_from_slice(&((raw_value / (0.5364 / 12.0)) as i32).to_le_bytes());
+                continue;
+            }
             let value = match self.config.waveform.as_str() {
                 "sine" => {
                     let raw_value = (phase * 2.0 * std::f64::consts::PI).sin() * amplitude / 2.0
@@ -310,6 +349,7 @@ impl DataReader for FakeBinaryReader {
         packet.push(sc1);
         packet.push(sc2);
         self.t += 0.001;
+        self.sample_index += 1;
         thread::sleep(Duration::from_millis(
             (1000.0 / self.config.frequency).round() as u64,
         ));
@@ -335,6 +375,7 @@ fn compute_checksum(data: &[u8]) -> (u8, u8) {
 fn process_buffer(buffer: &mut Vec<u8>, state: &AppState, app: &AppHandle) {
     let mut info_bytes = Vec::new();
     let header_len = DATA_HEADER.len();
+    let sample_rate = state.stream.get_sample_rate();
     let mut i = 0;
     while i + header_len <= buffer.len() {
         if &buffer[i..i + header_len] == &DATA_HEADER {
@@ -351,11 +392,39 @@ fn process_buffer(buffer: &mut Vec<u8>, state: &AppState, app: &AppHandle) {
                     // Convert raw value to real voltage using the formula: raw_value * 0.5364 / 12
                     data[j] = (v as f32) * 0.5364 / 12.0; // hard code for real unit calculation
                 }
-                // Update both buffer and signal quality with the new data
-                state.buffer.add_data(data);
-                state.signal_quality.add_data(data);
-                state.recording.add_data(data);
-                let _ = app.emit("serial_data", data);
+                // Visualization sees the montage output plus derived channels;
+                // quality and spectra stay electrode-level
+                let mut display = state.montage.apply(&data);
+                let derived = state.expressions.evaluate(&data, &display, &state.montage, sample_rate);
+                display.extend(derived.iter().map(|(_, value)| *value));
+                state.buffer.add_data(display.clone());
+                state.recording.add_data(data, &derived);
+                state.sample_output.add_data(&display, sample_rate);
+                if let Some(reason) = state.trigger.check_sample(&display) {
+                    trigger::fire_trigger(app, reason);
+                }
+                let _ = app.emit("serial_data", display);
+                if let Some(report) = state.signal_quality.add_data(data, sample_rate) {
+                    if let Some(reason) = state.trigger.check_quality(&report) {
+                        trigger::fire_trigger(app, reason);
+                    }
+                    let _ = app.emit("signal_quality", report);
+                }
+                if let Some(report) = state.impedance.add_data(data, sample_rate) {
+                    let _ = app.emit("impedance_update", report);
+                }
+                for artifact in state.artifact.add_data(data, sample_rate) {
+                    if let Some(reason) = state.trigger.check_artifact(&artifact) {
+                        trigger::fire_trigger(app, reason);
+                    }
+                    if state.artifact.annotate_recording() {
+                        state.recording.add_annotation(Annotation::from(&artifact));
+                    }
+                    let _ = app.emit("artifact_detected", artifact);
+                }
+                if let Some(spectrum) = state.spectrum.add_data(data, sample_rate) {
+                    let _ = app.emit("spectrum", spectrum);
+                }
             }
             i += DATA_PACKET_LENGTH;
         } else {
@@ -363,6 +432,7 @@ fn process_buffer(buffer: &mut Vec<u8>, state: &AppState, app: &AppHandle) {
             i += 1;
         }
     }
+    trigger::poll_trigger(app);
     // Remove processed bytes, keep leftover in buffer
     buffer.drain(..i);
     // Emit collected invalid data once
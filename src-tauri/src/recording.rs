@@ -1,30 +1,142 @@
+use crate::artifact::ArtifactEvent;
+use crate::clock::SessionClock;
+use crate::integrity;
+use crate::recording_reader;
+use crate::resample::Resampler;
+use crate::rotation::SegmentLimits;
+use crate::session;
 use crate::state::AppState;
+use crate::storage::{self, StorageMonitor};
+use crate::writer::{self, RecordingInfo, Row};
+use serde::Serialize;
 use serde_json::json;
-use std::fs::{OpenOptions, File};
+use std::fs::OpenOptions;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::{atomic::Ordering, Arc};
 use std::thread::{self, JoinHandle};
 use std::time::{SystemTime, Duration};
 use tauri::{AppHandle, Emitter, Manager};
 
+/// An annotation attached to the active recording, written to a sidecar file.
+#[derive(Debug, Clone, Serialize)]
+pub struct Annotation {
+    pub onset_ms: u64, // Milliseconds since the Unix epoch
+    pub duration_ms: f64,
+    pub channel: Option<usize>,
+    pub label: String,
+}
+
+impl Annotation {
+    /// First line of an annotation sidecar
+    pub const SIDECAR_HEADER: &'static str = "onset_ms,duration_ms,channel,label";
+
+    /// The annotation as a line of the sidecar, with the label quoted
+    pub fn sidecar_line(&self) -> String {
+        let channel = self.channel.map(|c| c.to_string()).unwrap_or_default();
+        format!(
+            "{},{},{},\"{}\"",
+            self.onset_ms,
+            self.duration_ms,
+            channel,
+            self.label.replace('"', "\"\"")
+        )
+    }
+}
+
+impl From<&ArtifactEvent> for Annotation {
+    fn from(artifact: &ArtifactEvent) -> Self {
+        Self {
+            onset_ms: artifact.onset_ms,
+            duration_ms: artifact.duration_ms,
+            channel: Some(artifact.channel),
+            label: format!("artifact:{}", artifact.kind.label()),
+        }
+    }
+}
+
+/// Checks that `format` names a supported recording format.
+pub fn validate_format(format: &str) -> Result<(), String> {
+    if writer::FORMATS.contains(&format) {
+        Ok(())
+    } else {
+        Err("Invalid format specified".to_string())
+    }
+}
+
+/// Channel layout and format options handed to a new segment writer.
+fn recording_info(state: &AppState) -> RecordingInfo {
+    RecordingInfo {
+        labels: state.recording.channel_labels(),
+        sample_rate: *state.recording.recording_sample_rate.lock().unwrap(),
+        device: state.stream.device.lock().unwrap().describe(),
+        binary: state.recording.binary_options.lock().unwrap().clone(),
+        edf: state.recording.edf_options.lock().unwrap().clone(),
+        brainvision: state.recording.brainvision_options.lock().unwrap().clone(),
+        compression: state.recording.compression.lock().unwrap().clone(),
+    }
+}
+
+/// Freezes the montage, derived channels and output rate for the next recording
+/// so columns stay stable across segments.
+pub fn prepare_layout(state: &AppState) -> Result<(), String> {
+    // Build the resampler first so an unsupported rate fails before anything changes
+    let source_rate = state.stream.get_sample_rate();
+    let config = state.recording.resample_config.lock().unwrap().clone();
+    let resampler = if config.enabled && (config.target_rate - source_rate).abs() > f64::EPSILON {
+        Some(Resampler::new(source_rate, &config)?)
+    } else {
+        None
+    };
+
+    *state.recording.recording_montage.lock().unwrap() =
+        if state.montage.apply_to_recording.load(Ordering::SeqCst) {
+            state.montage.active.lock().unwrap().clone()
+        } else {
+            None
+        };
+    *state.recording.recording_derived.lock().unwrap() = state.expressions.names();
+
+    // Resample to the configured output rate; the live stream keeps the device rate
+    *state.recording.recording_sample_rate.lock().unwrap() =
+        resampler.as_ref().map(|r| r.target_rate()).unwrap_or(source_rate);
+    *state.recording.recording_resampler.lock().unwrap() = resampler;
+    // Sample indices and times count from the first sample of this session
+    *state.recording.clock.lock().unwrap() = SessionClock::new(source_rate);
+    Ok(())
+}
+
 /// Starts recording data to a file in the specified format.
 /// 
 /// # Arguments
-/// * `format` - The format to use for recording ("csv", "json", or "binary")
+/// * `format` - The format to use for recording ("csv", "json", "jsonl", "binary", "edf", "bdf", "brainvision" or "xdf")
 /// * `directory` - The directory path where recordings should be saved
 /// * `max_duration_minutes` - Maximum duration in minutes for each recording segment
-/// * `_auto_start` - Whether to automatically start recording (currently unused)
+/// * `auto_start` - Arm the configured trigger instead of starting now; the filename
+///   is then reported by the `recording_triggered` event and an empty string is returned
 /// * `state` - Application state containing shared data
 pub fn start_recording(
     format: String,
     directory: String,
     max_duration_minutes: u32,
-    _auto_start: bool,
+    auto_start: bool,
     app_handle: AppHandle,
 ) -> Result<String, String> {
+    if auto_start {
+        crate::trigger::arm_trigger(&app_handle, format, directory, max_duration_minutes)?;
+        return Ok(String::new());
+    }
+
     let state = app_handle.state::<Arc<AppState>>();
-    let mut path = PathBuf::from(&directory);
+    validate_format(&format)?;
+
+    // An armed or fired trigger has already frozen the layout its pre-trigger rows use
+    if !state.recording.recording_active.load(Ordering::SeqCst)
+        && !state.trigger.armed.load(Ordering::SeqCst)
+        && !state.recording.pre_trigger_held.load(Ordering::SeqCst)
+    {
+        prepare_layout(&state)?;
+    }
     
     // Create a timestamped filename
     let now = SystemTime::now()
@@ -32,33 +144,63 @@ pub fn start_recording(
         .map_err(|e| e.to_string())?;
     
     let timestamp = now.as_millis();
-    let filename = match format.as_str() {
-        "csv" => format!("serial_recording_{}.csv", timestamp),
-        "json" => format!("serial_recording_{}.json", timestamp),
-        "binary" => format!("serial_recording_{}.bin", timestamp),
-        _ => return Err("Invalid format specified".to_string()),
+    let info = recording_info(&state);
+    let filename = writer::file_name(&format!("serial_recording_{}", timestamp), &format, &info.compression);
+    
+    // A new session gets its own directory; a running one keeps writing to its directory
+    let (new_session, directory) = if state.recording.recording_active.load(Ordering::SeqCst) {
+        let current = state.recording.recording_directory.lock().unwrap().clone();
+        (None, current.unwrap_or(directory))
+    } else {
+        storage::prepare_session(&state, Path::new(&directory))?;
+        let (id, session_directory) = session::create_session_directory(Path::new(&directory))?;
+        (Some(id), session_directory.to_string_lossy().into_owned())
     };
+    let mut path = PathBuf::from(&directory);
     
     // Clone the filename before pushing to path to avoid ownership issues
     path.push(filename.clone());
     
-    // Set up the file
-    let file = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(&path)
-        .map_err(|e| format!("Failed to create recording file: {}", e))?;
+    // Set up the file and its format writer
+    let writer = match writer::create_writer(&format, &path, &info) {
+        Ok(writer) => writer,
+        Err(e) => {
+            if new_session.is_some() {
+                let _ = std::fs::remove_dir_all(&directory);
+            }
+            return Err(e);
+        }
+    };
     
-    // Store the file in state for continued writing
-    *state.recording.recording_file.lock().unwrap() = Some((file, format.clone()));
+    // Store the writer in state for continued writing; a replaced one is completed first
+    let previous = state.recording.recording_writer.lock().unwrap().replace((writer, format.clone()));
+    if let Some((mut previous, _)) = previous {
+        if let Err(e) = previous.finish() {
+            eprintln!("Error finishing recording file: {}", e);
+        }
+        seal_current_segment(&state, false);
+    }
     
     // Store the filename for retrieval even when switching views
     *state.recording.recording_filename.lock().unwrap() = Some(filename.clone());
+    *state.recording.recording_directory.lock().unwrap() = Some(directory.clone());
+
+    // The session manifest lists every file written until the recording stops
+    match new_session {
+        Some(id) => {
+            session::begin_session(&state, &id, &format, Path::new(&directory), &filename);
+            // Retention runs once the new session counts among the kept ones
+            if let Some(parent) = Path::new(&directory).parent() {
+                storage::apply_retention_automatically(&state, parent);
+            }
+        }
+        None => session::add_segment(&state, &filename),
+    }
     
     // Start the recording thread that will poll data and write to file
     if !state.recording.recording_active.load(Ordering::SeqCst) {
-        state.recording.recording_active.store(true, Ordering::SeqCst);
+        // A fired trigger hands over the rows captured before it
+        state.recording.begin_writing();
         let state_clone = state.inner().clone();
         
         // Clone format and directory for the emit event after max duration
@@ -68,27 +210,6 @@ pub fn start_recording(
         let max_duration = Duration::from_secs(max_duration_minutes as u64 * 60);
         let start_time = SystemTime::now();
         
-        // Write header for CSV format
-        if format == "csv" {
-            if let Some((ref mut file, _)) = *state.recording.recording_file.lock().unwrap() {
-                // Write CSV header based on channel count
-                let mut header = String::from("timestamp");
-                for i in 0..8 { // Support up to 8 channels as per memory
-                    header.push_str(&format!(",channel_{}", i));
-                }
-                if let Err(e) = writeln!(file, "{}", header) {
-                    return Err(format!("Failed to write CSV header: {}", e));
-                }
-            }
-        } else if format == "json" {
-            // Start JSON array
-            if let Some((ref mut file, _)) = *state.recording.recording_file.lock().unwrap() {
-                if let Err(e) = file.write_all(b"[") {
-                    return Err(format!("Failed to write JSON opening: {}", e));
-                }
-            }
-        }
-        
         let handle = spawn_recording_thread(state_clone, format_clone, directory_clone, max_duration, start_time);
         
         *state.recording.recording_handle.lock().unwrap() = Some(handle);
@@ -98,31 +219,98 @@ pub fn start_recording(
     Ok(filename)
 }
 
+/// Pauses an active recording: samples and video frames are discarded until
+/// `resume_recording`, while the segment file and the video file stay open.
+pub fn pause_recording(app_handle: AppHandle) -> Result<(), String> {
+    let state = app_handle.state::<Arc<AppState>>();
+    if !state.recording.recording_active.load(Ordering::SeqCst) {
+        return Err("No recording to pause".to_string());
+    }
+    if state.recording.paused.swap(true, Ordering::SeqCst) {
+        return Err("Recording is already paused".to_string());
+    }
+    let now = SystemTime::now();
+    *state.recording.paused_at.lock().unwrap() = Some(now);
+    println!("[Recording] Paused");
+    let _ = app_handle.emit("recording_paused", millis(now));
+    Ok(())
+}
+
+/// Resumes a paused recording and records the pause as an annotation in the
+/// segment and in the session manifest. Returns the length of the pause in ms.
+pub fn resume_recording(app_handle: AppHandle) -> Result<f64, String> {
+    let state = app_handle.state::<Arc<AppState>>();
+    let duration_ms = end_pause(&state).ok_or("Recording is not paused")?;
+    println!("[Recording] Resumed after {:.0} ms", duration_ms);
+    let _ = app_handle.emit("recording_resumed", duration_ms);
+    Ok(duration_ms)
+}
+
+/// Clears the pause flag and annotates the pause; None when not paused
+fn end_pause(state: &AppState) -> Option<f64> {
+    let started = state.recording.paused_at.lock().unwrap().take()?;
+    state.recording.paused.store(false, Ordering::SeqCst);
+    let duration_ms = SystemTime::now().duration_since(started).unwrap_or_default().as_secs_f64() * 1000.0;
+    // Pushed directly: a recording being stopped is already marked inactive
+    state.recording.annotations.lock().unwrap().push(Annotation {
+        onset_ms: millis(started),
+        duration_ms,
+        channel: None,
+        label: "pause".to_string(),
+    });
+    session::add_pause(state, millis(started), duration_ms);
+    Some(duration_ms)
+}
+
+fn millis(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
 /// Stops an active recording process.
 pub fn stop_recording(app_handle: AppHandle) -> Result<(), String> {
     let state = app_handle.state::<Arc<AppState>>();
-    
-    // Only do something if recording is active
-    if state.recording.recording_active.load(Ordering::SeqCst) {
-        // Close the JSON array for JSON format recordings
-        if let Some((ref mut file, ref format)) = *state.recording.recording_file.lock().unwrap() {
-            if format == "json" {
-                if let Err(e) = file.write_all(b"]") {
-                    eprintln!("Error closing JSON file: {}", e);
-                }
-            }
+    end_recording(&state);
+    Ok(())
+}
+
+/// Stops the recording from the recording thread, which `stop_recording` waits
+/// for; the stop runs on a thread of its own, which is returned
+pub fn stop_recording_in_background(state: &Arc<AppState>) -> JoinHandle<()> {
+    let state = state.clone();
+    thread::spawn(move || end_recording(&state))
+}
+
+fn end_recording(state: &AppState) {
+    // Only the caller that clears the flag cleans up; concurrent stops return here
+    if !state.recording.recording_active.swap(false, Ordering::SeqCst) {
+        return;
+    }
+
+    // The recording thread writes what is left and completes the file before it exits
+    let handle = state.recording.recording_handle.lock().unwrap().take();
+    if let Some(handle) = handle {
+        if handle.join().is_err() {
+            eprintln!("Recording thread panicked");
         }
-        
-        // Set the flag to false before cleaning up
-        state.recording.recording_active.store(false, Ordering::SeqCst);
-        
-        // Clean up the file handles and threads
-        let _ = state.recording.recording_handle.lock().unwrap().take();
-        *state.recording.recording_file.lock().unwrap() = None;
-        *state.recording.recording_filename.lock().unwrap() = None;
     }
-    
-    Ok(())
+    *state.recording.recording_writer.lock().unwrap() = None;
+    *state.recording.recording_filename.lock().unwrap() = None;
+    *state.recording.annotation_file.lock().unwrap() = None;
+    state.recording.video_frames.lock().unwrap().clear();
+    *state.recording.recording_resampler.lock().unwrap() = None;
+    // Markers placed between recordings aren't stamped against the finished session
+    let mut clock = state.recording.clock.lock().unwrap();
+    *clock = SessionClock::new(clock.rate());
+    drop(clock);
+    *state.trigger.stop_deadline.lock().unwrap() = None;
+    state.recording.annotations.lock().unwrap().clear();
+    session::end_session(state);
+
+    // Retention works on the recording directory holding the session directories
+    let directory = state.recording.recording_directory.lock().unwrap().clone();
+    if let Some(parent) = directory.as_deref().and_then(|d| Path::new(d).parent()) {
+        storage::apply_retention_automatically(state, parent);
+    }
 }
 
 /// Spawns a thread to handle recording data to files.
@@ -134,129 +322,211 @@ fn spawn_recording_thread(
     start_time: SystemTime,
 ) -> JoinHandle<()> {
     thread::spawn(move || {
-        let mut first_json_entry = true;
-        let mut segment_start_time = start_time;
+        let mut segment = Segment {
+            limits: SegmentLimits::new(state_clone.recording.rotation.lock().unwrap().clone()),
+            format: format_clone,
+            directory: directory_clone,
+            start_time,
+            max_duration,
+        };
+        let mut storage_monitor = StorageMonitor::new(Path::new(&segment.directory));
         
         while state_clone.recording.recording_active.load(Ordering::SeqCst) {
-            // Check if the current segment exceeded the configured duration
-            if let Ok(elapsed) = SystemTime::now().duration_since(segment_start_time) {
-                if elapsed > max_duration {
-                    handle_segment_rotation(
-                        &state_clone,
-                        &format_clone,
-                        &directory_clone,
-                        &mut first_json_entry,
-                        &mut segment_start_time,
-                        &max_duration,
-                    );
+            // Check if the current segment exceeded the configured duration; a paused
+            // recording stays in its segment so the pause doesn't start a new file
+            let paused = state_clone.recording.paused.load(Ordering::SeqCst);
+            if let (false, Ok(elapsed)) = (paused, SystemTime::now().duration_since(segment.start_time)) {
+                if elapsed > segment.max_duration {
+                    segment.rotate(&state_clone);
                     continue; // Skip to next loop to immediately write to the new segment
                 }
             }
 
-            // Get batch of recording data
-            let data_batch = state_clone.recording.get_recording_data();
-            if data_batch.is_empty() {
-                // If no new data, sleep a bit and try again
-                thread::sleep(Duration::from_millis(10));
-                continue;
-            }
-            
-            // Record the data based on format
-            let mut recording_file = state_clone.recording.recording_file.lock().unwrap();
-            if let Some((ref mut file, ref format)) = *recording_file {
-                match format.as_str() {
-                    "csv" => {
-                        write_csv_data(file, &data_batch);
-                    },
-                    "json" => {
-                        write_json_data(file, &data_batch, &mut first_json_entry);
-                    },
-                    "binary" => {
-                        write_binary_data(file, &data_batch);
-                    },
-                    _ => {}
-                }
+            write_annotations(&state_clone);
+            write_video_frames(&state_clone);
+            session::add_sync_points(&state_clone, state_clone.recording.take_sync_points());
+            report_overrun(&state_clone);
+            storage_monitor.check(&state_clone);
+
+            // Wait for rows without holding any lock the acquisition side needs
+            let data_batch = state_clone.recording.get_recording_data(Duration::from_millis(20));
+            if !data_batch.is_empty() {
+                segment.write(&state_clone, &data_batch);
             }
-            
-            // Sleep a shorter time to check for new data more frequently
-            thread::sleep(Duration::from_millis(5));
         }
         
-        // Finalize the recording
-        let mut recording_file = state_clone.recording.recording_file.lock().unwrap();
-        if let Some((ref mut file, ref format)) = *recording_file {
-            if format == "json" {
-                // Close the JSON array
-                let _ = file.write_all(b"]");
+        // Finalize the recording with everything still queued, in memory or spilled
+        loop {
+            let remaining = state_clone.recording.get_recording_data(Duration::ZERO);
+            if remaining.is_empty() {
+                break;
+            }
+            segment.write(&state_clone, &remaining);
+        }
+        // A pause running into the stop is still recorded
+        end_pause(&state_clone);
+        write_annotations(&state_clone);
+        write_video_frames(&state_clone);
+        session::add_sync_points(&state_clone, state_clone.recording.take_sync_points());
+        report_overrun(&state_clone);
+        state_clone.recording.pipeline.close();
+        let mut recording_writer = state_clone.recording.recording_writer.lock().unwrap();
+        if let Some((ref mut writer, ref format)) = *recording_writer {
+            if let Err(e) = writer.finish() {
+                eprintln!("Error finishing {} recording: {}", format, e);
             }
-            
-            // Flush the file
-            let _ = file.flush();
         }
         
-        // Clear the file handle
-        *recording_file = None;
+        // Clear the writer
+        let finished = recording_writer.take().is_some();
+        drop(recording_writer);
+        if finished {
+            seal_current_segment(&state_clone, true);
+        }
     })
 }
 
-/// Rotates to a new recording segment when the max duration is reached.
+/// The segment being written and the limits that end it
+struct Segment {
+    format: String,
+    directory: String,
+    start_time: SystemTime,
+    max_duration: Duration,
+    limits: SegmentLimits,
+}
+
+impl Segment {
+    /// Writes rows, rotating between them so every sample lands in exactly one segment.
+    fn write(&mut self, state: &Arc<AppState>, mut rows: &[Row]) {
+        let filename = state.recording.recording_filename.lock().unwrap().clone().unwrap_or_default();
+        if self.limits.is_full(Path::new(&self.directory), &filename) {
+            self.rotate(state);
+        }
+        loop {
+            let count = self.limits.rows_to_write(rows.iter().map(|(_, timestamp, _)| *timestamp));
+            let (segment_rows, rest) = rows.split_at(count);
+            if !segment_rows.is_empty() {
+                write_samples(state, segment_rows);
+                self.limits.add(count);
+            }
+            rows = rest;
+            if rows.is_empty() {
+                break;
+            }
+            self.rotate(state);
+        }
+    }
+
+    fn rotate(&mut self, state: &Arc<AppState>) {
+        handle_segment_rotation(state, &self.format, &self.directory, &mut self.start_time, &self.max_duration);
+        self.limits = SegmentLimits::new(state.recording.rotation.lock().unwrap().clone());
+    }
+}
+
+/// Records the checksums of the segment just finished; `wait` seals it before returning
+fn seal_current_segment(state: &AppState, wait: bool) {
+    let filename = state.recording.recording_filename.lock().unwrap().clone();
+    let directory = state.recording.recording_directory.lock().unwrap().clone();
+    if let (Some(filename), Some(directory)) = (filename, directory) {
+        integrity::seal_segment(Path::new(&directory), &filename, wait);
+    }
+}
+
+/// Emits `recording_overrun` with the pipeline counters when the writer fell behind
+/// far enough to spill rows to disk or drop them.
+fn report_overrun(state: &Arc<AppState>) {
+    if !state.recording.pipeline.take_overrun() {
+        return;
+    }
+    let stats = state.recording.pipeline.stats();
+    eprintln!(
+        "[Recording] Overrun: {} rows queued, {} spilled, {} dropped",
+        stats.queued, stats.spilled, stats.dropped
+    );
+    if let Some(app_handle) = state.communication.app_handle.lock().unwrap().as_ref() {
+        let _ = app_handle.emit("recording_overrun", stats);
+    }
+}
+
+/// Writes rows to the current segment and counts them in the session manifest.
+fn write_samples(state: &Arc<AppState>, rows: &[Row]) {
+    let written = match *state.recording.recording_writer.lock().unwrap() {
+        Some((ref mut writer, ref format)) => match writer.write_samples(rows) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("Error writing {} recording: {}", format, e);
+                state.storage.write_failed.store(true, Ordering::SeqCst);
+                false
+            }
+        },
+        None => false,
+    };
+    if written {
+        state.recording.pipeline.add_written(rows.len());
+        session::count_samples(state, rows.len());
+    }
+}
+
+/// Rotates to a new recording segment when the max duration or a rotation limit is reached.
 fn handle_segment_rotation(
     state_clone: &Arc<AppState>,
     format_clone: &str,
     directory_clone: &str,
-    first_json_entry: &mut bool,
     segment_start_time: &mut SystemTime,
     _max_duration: &Duration,
 ) {
-    // Finalize the current segment file (flush + close JSON array if needed)
-    {
-        let mut recording_file = state_clone.recording.recording_file.lock().unwrap();
-        if let Some((ref mut file, ref fmt)) = *recording_file {
-            if fmt == "json" {
-                let _ = file.write_all(b"]");
+    // Annotations and frames queued so far belong to the closing segment
+    write_annotations(state_clone);
+    write_video_frames(state_clone);
+
+    // Finalize the current segment file
+    let finished = match *state_clone.recording.recording_writer.lock().unwrap() {
+        Some((ref mut writer, _)) => {
+            if let Err(e) = writer.finish() {
+                eprintln!("Error finishing recording segment: {}", e);
             }
-            let _ = file.flush();
+            true
         }
+        None => false,
+    };
+    if !finished {
+        return; // A failed rotation has already stopped the recording
     }
+    seal_current_segment(state_clone, false);
 
     // Create a new filename based on the same format & directory
     let now = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap_or_else(|_| Duration::from_secs(0));
     let timestamp = now.as_millis();
-    let new_filename = match format_clone {
-        "csv" => format!("serial_recording_{}.csv", timestamp),
-        "json" => format!("serial_recording_{}.json", timestamp),
-        _ => format!("serial_recording_{}.bin", timestamp),
-    };
+    let base_filename = format!("serial_recording_{}", timestamp);
+    let info = recording_info(state_clone);
+    let new_filename = writer::file_name(&base_filename, format_clone, &info.compression);
 
     let mut new_path = PathBuf::from(directory_clone);
     new_path.push(&new_filename);
 
-    if let Ok(mut new_file) = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(&new_path)
-    {
-        // CSV: write header; JSON: open array
-        if format_clone == "csv" {
-            let mut header = String::from("timestamp");
-            for i in 0..8 {
-                header.push_str(&format!(",channel_{}", i));
+    match writer::create_writer(format_clone, &new_path, &info) {
+        Ok(new_writer) => {
+            // Swap the writer and update filename in shared state
+            *state_clone.recording.recording_writer.lock().unwrap() =
+                Some((new_writer, format_clone.to_string()));
+            *state_clone.recording.recording_filename.lock().unwrap() = Some(new_filename.clone());
+            session::add_segment(state_clone, &new_filename);
+            
+            // Log the segment change
+            println!("Recording segment changed to: {}", new_filename);
+        }
+        Err(e) => {
+            // The finished writer must not take more rows or be finished again
+            state_clone.recording.recording_writer.lock().unwrap().take();
+            eprintln!("Error creating recording segment, stopping the recording: {}", e);
+            if let Some(app_handle) = state_clone.communication.app_handle.lock().unwrap().as_ref() {
+                let _ = app_handle.emit("recording_error", format!("Failed to start a new segment: {}", e));
             }
-            let _ = writeln!(new_file, "{}", header);
-        } else if format_clone == "json" {
-            let _ = new_file.write_all(b"[");
+            stop_recording_in_background(state_clone);
+            return;
         }
-
-        // Swap file handle and update filename in shared state
-        *state_clone.recording.recording_file.lock().unwrap() =
-            Some((new_file, format_clone.to_string()));
-        *state_clone.recording.recording_filename.lock().unwrap() = Some(new_filename.clone());
-        
-        // Log the segment change
-        println!("Recording segment changed to: {}", new_filename);
     }
 
     // Emit events to frontend just to notify filename change
@@ -266,7 +536,8 @@ fn handle_segment_rotation(
             json!({
                 "filename": new_filename.clone(),
                 "directory": directory_clone,
-                "format": format_clone
+                "format": format_clone,
+                "session": state_clone.session.active.lock().unwrap().as_ref().map(|s| s.metadata.id.clone())
             })
         });
         
@@ -278,9 +549,6 @@ fn handle_segment_rotation(
     
     // Handle video recording segment rotation directly in the backend
     if let Some(app_handle) = state_clone.communication.app_handle.lock().unwrap().as_ref() {
-        // Extract base filename for video recording (remove extension)
-        let base_filename = new_filename.replace(&format!(".{}", format_clone), "");
-        
         // First stop the current video recording
         println!("Stopping video recording for segment rotation");
         match crate::streaming::stop_video_recording(app_handle.clone()) {
@@ -295,7 +563,7 @@ fn handle_segment_rotation(
         println!("Starting new video recording segment with base filename: {}", base_filename);
         match crate::streaming::start_video_recording(
             app_handle.clone(),
-            base_filename,
+            base_filename.clone(),
             directory_clone.to_string(),
         ) {
             Ok(_) => println!("Successfully started new video recording segment"),
@@ -303,100 +571,68 @@ fn handle_segment_rotation(
         }
     }
 
-    // Reset per-segment flags and timer
-    *first_json_entry = true;
+    // Reset the segment timer
     *segment_start_time = SystemTime::now();
 }
 
-/// Writes data in CSV format to the specified file.
-fn write_csv_data(file: &mut File, timestamped_data: &[(SystemTime, [f32; 8])]) {
-    // Process each data point with its timestamp
-    for (timestamp, channel_data) in timestamped_data {
-        // Convert timestamp to milliseconds
-        let timestamp_ms = timestamp
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap_or_else(|_| Duration::from_secs(0))
-            .as_millis();
-            
-        // CSV: timestamp,val1,val2,...
-        let mut line = format!("{}", timestamp_ms);
-        // Each channel_data is an array of 8 f32 values
-        for &value in channel_data.iter() {
-            line.push_str(&format!(",{}", value));
-        }
-        if let Err(e) = writeln!(file, "{}", line) {
-            eprintln!("Error writing to CSV file: {}", e);
-            break;
-        }
+/// Hands queued video frame times to the current segment writer.
+fn write_video_frames(state: &Arc<AppState>) {
+    let frames = state.recording.take_video_frames();
+    if frames.is_empty() {
+        return;
     }
-    // Flush CSV entries to disk in real time
-    if let Err(e) = file.flush() {
-        eprintln!("Error flushing CSV file: {}", e);
+    if let Some((ref mut writer, ref format)) = *state.recording.recording_writer.lock().unwrap() {
+        if let Err(e) = writer.write_video_frames(&frames) {
+            eprintln!("Error writing {} video frames: {}", format, e);
+        }
     }
 }
 
-/// Writes data in JSON format to the specified file.
-fn write_json_data(file: &mut File, timestamped_data: &[(SystemTime, [f32; 8])], first_json_entry: &mut bool) {
-    // Process each data point with its timestamp
-    for (timestamp, channel_data) in timestamped_data {
-        // Convert timestamp to milliseconds
-        let timestamp_ms = timestamp
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap_or_else(|_| Duration::from_secs(0))
-            .as_millis();
-                        
-        // Convert data to flattened Vec for JSON serialization
-        let mut values = Vec::new();
-        for &value in channel_data.iter() {
-            values.push(value);
-        }
-                        
-        let json_entry = format!("{}{{\"timestamp\": {},\"values\": {}}}",
-            if *first_json_entry { "" } else { "," },
-            timestamp_ms,
-            serde_json::to_string(&values).unwrap()
-        );
-        *first_json_entry = false;
-                        
-        if let Err(e) = file.write_all(json_entry.as_bytes()) {
-            eprintln!("Error writing to JSON file: {}", e);
-            break;
-        }
+/// Appends queued annotations to the sidecar of the current segment
+/// (`<segment>.annotations.csv`), opening a new sidecar after rotation.
+fn write_annotations(state: &Arc<AppState>) {
+    let annotations = state.recording.take_annotations();
+    if annotations.is_empty() {
+        return;
     }
-}
 
-/// Writes data in binary format to the specified file.
-fn write_binary_data(file: &mut File, timestamped_data: &[(SystemTime, [f32; 8])]) {
-    // Process each data point with its timestamp
-    for (timestamp, channel_data) in timestamped_data {
-        // Convert timestamp to milliseconds
-        let timestamp_ms = timestamp
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap_or_else(|_| Duration::from_secs(0))
-            .as_millis() as u64;
-            
-        // Calculate number of values
-        let num_values = channel_data.len() as u32;
-        
-        // Write timestamp
-        if let Err(e) = file.write_all(&timestamp_ms.to_le_bytes()) {
-            eprintln!("Error writing timestamp to binary file: {}", e);
-            break;
+    // Formats with an annotation channel keep them in the signal file
+    if let Some((ref mut writer, _)) = *state.recording.recording_writer.lock().unwrap() {
+        match writer.write_annotations(&annotations) {
+            Ok(true) => return,
+            Ok(false) => {}
+            Err(e) => eprintln!("Error writing annotations: {}", e),
         }
-        
-        // Write number of values
-        if let Err(e) = file.write_all(&num_values.to_le_bytes()) {
-            eprintln!("Error writing value count to binary file: {}", e);
-            break;
+    }
+    let filename = state.recording.recording_filename.lock().unwrap().clone();
+    let directory = state.recording.recording_directory.lock().unwrap().clone();
+    let (Some(filename), Some(directory)) = (filename, directory) else {
+        return;
+    };
+
+    let mut annotation_file = state.recording.annotation_file.lock().unwrap();
+    let current = annotation_file.as_ref().map(|(_, segment)| segment == &filename).unwrap_or(false);
+    if !current {
+        let path = recording_reader::annotation_path(&PathBuf::from(&directory).join(&filename));
+        match OpenOptions::new().append(true).create(true).open(&path) {
+            Ok(mut file) => {
+                let _ = writeln!(file, "{}", Annotation::SIDECAR_HEADER);
+                *annotation_file = Some((file, filename.clone()));
+            }
+            Err(e) => {
+                eprintln!("Error creating annotation file: {}", e);
+                return;
+            }
         }
-        
-        // Write each value
-        for &value in channel_data.iter() {
-            let value_f64 = value as f64;
-            if let Err(e) = file.write_all(&value_f64.to_le_bytes()) {
-                eprintln!("Error writing value to binary file: {}", e);
+    }
+
+    if let Some((ref mut file, _)) = *annotation_file {
+        for annotation in &annotations {
+            if let Err(e) = writeln!(file, "{}", annotation.sidecar_line()) {
+                eprintln!("Error writing annotation: {}", e);
                 break;
             }
         }
+        let _ = file.flush();
     }
 }
@@ -0,0 +1,738 @@
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use serde::Serialize;
+
+use crate::binary::{self, BinaryReader};
+use crate::brainvision;
+use crate::compression;
+use crate::edf;
+use crate::jsonl;
+use crate::montage;
+use crate::recording::Annotation;
+use crate::state::AppState;
+use crate::xdf;
+
+/// A sample read from a recording file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub time: SystemTime,
+    pub index: Option<u64>, // Session sample index, in the formats that store it
+    pub values: Vec<f32>,
+}
+
+impl Sample {
+    /// Milliseconds since the Unix epoch, as recordings are shown
+    pub fn millis(&self) -> u64 {
+        millis(self.time)
+    }
+}
+
+pub type Samples = Vec<Sample>;
+
+/// What a recording file keeps about how it was recorded, as far as its format has room for it
+#[derive(Debug, Clone, Default)]
+pub struct SourceInfo {
+    pub sample_rate: Option<f64>, // Nominal rate; otherwise only implied by the timestamps
+    pub device: Option<String>,
+    pub patient_id: Option<String>,   // EDF+ patient field
+    pub recording_id: Option<String>, // EDF+ recording field after the start date
+}
+
+/// Channel labels, samples and in-band annotations read from a recording file
+#[derive(Debug, Clone, Default)]
+pub struct Contents {
+    pub labels: Vec<String>,
+    pub samples: Samples,
+    pub annotations: Vec<Annotation>,
+    pub source: SourceInfo,
+}
+
+fn millis(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+/// Time `ms` milliseconds after the Unix epoch
+pub fn time_from_millis(ms: f64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs_f64(ms.max(0.0) / 1000.0)
+}
+
+/// Recordings `open_recording` keeps in memory
+const MAX_OPENED: usize = 4;
+
+/// A recording loaded into memory
+#[derive(Debug, Clone)]
+pub struct LoadedRecording {
+    pub labels: Vec<String>,
+    pub samples: Samples,
+    pub annotations: Vec<Annotation>,
+    pub source: SourceInfo,
+    pub ordered: bool, // Timestamps never decrease; those of legacy recordings may step back
+}
+
+impl LoadedRecording {
+    fn new(contents: Contents) -> Self {
+        Self {
+            ordered: in_order(&contents.samples),
+            labels: contents.labels,
+            samples: contents.samples,
+            annotations: contents.annotations,
+            source: contents.source,
+        }
+    }
+
+    /// Earliest and latest sample time: those of the first and last sample unless
+    /// the timestamps step back
+    pub fn time_range(&self) -> Option<(SystemTime, SystemTime)> {
+        if self.ordered {
+            return Some((self.samples.first()?.time, self.samples.last()?.time));
+        }
+        let times = self.samples.iter().map(|sample| sample.time);
+        Some((times.clone().min()?, times.max()?))
+    }
+}
+
+fn in_order(samples: &[Sample]) -> bool {
+    samples.windows(2).all(|pair| pair[0].time <= pair[1].time)
+}
+
+/// Where reading continues when an uncompressed recording still being written
+/// grows: the end of its last complete sample, and what the format needs to go on
+#[derive(Debug, Clone, Copy)]
+enum Resume {
+    Csv { offset: u64, indexed: bool },
+    Jsonl { offset: u64 },
+    Binary { offset: u64 },
+}
+
+impl Resume {
+    fn offset(&self) -> u64 {
+        match *self {
+            Resume::Csv { offset, .. } | Resume::Jsonl { offset } | Resume::Binary { offset } => offset,
+        }
+    }
+}
+
+/// Recording format implied by the file extension, ignoring a `.gz`/`.zst` suffix
+pub fn format_of(path: &Path) -> Result<&'static str, String> {
+    match compression::logical_path(path).extension().and_then(|e| e.to_str()) {
+        Some("csv") => Ok("csv"),
+        Some("json") => Ok("json"),
+        Some("jsonl") => Ok("jsonl"),
+        Some("bin") => Ok("binary"),
+        Some("edf") => Ok("edf"),
+        Some("bdf") => Ok("bdf"),
+        Some("vhdr") => Ok("brainvision"),
+        Some("xdf") => Ok("xdf"),
+        _ => Err(format!("Unsupported recording file: {}", path.display())),
+    }
+}
+
+/// Path of the annotation sidecar written next to a segment
+pub fn annotation_path(path: &Path) -> PathBuf {
+    let path = compression::logical_path(path);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    path.with_file_name(format!("{}.annotations.csv", stem))
+}
+
+/// Reads a recording in any of the recording formats, plain or compressed,
+/// together with its annotation sidecar.
+pub fn load_recording(path: &Path) -> Result<LoadedRecording, String> {
+    let (mut contents, _) = read_contents(path)?;
+    contents.annotations.extend(read_annotations(&annotation_path(path))?);
+    Ok(LoadedRecording::new(contents))
+}
+
+/// Reads the signal file of a recording, and where to continue when it is an
+/// uncompressed file of a format written by appending
+fn read_contents(path: &Path) -> Result<(Contents, Option<Resume>), String> {
+    let format = format_of(path)?;
+    // BrainVision spreads a recording over three files, found through the header
+    if format == "brainvision" {
+        return Ok((brainvision::read_brainvision(path)?, None));
+    }
+    let bytes = compression::read_file(path)?;
+    let (contents, resume) = match format {
+        "csv" => {
+            let (contents, resume) = read_csv(&bytes)?;
+            (contents, Some(resume))
+        }
+        "json" => (read_json(&bytes)?, None),
+        "jsonl" => {
+            let mut contents = Contents::default();
+            let read = jsonl::read_jsonl(&bytes, &mut contents)?;
+            (contents, Some(Resume::Jsonl { offset: read as u64 }))
+        }
+        "edf" | "bdf" => (edf::read_edf(&bytes)?, None),
+        "xdf" => (xdf::read_xdf(&bytes)?, None),
+        _ if binary::is_versioned(&bytes) => {
+            let mut reader = BinaryReader::new(bytes.as_slice())?;
+            let header = reader.header();
+            let mut contents = Contents {
+                labels: header.channels.iter().map(|c| c.label.clone()).collect(),
+                source: SourceInfo {
+                    sample_rate: Some(header.sample_rate),
+                    device: Some(header.device.clone()).filter(|d| !d.is_empty()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            read_chunks(&mut reader, &mut contents.samples)?;
+            (contents, Some(Resume::Binary { offset: reader.position() }))
+        }
+        _ => (read_binary(&bytes)?, None),
+    };
+    let compressed = compression::logical_path(path) != path;
+    Ok((contents, resume.filter(|_| !compressed)))
+}
+
+/// Adds the samples and in-band annotations written to a recording since
+/// `resume` and returns where to continue next time
+fn read_appended(path: &Path, resume: Resume, recording: &mut LoadedRecording) -> Result<Resume, String> {
+    let mut file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let length = file.metadata().map_err(|e| format!("Failed to read {}: {}", path.display(), e))?.len();
+    if length < resume.offset() {
+        return Err(format!("{} was replaced", path.display()));
+    }
+    let mut appended = || {
+        let mut bytes = Vec::new();
+        file.seek(SeekFrom::Start(resume.offset()))
+            .and_then(|_| file.read_to_end(&mut bytes))
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Ok::<_, String>(bytes)
+    };
+    Ok(match resume {
+        Resume::Csv { offset, indexed } => {
+            let read = read_csv_rows(&appended()?, recording.labels.len(), indexed, &mut recording.samples)?;
+            Resume::Csv {
+                offset: offset + read as u64,
+                indexed,
+            }
+        }
+        Resume::Jsonl { offset } => {
+            let mut contents = Contents::default();
+            let read = jsonl::read_jsonl(&appended()?, &mut contents)?;
+            recording.samples.extend(contents.samples);
+            recording.annotations.extend(contents.annotations);
+            Resume::Jsonl {
+                offset: offset + read as u64,
+            }
+        }
+        Resume::Binary { offset } => {
+            let mut reader = BinaryReader::new(file)?;
+            reader.seek(offset)?;
+            read_chunks(&mut reader, &mut recording.samples)?;
+            Resume::Binary {
+                offset: reader.position(),
+            }
+        }
+    })
+}
+
+fn read_csv(bytes: &[u8]) -> Result<(Contents, Resume), String> {
+    let header_end = bytes.iter().position(|&b| b == b'\n').map(|p| p + 1).unwrap_or(bytes.len());
+    let header = String::from_utf8_lossy(&bytes[..header_end]);
+    if header.trim().is_empty() {
+        return Err("Empty CSV recording".to_string());
+    }
+    let mut labels: Vec<String> = csv_fields(header.trim_end()).into_iter().skip(1).collect();
+    // Recordings since the session clock carry the sample index after the channels
+    let indexed = labels.last().map(|c| c == "sample_index").unwrap_or(false);
+    if indexed {
+        labels.pop();
+    }
+
+    let mut samples = Vec::new();
+    let read = read_csv_rows(&bytes[header_end..], labels.len(), indexed, &mut samples)?;
+    let offset = (header_end + read) as u64;
+    let contents = Contents {
+        labels,
+        samples,
+        ..Default::default()
+    };
+    Ok((contents, Resume::Csv { offset, indexed }))
+}
+
+/// Adds the complete rows of `bytes` to `samples` and returns the bytes they
+/// take. A last line without its line break is still being written, or was cut
+/// off by an interrupted recording, and is left unread.
+fn read_csv_rows(bytes: &[u8], channels: usize, indexed: bool, samples: &mut Samples) -> Result<usize, String> {
+    let mut read = 0;
+    for (number, line) in bytes.split_inclusive(|&b| b == b'\n').enumerate() {
+        if !line.ends_with(b"\n") {
+            break;
+        }
+        let text = String::from_utf8_lossy(line);
+        if !text.trim().is_empty() {
+            let mut fields: Vec<&str> = text.trim_end().split(',').map(str::trim).collect();
+            let timestamp = fields
+                .first()
+                .and_then(|t| t.parse::<u64>().ok())
+                .ok_or_else(|| format!("Invalid timestamp in CSV row {}", number + 1))?;
+            if fields.len() != 1 + channels + indexed as usize {
+                break;
+            }
+            let index = if indexed { fields.pop().and_then(|i| i.parse().ok()) } else { None };
+            samples.push(Sample {
+                time: SystemTime::UNIX_EPOCH + Duration::from_millis(timestamp),
+                index,
+                values: fields[1..].iter().map(|v| v.parse().unwrap_or(f32::NAN)).collect(),
+            });
+        }
+        read += line.len();
+    }
+    Ok(read)
+}
+
+/// Fields of a CSV line, unquoting RFC 4180 quoted fields
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            ('"', true) => quoted = false,
+            ('"', false) if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            }
+            (',', false) => fields.push(std::mem::take(&mut field).trim().to_string()),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field.trim().to_string());
+    fields
+}
+
+fn read_json(bytes: &[u8]) -> Result<Contents, String> {
+    let samples = match serde_json::from_slice::<Vec<serde_json::Value>>(bytes) {
+        Ok(entries) => entries
+            .iter()
+            .map(|entry| jsonl::sample_from(entry).ok_or("JSON entry without timestamp or values"))
+            .collect::<Result<Vec<_>, _>>()?,
+        // An unfinished array is what an interrupted recording leaves behind
+        Err(_) => jsonl::read_json_entries(bytes),
+    };
+    Ok(Contents {
+        labels: channel_labels(&samples),
+        samples,
+        ..Default::default()
+    })
+}
+
+fn read_binary(bytes: &[u8]) -> Result<Contents, String> {
+    // Version 1: headerless `u64 ms, u32 count, f64 values` samples
+    let mut samples = Vec::new();
+    let mut offset = 0;
+    while offset + 12 <= bytes.len() {
+        let timestamp = u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+        let count = u32::from_le_bytes(bytes[offset + 8..offset + 12].try_into().unwrap()) as usize;
+        let end = offset + 12 + count * 8;
+        if end > bytes.len() {
+            break; // Truncated final sample
+        }
+        let values = bytes[offset + 12..end]
+            .chunks_exact(8)
+            .map(|v| f64::from_le_bytes(v.try_into().unwrap()) as f32)
+            .collect();
+        samples.push(Sample {
+            time: SystemTime::UNIX_EPOCH + Duration::from_millis(timestamp),
+            index: None,
+            values,
+        });
+        offset = end;
+    }
+    Ok(Contents {
+        labels: channel_labels(&samples),
+        samples,
+        ..Default::default()
+    })
+}
+
+/// Adds the samples of the remaining chunks of a versioned binary recording
+fn read_chunks<R: Read>(reader: &mut BinaryReader<R>, samples: &mut Samples) -> Result<(), String> {
+    let skipped = reader.skipped_bytes();
+    let mut next_index = None;
+    while let Some(chunk) = reader.next_chunk()? {
+        // The session clock skips indices over samples lost during acquisition
+        if let (Some(expected), Some(first)) = (next_index, chunk.first_index) {
+            if first != expected {
+                eprintln!("[Recording] {} samples missing before sample index {}", first.saturating_sub(expected), first);
+            }
+        }
+        next_index = chunk.first_index.map(|first| first + chunk.rows.len() as u64);
+        let timestamps = chunk.timestamps_us();
+        samples.extend(timestamps.into_iter().zip(chunk.rows).enumerate().map(|(i, (us, values))| Sample {
+            time: SystemTime::UNIX_EPOCH + Duration::from_micros(us),
+            index: chunk.first_index.map(|first| first + i as u64),
+            values,
+        }));
+    }
+    if reader.skipped_bytes() > skipped {
+        eprintln!("[Recording] Skipped {} bytes of corrupt binary data", reader.skipped_bytes() - skipped);
+    }
+    Ok(())
+}
+
+/// json and version 1 binary recordings carry no labels; name channels by position
+fn channel_labels(samples: &[Sample]) -> Vec<String> {
+    let count = samples.first().map(|sample| sample.values.len()).unwrap_or(montage::RAW_CHANNELS);
+    (0..count).map(montage::default_label).collect()
+}
+
+/// Parses an `onset_ms,duration_ms,channel,label` sidecar; a missing file means no annotations.
+pub fn read_annotations(path: &Path) -> Result<Vec<Annotation>, String> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let text = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let mut annotations = Vec::new();
+    for line in text.lines() {
+        let mut fields = line.splitn(4, ',');
+        let (Some(onset), Some(duration), Some(channel), Some(label)) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        // Header lines repeat whenever the sidecar is reopened
+        let Ok(onset_ms) = onset.parse::<u64>() else {
+            continue;
+        };
+        let label = label.trim();
+        let label = label
+            .strip_prefix('"')
+            .and_then(|l| l.strip_suffix('"'))
+            .unwrap_or(label)
+            .replace("\"\"", "\"");
+        annotations.push(Annotation {
+            onset_ms,
+            duration_ms: duration.parse().unwrap_or(0.0),
+            channel: channel.parse().ok(),
+            label,
+        });
+    }
+    Ok(annotations)
+}
+
+/// Mean sample rate implied by the sample times, rounded to whole hertz when close
+pub fn estimate_sample_rate(samples: &[Sample]) -> Option<f64> {
+    let (first, last) = (samples.first()?, samples.last()?);
+    let seconds = last.time.duration_since(first.time).ok()?.as_secs_f64();
+    // Stored indices count the samples lost in gaps as well
+    let periods = match (first.index, last.index) {
+        (Some(first), Some(last)) if last > first => last - first,
+        _ => samples.len() as u64 - 1,
+    };
+    if periods == 0 || seconds <= 0.0 {
+        return None;
+    }
+    let rate = periods as f64 / seconds;
+    let rounded = rate.round();
+    Some(if (rate - rounded).abs() / rounded.max(1.0) < 0.002 { rounded } else { rate })
+}
+
+/// Summary of a stored recording
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingMetadata {
+    pub path: String,
+    pub format: String,
+    pub labels: Vec<String>,
+    pub channels: usize,
+    pub samples: usize,
+    pub start_ms: Option<u64>, // Timestamp of the first sample, milliseconds since the Unix epoch
+    pub end_ms: Option<u64>,   // Timestamp of the last sample
+    pub duration_s: f64,       // Through the end of the last sample period
+    pub sample_rate: Option<f64>,
+    pub annotations: usize,
+    pub size_bytes: u64, // All files of the recording, including sidecars
+}
+
+/// Samples of one time window of a stored recording, laid out for plotting
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingWindow {
+    pub start_ms: u64, // Requested window, milliseconds since the Unix epoch
+    pub end_ms: u64,
+    pub labels: Vec<String>,          // Labels of the returned channels
+    pub timestamps: Vec<u64>,         // Milliseconds since the Unix epoch
+    pub values: Vec<Vec<f32>>,        // One series per returned channel, aligned with `timestamps`
+    pub step: usize,                  // Every `step`-th sample is returned to stay within the point limit
+    pub annotations: Vec<Annotation>, // Annotations with an onset inside the window
+}
+
+/// Recording files that belong to `path`: the files sharing its name, like
+/// BrainVision data and marker files and annotation sidecars
+pub fn recording_files(path: &Path) -> Vec<(PathBuf, fs::Metadata)> {
+    let logical = compression::logical_path(path);
+    let (Some(stem), Some(directory)) = (logical.file_stem().and_then(|s| s.to_str()), path.parent()) else {
+        return Vec::new();
+    };
+    let prefix = format!("{}.", stem);
+    fs::read_dir(directory)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+                .filter_map(|entry| entry.metadata().ok().map(|metadata| (entry.path(), metadata)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Total size and latest modification of the files of a recording; a change
+/// means the cached copy is stale (a segment still being written, for one)
+fn fingerprint(path: &Path) -> (u64, Option<SystemTime>) {
+    let files = recording_files(path);
+    let size = files.iter().map(|(_, metadata)| metadata.len()).sum();
+    let modified = files.iter().filter_map(|(_, metadata)| metadata.modified().ok()).max();
+    (size, modified)
+}
+
+/// A recording kept in memory by `open_recording`
+pub struct OpenedRecording {
+    path: PathBuf,
+    fingerprint: (u64, Option<SystemTime>),
+    recording: Arc<LoadedRecording>,
+    resume: Option<Resume>, // Set while the signal file can be read on from where it was left
+    sidecar: usize,         // Annotations at the end of `recording` read from the sidecar
+}
+
+impl OpenedRecording {
+    fn load(path: &Path, fingerprint: (u64, Option<SystemTime>)) -> Result<Self, String> {
+        let (mut contents, resume) = read_contents(path)?;
+        let sidecar = read_annotations(&annotation_path(path))?;
+        let sidecar_count = sidecar.len();
+        contents.annotations.extend(sidecar);
+        Ok(Self {
+            path: path.to_path_buf(),
+            fingerprint,
+            recording: Arc::new(LoadedRecording::new(contents)),
+            resume,
+            sidecar: sidecar_count,
+        })
+    }
+
+    /// Catches up with a recording that is still being written: only what was
+    /// appended to the signal file is read, the sidecar is read again
+    fn refresh(mut self, fingerprint: (u64, Option<SystemTime>)) -> Result<Self, String> {
+        let Some(resume) = self.resume else {
+            return Self::load(&self.path, fingerprint);
+        };
+        let recording = Arc::make_mut(&mut self.recording);
+        recording.annotations.truncate(recording.annotations.len() - self.sidecar);
+        let known = recording.samples.len();
+        let resume = read_appended(&self.path, resume, recording)?;
+        let appended = &recording.samples[known.saturating_sub(1)..];
+        recording.ordered = recording.ordered && in_order(appended);
+        let sidecar = read_annotations(&annotation_path(&self.path))?;
+        self.sidecar = sidecar.len();
+        recording.annotations.extend(sidecar);
+        self.resume = Some(resume);
+        self.fingerprint = fingerprint;
+        Ok(self)
+    }
+}
+
+/// Loads a recording, reusing one opened before while its files are unchanged
+/// and reading only what was appended to one still being written, so scrolling
+/// through a file does not parse it again for every window
+pub fn open_recording(state: &AppState, path: &Path) -> Result<Arc<LoadedRecording>, String> {
+    if !path.is_file() {
+        return Err(format!("Recording {} not found", path.display()));
+    }
+    let fingerprint = fingerprint(path);
+    let cached = {
+        let mut opened = state.reader.opened.lock().unwrap();
+        opened.iter().position(|o| o.path == path).map(|i| opened.remove(i))
+    };
+    let opened = match cached {
+        Some(opened) if opened.fingerprint == fingerprint => opened,
+        Some(opened) => opened.refresh(fingerprint).or_else(|e| {
+            eprintln!("[Recording] Reading {} again: {}", path.display(), e);
+            OpenedRecording::load(path, fingerprint)
+        })?,
+        None => OpenedRecording::load(path, fingerprint)?,
+    };
+    let recording = opened.recording.clone();
+    // Most recently used last
+    let mut cache = state.reader.opened.lock().unwrap();
+    cache.retain(|o| o.path != path);
+    cache.push(opened);
+    if cache.len() > MAX_OPENED {
+        cache.remove(0);
+    }
+    Ok(recording)
+}
+
+pub fn recording_metadata(path: &Path, recording: &LoadedRecording) -> Result<RecordingMetadata, String> {
+    let sample_rate = recording.source.sample_rate.or_else(|| estimate_sample_rate(&recording.samples));
+    let range = recording.time_range();
+    let period = sample_rate.map(|rate| 1.0 / rate).unwrap_or(0.0);
+    let duration_s = match range {
+        Some((start, end)) => end.duration_since(start).unwrap_or_default().as_secs_f64() + period,
+        None => 0.0,
+    };
+    Ok(RecordingMetadata {
+        path: path.to_string_lossy().into_owned(),
+        format: format_of(path)?.to_string(),
+        labels: recording.labels.clone(),
+        channels: recording.labels.len(),
+        samples: recording.samples.len(),
+        start_ms: range.map(|(start, _)| millis(start)),
+        end_ms: range.map(|(_, end)| millis(end)),
+        duration_s,
+        sample_rate,
+        annotations: recording.annotations.len(),
+        size_bytes: fingerprint(path).0,
+    })
+}
+
+/// Samples from `offset_s` seconds after the first sample for `duration_s`
+/// seconds, of the listed channels (all when None), with at most `max_points`
+/// samples per channel.
+pub fn read_window(
+    recording: &LoadedRecording,
+    offset_s: f64,
+    duration_s: f64,
+    channels: Option<&[usize]>,
+    max_points: Option<usize>,
+) -> Result<RecordingWindow, String> {
+    if !offset_s.is_finite() || offset_s < 0.0 {
+        return Err("Window offset must not be negative".to_string());
+    }
+    if !duration_s.is_finite() || duration_s <= 0.0 {
+        return Err("Window duration must be positive".to_string());
+    }
+    if max_points == Some(0) {
+        return Err("Point limit must be positive".to_string());
+    }
+    let channels: Vec<usize> = match channels {
+        Some(channels) => {
+            if let Some(&channel) = channels.iter().find(|&&c| c >= recording.labels.len()) {
+                return Err(format!("Channel {} is not in the recording", channel));
+            }
+            channels.to_vec()
+        }
+        None => (0..recording.labels.len()).collect(),
+    };
+
+    let first = recording.time_range().map(|(start, _)| start).unwrap_or(SystemTime::UNIX_EPOCH);
+    let start = first + Duration::from_secs_f64(offset_s);
+    let end = start + Duration::from_secs_f64(duration_s);
+    let window: Vec<&Sample> = if recording.ordered {
+        // Samples in time order are found by binary search
+        let from = recording.samples.partition_point(|sample| sample.time < start);
+        let to = recording.samples.partition_point(|sample| sample.time < end);
+        recording.samples[from..to].iter().collect()
+    } else {
+        recording.samples.iter().filter(|sample| (start..end).contains(&sample.time)).collect()
+    };
+    let step = max_points.map(|max| window.len().div_ceil(max)).unwrap_or(1).max(1);
+
+    let picked: Vec<&Sample> = window.into_iter().step_by(step).collect();
+    let values = channels
+        .iter()
+        .map(|&channel| picked.iter().map(|sample| sample.values.get(channel).copied().unwrap_or(f32::NAN)).collect())
+        .collect();
+    let (start_ms, end_ms) = (millis(start), millis(end));
+    Ok(RecordingWindow {
+        start_ms,
+        end_ms,
+        labels: channels.iter().map(|&c| recording.labels[c].clone()).collect(),
+        timestamps: picked.iter().map(|sample| sample.millis()).collect(),
+        values,
+        step,
+        annotations: recording
+            .annotations
+            .iter()
+            .filter(|a| a.onset_ms >= start_ms && a.onset_ms < end_ms)
+            .cloned()
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{millis, rows, temp_dir};
+    use crate::writer::{self, RecordingInfo};
+
+    fn sample(ms: u64, index: Option<u64>, value: f32) -> Sample {
+        Sample {
+            time: SystemTime::UNIX_EPOCH + Duration::from_millis(ms),
+            index,
+            values: vec![value],
+        }
+    }
+
+    #[test]
+    fn reads_on_where_growing_recordings_left_off() {
+        let rows = rows(600, 2, 100.0);
+        let info = RecordingInfo {
+            labels: vec!["C3".to_string(), "C4".to_string()],
+            sample_rate: 100.0,
+            ..Default::default()
+        };
+        for format in ["csv", "jsonl", "binary"] {
+            let path = temp_dir("reader").join(writer::file_name("recording", format, &info.compression));
+            let mut writer = writer::create_writer(format, &path, &info).unwrap();
+            writer.write_samples(&rows[..250]).unwrap();
+            let mut opened = OpenedRecording::load(&path, fingerprint(&path)).unwrap();
+            assert!(opened.resume.is_some(), "{}", format);
+
+            writer.write_samples(&rows[250..]).unwrap();
+            writer.finish().unwrap();
+            opened = opened.refresh(fingerprint(&path)).unwrap();
+            let samples = &opened.recording.samples;
+            assert_eq!(samples.len(), rows.len(), "{}", format);
+            for (sample, (index, time, row)) in samples.iter().zip(&rows) {
+                assert_eq!((&sample.values, sample.index), (row, Some(*index)), "{}", format);
+                assert_eq!(sample.millis(), millis(*time), "{}", format);
+            }
+            assert!(opened.recording.ordered);
+        }
+    }
+
+    #[test]
+    fn csv_lines_still_being_written_are_left_for_later() {
+        let path = temp_dir("reader").join("recording.csv");
+        fs::write(&path, "timestamp,C3,sample_index\n1000,1.5,0\n1004,2.5,1\n1008,3").unwrap();
+        let mut opened = OpenedRecording::load(&path, fingerprint(&path)).unwrap();
+        assert_eq!(opened.recording.samples, vec![sample(1000, Some(0), 1.5), sample(1004, Some(1), 2.5)]);
+
+        fs::write(&path, "timestamp,C3,sample_index\n1000,1.5,0\n1004,2.5,1\n1008,3.5,2\n").unwrap();
+        opened = opened.refresh(fingerprint(&path)).unwrap();
+        assert_eq!(opened.recording.samples.last(), Some(&sample(1008, Some(2), 3.5)));
+        assert_eq!(opened.recording.samples.len(), 3);
+    }
+
+    #[test]
+    fn timestamps_stepping_back_are_windowed_in_full() {
+        let recording = LoadedRecording::new(Contents {
+            labels: vec!["C3".to_string()],
+            samples: vec![
+                sample(5000, None, 1.0),
+                sample(1000, None, 2.0),
+                sample(3000, None, 3.0),
+                sample(2000, None, 4.0),
+            ],
+            ..Default::default()
+        });
+        assert!(!recording.ordered);
+
+        let metadata = recording_metadata(Path::new("legacy.csv"), &recording).unwrap();
+        assert_eq!((metadata.start_ms, metadata.end_ms), (Some(1000), Some(5000)));
+        assert!(metadata.duration_s >= 4.0);
+
+        let window = read_window(&recording, 1.0, 2.0, None, None).unwrap();
+        assert_eq!(window.timestamps, vec![3000, 2000]);
+        assert_eq!(window.values, vec![vec![3.0, 4.0]]);
+    }
+}
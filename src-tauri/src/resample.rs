@@ -0,0 +1,206 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+/// Largest interpolation factor accepted for a rate conversion
+const MAX_FACTOR: u64 = 1024;
+
+/// Output-rate settings for recordings and the UDP sample outputs; the live
+/// stream shown in the app keeps the device rate.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResampleConfig {
+    pub enabled: bool,
+    pub target_rate: f64,       // Output sample rate in Hz
+    pub taps_per_phase: usize,  // Filter length per polyphase branch; longer is sharper
+}
+
+impl Default for ResampleConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            target_rate: 250.0,
+            taps_per_phase: 32,
+        }
+    }
+}
+
+impl ResampleConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.target_rate.is_finite() || self.target_rate <= 0.0 {
+            return Err("Target sample rate must be positive".to_string());
+        }
+        if !(4..=256).contains(&self.taps_per_phase) {
+            return Err("Taps per phase must be between 4 and 256".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Reduce `target / source` to a rational `up / down`, allowing millihertz rates
+fn rational_ratio(source: f64, target: f64) -> Result<(usize, usize), String> {
+    let source_mhz = (source * 1000.0).round() as u64;
+    let target_mhz = (target * 1000.0).round() as u64;
+    if source_mhz == 0 || target_mhz == 0 {
+        return Err("Sample rates must be positive".to_string());
+    }
+    let divisor = gcd(source_mhz, target_mhz);
+    let (up, down) = (target_mhz / divisor, source_mhz / divisor);
+    if up > MAX_FACTOR || down > MAX_FACTOR * 16 {
+        return Err(format!("Cannot resample {} Hz to {} Hz with a rational factor", source, target));
+    }
+    Ok((up as usize, down as usize))
+}
+
+/// Windowed-sinc low-pass prototype with a Blackman window
+fn design_lowpass(len: usize, cutoff: f64) -> Vec<f64> {
+    let center = (len - 1) as f64 / 2.0;
+    (0..len)
+        .map(|i| {
+            let x = i as f64 - center;
+            let sinc = if x == 0.0 {
+                2.0 * cutoff
+            } else {
+                (2.0 * std::f64::consts::PI * cutoff * x).sin() / (std::f64::consts::PI * x)
+            };
+            let w = 2.0 * std::f64::consts::PI * i as f64 / (len - 1) as f64;
+            sinc * (0.42 - 0.5 * w.cos() + 0.08 * (2.0 * w).cos())
+        })
+        .collect()
+}
+
+/// Streaming anti-aliased polyphase resampler over multi-channel rows
+pub struct Resampler {
+    up: usize,
+    down: usize,
+    target_rate: f64,
+    phases: Vec<Vec<f32>>,        // phases[p][k] = h[p + k * up] * up
+    history: VecDeque<Vec<f32>>,  // Most recent input row first
+    input_index: u64,
+    next_output: u64,             // Upsampled-domain time of the next output sample
+    output_index: u64,
+}
+
+impl Resampler {
+    pub fn new(source_rate: f64, config: &ResampleConfig) -> Result<Self, String> {
+        config.validate()?;
+        let (up, down) = rational_ratio(source_rate, config.target_rate)?;
+        let taps = config.taps_per_phase;
+
+        // Cut off below the lower Nyquist frequency, relative to the upsampled rate
+        let cutoff = 0.45 / up.max(down) as f64;
+        let prototype = design_lowpass(up * taps, cutoff);
+        let phases = (0..up)
+            .map(|p| {
+                (0..taps)
+                    .map(|k| (prototype[p + k * up] * up as f64) as f32)
+                    .collect()
+            })
+            .collect();
+
+        Ok(Self {
+            up,
+            down,
+            target_rate: config.target_rate,
+            phases,
+            history: VecDeque::with_capacity(taps),
+            input_index: 0,
+            next_output: 0,
+            output_index: 0,
+        })
+    }
+
+    pub fn target_rate(&self) -> f64 {
+        self.target_rate
+    }
+
+    /// Group delay of the linear-phase filter in seconds: output `n` represents
+    /// the input at `n / target_rate` minus this delay
+    pub fn delay_s(&self) -> f64 {
+        let taps = self.up * self.phases[0].len();
+        // The upsampled domain runs at target_rate * down
+        (taps - 1) as f64 / 2.0 / (self.target_rate * self.down as f64)
+    }
+
+    /// Feed one input row; returns the output rows it completes with their output indices
+    pub fn process(&mut self, row: &[f32]) -> Vec<(u64, Vec<f32>)> {
+        let taps = self.phases[0].len();
+        if self.history.len() >= taps {
+            self.history.pop_back();
+        }
+        self.history.push_front(row.to_vec());
+
+        let mut outputs = Vec::new();
+        let window_end = (self.input_index + 1) * self.up as u64;
+        while self.next_output < window_end {
+            let phase = &self.phases[(self.next_output % self.up as u64) as usize];
+            let mut out = vec![0f32; row.len()];
+            for (coefficient, input) in phase.iter().zip(self.history.iter()) {
+                for (o, x) in out.iter_mut().zip(input.iter()) {
+                    *o += coefficient * x;
+                }
+            }
+            outputs.push((self.output_index, out));
+            self.output_index += 1;
+            self.next_output += self.down as u64;
+        }
+        self.input_index += 1;
+        outputs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resampler(source: f64, target: f64) -> Resampler {
+        let config = ResampleConfig {
+            enabled: true,
+            target_rate: target,
+            ..Default::default()
+        };
+        Resampler::new(source, &config).unwrap()
+    }
+
+    #[test]
+    fn reduces_rate_ratios() {
+        assert_eq!(rational_ratio(500.0, 250.0), Ok((1, 2)));
+        assert_eq!(rational_ratio(250.0, 1000.0), Ok((4, 1)));
+        assert_eq!(rational_ratio(256.0, 250.0), Ok((125, 128)));
+        assert!(rational_ratio(1000.0, 999.123).is_err());
+    }
+
+    #[test]
+    fn keeps_dc_gain_and_output_count() {
+        let mut resampler = resampler(500.0, 250.0);
+        let outputs: Vec<_> = (0..1000).flat_map(|_| resampler.process(&[1.0])).collect();
+        assert_eq!(outputs.len(), 500);
+        for (_, row) in &outputs[100..] {
+            assert!((row[0] - 1.0).abs() < 1e-3, "{}", row[0]);
+        }
+    }
+
+    #[test]
+    fn delay_aligns_outputs_with_their_input() {
+        for (source, target) in [(500.0, 250.0), (250.0, 1000.0), (1000.0, 256.0)] {
+            let mut resampler = resampler(source, target);
+            let step_at = 1.0; // Seconds
+            let outputs: Vec<_> = (0..(2.0 * source) as usize)
+                .flat_map(|i| resampler.process(&[if i as f64 / source >= step_at { 1.0 } else { 0.0 }]))
+                .collect();
+            let (index, _) = outputs.iter().find(|(_, row)| row[0] >= 0.5).unwrap();
+            let time = *index as f64 / target - resampler.delay_s();
+            // The step sits between two input samples, so its half point is half a period early
+            let expected = step_at - 0.5 / source;
+            assert!((time - expected).abs() <= 1.0 / target, "{} -> {}: {} vs {}", source, target, time, expected);
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+use chrono::{DateTime, Local, Timelike};
+use serde::{Deserialize, Serialize};
+
+use crate::compression;
+
+/// Segment size is checked on disk at most this often
+const SIZE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// When to start a new segment, in addition to the maximum duration given to `start_recording`.
+/// Limits left out are not applied.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RotationConfig {
+    pub max_bytes: Option<u64>,              // Segment size on disk, checked about once a second
+    pub max_samples: Option<u64>,            // Samples per segment, exact
+    pub clock_interval_minutes: Option<u32>, // Rotate at local clock multiples, e.g. 60 for the top of every hour
+}
+
+impl RotationConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_bytes == Some(0) {
+            return Err("Maximum segment size must be positive".to_string());
+        }
+        if self.max_samples == Some(0) {
+            return Err("Maximum samples per segment must be positive".to_string());
+        }
+        if let Some(minutes) = self.clock_interval_minutes {
+            // Intervals must divide the day so every day has the same boundaries
+            if minutes == 0 || 24 * 60 % minutes != 0 {
+                return Err("Clock interval must be a divisor of 1440 minutes".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// First local clock multiple of `minutes` after `time`
+fn next_clock_boundary(time: SystemTime, minutes: u32) -> SystemTime {
+    let local: DateTime<Local> = time.into();
+    let interval = minutes as u64 * 60;
+    let into_interval = Duration::new(
+        local.num_seconds_from_midnight() as u64 % interval,
+        local.nanosecond() % 1_000_000_000, // Leap seconds are reported as nanoseconds past 1e9
+    );
+    time - into_interval + Duration::from_secs(interval)
+}
+
+/// Size of a segment on disk, including the files that share its name
+/// (BrainVision data and marker files, annotation sidecars)
+fn segment_size(directory: &Path, filename: &str) -> u64 {
+    let logical = compression::logical_path(Path::new(filename));
+    let Some(stem) = logical.file_stem().and_then(|s| s.to_str()) else {
+        return 0;
+    };
+    let prefix = format!("{}.", stem);
+    fs::read_dir(directory)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+                .filter_map(|entry| entry.metadata().ok())
+                .map(|metadata| metadata.len())
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Progress of the segment being written, measured against the rotation limits
+pub struct SegmentLimits {
+    config: RotationConfig,
+    samples: u64,
+    boundary: Option<SystemTime>, // Rows stamped at or after this go to the next segment
+    size_checked: Instant,
+}
+
+impl SegmentLimits {
+    pub fn new(config: RotationConfig) -> Self {
+        Self {
+            boundary: None,
+            config,
+            samples: 0,
+            size_checked: Instant::now(),
+        }
+    }
+
+    /// Number of leading rows, stamped with `timestamps`, that still belong to the
+    /// current segment. Less than the number of rows means rotate after writing them.
+    pub fn rows_to_write(&mut self, timestamps: impl Iterator<Item = SystemTime>) -> usize {
+        let mut timestamps = timestamps.peekable();
+        // The clock boundary follows the first row of the segment rather than the wall clock,
+        // which runs ahead of rows that were queued or held before a trigger
+        if let (None, Some(minutes), Some(first)) =
+            (self.boundary, self.config.clock_interval_minutes, timestamps.peek())
+        {
+            self.boundary = Some(next_clock_boundary(*first, minutes));
+        }
+        let room = self
+            .config
+            .max_samples
+            .map(|max| max.saturating_sub(self.samples) as usize)
+            .unwrap_or(usize::MAX);
+        let mut count = 0;
+        for timestamp in timestamps {
+            if count == room || self.boundary.map(|b| timestamp >= b).unwrap_or(false) {
+                break;
+            }
+            count += 1;
+        }
+        count
+    }
+
+    /// Counts rows written to the segment
+    pub fn add(&mut self, rows: usize) {
+        self.samples += rows as u64;
+    }
+
+    /// Whether the segment is complete; only called between rows
+    pub fn is_full(&mut self, directory: &Path, filename: &str) -> bool {
+        if self.config.max_samples.map(|max| self.samples >= max).unwrap_or(false) {
+            return true;
+        }
+        if let Some(max_bytes) = self.config.max_bytes {
+            if self.size_checked.elapsed() >= SIZE_CHECK_INTERVAL {
+                self.size_checked = Instant::now();
+                return segment_size(directory, filename) >= max_bytes;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_dir;
+
+    fn config(max_bytes: Option<u64>, max_samples: Option<u64>, clock_interval_minutes: Option<u32>) -> RotationConfig {
+        RotationConfig {
+            max_bytes,
+            max_samples,
+            clock_interval_minutes,
+        }
+    }
+
+    #[test]
+    fn validates_limits() {
+        assert!(config(None, None, None).validate().is_ok());
+        assert!(config(Some(1), Some(1), Some(90)).validate().is_ok());
+        assert!(config(Some(0), None, None).validate().is_err());
+        assert!(config(None, Some(0), None).validate().is_err());
+        for minutes in [0, 7, 1441] {
+            assert!(config(None, None, Some(minutes)).validate().is_err(), "{}", minutes);
+        }
+    }
+
+    #[test]
+    fn clock_boundaries_fall_on_local_multiples() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_123_456);
+        for minutes in [1, 15, 60, 1440] {
+            let boundary = next_clock_boundary(start, minutes);
+            let local: DateTime<Local> = boundary.into();
+            assert_eq!(local.num_seconds_from_midnight() % (minutes * 60), 0, "{}", minutes);
+            assert_eq!(local.nanosecond(), 0);
+            assert!(boundary > start && boundary <= start + Duration::from_secs(minutes as u64 * 60));
+            // A time on a boundary rotates at the next one
+            assert_eq!(next_clock_boundary(boundary, minutes), boundary + Duration::from_secs(minutes as u64 * 60));
+        }
+    }
+
+    #[test]
+    fn sample_limits_split_batches_exactly() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let times = |count: usize| (0..count).map(move |i| start + Duration::from_millis(i as u64 * 4));
+        let mut limits = SegmentLimits::new(config(None, Some(100), None));
+        assert_eq!(limits.rows_to_write(times(60)), 60);
+        limits.add(60);
+        assert!(!limits.is_full(Path::new("."), "unused.csv"));
+        assert_eq!(limits.rows_to_write(times(60)), 40);
+        limits.add(40);
+        assert!(limits.is_full(Path::new("."), "unused.csv"));
+        assert_eq!(limits.rows_to_write(times(60)), 0);
+    }
+
+    #[test]
+    fn clock_limits_keep_rows_before_the_boundary() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let times = |from: u64| (from..40_000).map(move |i| start + Duration::from_millis(i * 4));
+        let mut limits = SegmentLimits::new(config(None, None, Some(1)));
+        let boundary = next_clock_boundary(start, 1);
+        let count = limits.rows_to_write(times(0));
+        assert!(start + Duration::from_millis(count as u64 * 4) >= boundary);
+        assert!(start + Duration::from_millis((count as u64 - 1) * 4) < boundary);
+        // The next segment starts on the boundary and spans a whole minute of rows
+        let mut next = SegmentLimits::new(config(None, None, Some(1)));
+        assert_eq!(next.rows_to_write(times(count as u64)), 15_000);
+        // Without limits every row is written
+        let mut unlimited = SegmentLimits::new(RotationConfig::default());
+        assert_eq!(unlimited.rows_to_write((0..20_000).map(|i| start + Duration::from_millis(i * 4))), 20_000);
+    }
+
+    #[test]
+    fn size_limits_count_the_files_sharing_the_segment_name() {
+        let directory = temp_dir("rotation");
+        fs::write(directory.join("segment.csv.gz"), [0u8; 60]).unwrap();
+        fs::write(directory.join("segment.annotations.json"), [0u8; 50]).unwrap();
+        fs::write(directory.join("segment2.csv"), [0u8; 500]).unwrap();
+        assert_eq!(segment_size(&directory, "segment.csv.gz"), 110);
+
+        let mut limits = SegmentLimits::new(config(Some(100), None, None));
+        // Size is only checked once the check interval has passed
+        assert!(!limits.is_full(&directory, "segment.csv.gz"));
+        limits.size_checked -= SIZE_CHECK_INTERVAL;
+        assert!(limits.is_full(&directory, "segment.csv.gz"));
+
+        let mut limits = SegmentLimits::new(config(Some(200), None, None));
+        limits.size_checked -= SIZE_CHECK_INTERVAL;
+        assert!(!limits.is_full(&directory, "segment.csv.gz"));
+    }
+}
@@ -0,0 +1,175 @@
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use crate::resample::{ResampleConfig, Resampler};
+
+/// Socket bound for sending to resolved UDP targets
+pub type UdpTargets = (UdpSocket, Vec<SocketAddr>);
+
+/// Resolves "host:port" targets and binds a socket for them; None without targets
+pub fn open_udp_targets(targets: &[String], kind: &str) -> Result<Option<UdpTargets>, String> {
+    let mut addresses = Vec::new();
+    for target in targets {
+        let resolved = target
+            .to_socket_addrs()
+            .map_err(|e| format!("Invalid {} output {}: {}", kind, target, e))?
+            .next()
+            .ok_or_else(|| format!("Invalid {} output {}: no address found", kind, target))?;
+        addresses.push(resolved);
+    }
+    if addresses.is_empty() {
+        return Ok(None);
+    }
+    let socket = UdpSocket::bind(("0.0.0.0", 0)).map_err(|e| format!("Failed to open {} socket: {}", kind, e))?;
+    Ok(Some((socket, addresses)))
+}
+
+/// One datagram of the sample output
+#[derive(Debug, Serialize)]
+struct SamplePacket<'a> {
+    sample_index: u64, // Index at `sample_rate`, counted from when the output was (re)configured
+    sample_rate: f64,
+    values: &'a [f32],
+}
+
+/// Rate conversion of the sample output, rebuilt when the source rate or the settings change
+struct Stage {
+    source_rate: f64,
+    resampler: Option<Resampler>,
+    next_index: u64, // Index of the next sample sent at the source rate
+}
+
+impl Stage {
+    fn new(source_rate: f64, config: &ResampleConfig) -> Self {
+        let resampler = if config.enabled && (config.target_rate - source_rate).abs() > f64::EPSILON {
+            match Resampler::new(source_rate, config) {
+                Ok(resampler) => Some(resampler),
+                Err(e) => {
+                    eprintln!("[SampleOutput] Sending at {} Hz, cannot resample: {}", source_rate, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        Self {
+            source_rate,
+            resampler,
+            next_index: 0,
+        }
+    }
+}
+
+/// Sends the display channels as JSON datagrams to UDP targets, converted to
+/// the configured output rate when resampling is enabled
+pub struct SampleOutput {
+    outputs: Mutex<Vec<String>>,       // "host:port" targets as configured
+    socket: Mutex<Option<UdpTargets>>, // Bound socket and resolved targets
+    config: Mutex<ResampleConfig>,     // Same settings as the recording output rate
+    stage: Mutex<Option<Stage>>,
+}
+
+impl SampleOutput {
+    pub fn new() -> Self {
+        Self {
+            outputs: Mutex::new(Vec::new()),
+            socket: Mutex::new(None),
+            config: Mutex::new(ResampleConfig::default()),
+            stage: Mutex::new(None),
+        }
+    }
+
+    /// Replaces the UDP outputs; every target must resolve
+    pub fn set_outputs(&self, targets: Vec<String>) -> Result<(), String> {
+        let socket = open_udp_targets(&targets, "sample")?;
+        *self.socket.lock().unwrap() = socket;
+        *self.outputs.lock().unwrap() = targets;
+        *self.stage.lock().unwrap() = None;
+        Ok(())
+    }
+
+    pub fn outputs(&self) -> Vec<String> {
+        self.outputs.lock().unwrap().clone()
+    }
+
+    /// Applies new output-rate settings from the next sample on
+    pub fn set_resampling(&self, config: ResampleConfig) {
+        *self.config.lock().unwrap() = config;
+        *self.stage.lock().unwrap() = None;
+    }
+
+    /// Feed one row of display channels acquired at `sample_rate`
+    pub fn add_data(&self, row: &[f32], sample_rate: f64) {
+        let socket = self.socket.lock().unwrap();
+        let Some((socket, targets)) = socket.as_ref() else {
+            return;
+        };
+        let mut stage = self.stage.lock().unwrap();
+        if stage.as_ref().map(|s| s.source_rate != sample_rate).unwrap_or(true) {
+            *stage = Some(Stage::new(sample_rate, &self.config.lock().unwrap()));
+        }
+        let Some(stage) = stage.as_mut() else {
+            return;
+        };
+
+        let (rate, rows) = match stage.resampler.as_mut() {
+            Some(resampler) => (resampler.target_rate(), resampler.process(row)),
+            None => {
+                stage.next_index += 1;
+                (sample_rate, vec![(stage.next_index - 1, row.to_vec())])
+            }
+        };
+        for (sample_index, values) in rows {
+            let packet = SamplePacket {
+                sample_index,
+                sample_rate: rate,
+                values: &values,
+            };
+            let Ok(payload) = serde_json::to_vec(&packet) else {
+                continue;
+            };
+            for target in targets {
+                if let Err(e) = socket.send_to(&payload, target) {
+                    eprintln!("[SampleOutput] Failed to send samples to {}: {}", target, e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn sends_resampled_rows() {
+        let receiver = UdpSocket::bind(("127.0.0.1", 0)).unwrap();
+        receiver.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let output = SampleOutput::new();
+        output.set_outputs(vec![receiver.local_addr().unwrap().to_string()]).unwrap();
+        output.set_resampling(ResampleConfig {
+            enabled: true,
+            target_rate: 250.0,
+            ..ResampleConfig::default()
+        });
+
+        for i in 0..100 {
+            output.add_data(&[i as f32, 1.0], 500.0);
+        }
+        let mut buf = [0u8; 1024];
+        let mut packets = Vec::new();
+        while let Ok(n) = receiver.recv(&mut buf) {
+            packets.push(serde_json::from_slice::<serde_json::Value>(&buf[..n]).unwrap());
+            if packets.len() == 50 {
+                break;
+            }
+        }
+        assert_eq!(packets.len(), 50);
+        assert_eq!(packets[0]["sample_rate"], 250.0);
+        assert_eq!(packets[49]["sample_index"], 49);
+        assert_eq!(packets[0]["values"].as_array().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,482 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant, SystemTime};
+
+use chrono::Local;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::clock::SyncPoint;
+use crate::compression;
+use crate::state::AppState;
+use crate::types::DeviceInfo;
+
+/// Version of the session manifest layout
+pub const METADATA_VERSION: u32 = 2;
+
+/// Name of the manifest inside each session directory
+pub const MANIFEST_FILE: &str = "session.json";
+
+/// Session directories are named `session_<id>`
+const DIRECTORY_PREFIX: &str = "session_";
+
+/// Sample counts are saved to the manifest at least this often while recording
+const SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Session fields entered by the operator; kept for the following sessions until changed
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionDetails {
+    pub subject_id: String,
+    pub session_id: String,
+    pub operator: String,
+    pub protocol: String,
+    pub filters: Vec<String>, // Filters applied outside the app, e.g. by the amplifier
+    pub notes: String,
+}
+
+/// Changes to the session details; fields left out are kept
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SessionDetailsUpdate {
+    pub subject_id: Option<String>,
+    pub session_id: Option<String>,
+    pub operator: Option<String>,
+    pub protocol: Option<String>,
+    pub filters: Option<Vec<String>>,
+    pub notes: Option<String>,
+}
+
+impl SessionDetails {
+    fn apply(&mut self, update: SessionDetailsUpdate) {
+        let SessionDetailsUpdate {
+            subject_id,
+            session_id,
+            operator,
+            protocol,
+            filters,
+            notes,
+        } = update;
+        if let Some(subject_id) = subject_id {
+            self.subject_id = subject_id;
+        }
+        if let Some(session_id) = session_id {
+            self.session_id = session_id;
+        }
+        if let Some(operator) = operator {
+            self.operator = operator;
+        }
+        if let Some(protocol) = protocol {
+            self.protocol = protocol;
+        }
+        if let Some(filters) = filters {
+            self.filters = filters;
+        }
+        if let Some(notes) = notes {
+            self.notes = notes;
+        }
+    }
+}
+
+/// One recording file of a session and the video recorded alongside it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionSegment {
+    pub file: String,
+    pub started_ms: u64,
+    pub ended_ms: Option<u64>, // None while the segment is being written
+    pub samples: u64,
+    pub video_file: Option<String>,
+}
+
+impl SessionSegment {
+    fn new(file: &str, video_file: Option<String>) -> Self {
+        Self {
+            file: file.to_string(),
+            started_ms: now_ms(),
+            ended_ms: None,
+            samples: 0,
+            video_file,
+        }
+    }
+}
+
+/// A pause of the recording; no samples were written during it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionPause {
+    pub started_ms: u64,
+    pub duration_ms: f64,
+    pub segment: String, // Segment that was open during the pause
+}
+
+/// Contents of the `session.json` manifest in a session directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionMetadata {
+    pub version: u32,
+    pub id: String, // Generated identifier, also naming the session directory
+    #[serde(flatten)]
+    pub details: SessionDetails,
+    pub device: DeviceInfo,
+    pub format: String,
+    pub compression: compression::Compression,
+    pub sample_rate: f64,
+    pub channels: Vec<String>,
+    pub processing: Vec<String>, // Montage, derived channels and resampling applied by the app
+    pub app_version: String,
+    pub started_ms: u64,
+    pub stopped_ms: Option<u64>,
+    pub segments: Vec<SessionSegment>, // In recording order
+    #[serde(default)]
+    pub sync_points: Vec<SyncPoint>, // System clock readings against the session's sample clock
+    #[serde(default)]
+    pub pauses: Vec<SessionPause>,
+}
+
+/// The running session and where its manifest lives
+pub struct ActiveSession {
+    pub metadata: SessionMetadata,
+    pub directory: PathBuf,
+    saved_at: Instant,
+}
+
+impl ActiveSession {
+    fn save(&mut self) {
+        write_metadata(&self.directory.join(MANIFEST_FILE), &self.metadata);
+        self.saved_at = Instant::now();
+    }
+}
+
+/// Overview of a stored session for listings
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionSummary {
+    pub id: String,
+    pub directory: String,
+    pub subject_id: String,
+    pub started_ms: u64,
+    pub stopped_ms: Option<u64>,
+    pub segments: usize,
+    pub samples: u64,
+    pub size_bytes: u64,
+    pub active: bool,
+}
+
+/// A file found in a session directory
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionFile {
+    pub name: String,
+    pub size_bytes: u64,
+}
+
+/// Manifest of a stored session together with the files actually on disk
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionInspection {
+    pub metadata: SessionMetadata,
+    pub directory: String,
+    pub files: Vec<SessionFile>,
+    pub missing: Vec<String>, // Segment and video files listed in the manifest but not found
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_else(|_| Duration::from_secs(0))
+        .as_millis() as u64
+}
+
+/// Sortable, unique enough identifier: local start time plus a random suffix
+pub fn new_session_id() -> String {
+    let suffix: u16 = rand::thread_rng().gen();
+    format!("{}-{:04x}", Local::now().format("%Y%m%d-%H%M%S"), suffix)
+}
+
+/// Directory of session `id` under the recording directory
+pub fn session_directory(directory: &Path, id: &str) -> Result<PathBuf, String> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(format!("Invalid session ID '{}'", id));
+    }
+    Ok(directory.join(format!("{}{}", DIRECTORY_PREFIX, id)))
+}
+
+/// Whether `path` is a session directory: named `session_<id>` and holding a session manifest
+pub fn is_session_directory(path: &Path) -> bool {
+    let named = path.file_name().map(|name| name.to_string_lossy().starts_with(DIRECTORY_PREFIX)).unwrap_or(false);
+    named && path.join(MANIFEST_FILE).is_file()
+}
+
+/// Creates the directory of a new session and returns its ID and path.
+pub fn create_session_directory(directory: &Path) -> Result<(String, PathBuf), String> {
+    let id = new_session_id();
+    let path = session_directory(directory, &id)?;
+    fs::create_dir_all(&path).map_err(|e| format!("Failed to create session directory: {}", e))?;
+    Ok((id, path))
+}
+
+/// Describes what the app does to the samples before they are written
+fn processing(state: &AppState) -> Vec<String> {
+    let mut steps = Vec::new();
+    if let Some(montage) = state.recording.recording_montage.lock().unwrap().as_ref() {
+        steps.push(format!("montage {}", montage.name));
+    }
+    let derived = state.recording.recording_derived.lock().unwrap();
+    if !derived.is_empty() {
+        steps.push(format!("derived channels {}", derived.join(", ")));
+    }
+    if let Some(resampler) = state.recording.recording_resampler.lock().unwrap().as_ref() {
+        let taps = state.recording.resample_config.lock().unwrap().taps_per_phase;
+        steps.push(format!(
+            "resampled {} Hz to {} Hz (windowed-sinc low-pass, {} taps per phase)",
+            state.stream.get_sample_rate(),
+            resampler.target_rate(),
+            taps
+        ));
+    }
+    steps
+}
+
+fn write_metadata(path: &Path, metadata: &SessionMetadata) {
+    let result = serde_json::to_string_pretty(metadata)
+        .map_err(|e| e.to_string())
+        .and_then(|json| {
+            // Replace the manifest in one step so it is never left half written
+            let temporary = path.with_extension("json.tmp");
+            fs::write(&temporary, json)
+                .and_then(|_| fs::rename(&temporary, path))
+                .map_err(|e| e.to_string())
+        });
+    if let Err(e) = result {
+        eprintln!("Error writing session metadata {}: {}", path.display(), e);
+    }
+}
+
+fn read_metadata(path: &Path) -> Result<SessionMetadata, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    serde_json::from_str(&text).map_err(|e| format!("Invalid session metadata {}: {}", path.display(), e))
+}
+
+/// Starts the manifest of session `id` in `directory`, whose first segment is `filename`.
+pub fn begin_session(state: &AppState, id: &str, format: &str, directory: &Path, filename: &str) {
+    let metadata = SessionMetadata {
+        version: METADATA_VERSION,
+        id: id.to_string(),
+        details: state.session.details.lock().unwrap().clone(),
+        device: state.stream.device.lock().unwrap().clone(),
+        format: format.to_string(),
+        compression: state.recording.compression.lock().unwrap().method,
+        sample_rate: *state.recording.recording_sample_rate.lock().unwrap(),
+        channels: state.recording.channel_labels(),
+        processing: processing(state),
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        started_ms: now_ms(),
+        stopped_ms: None,
+        // Video may have been started before the recording
+        segments: vec![SessionSegment::new(
+            filename,
+            state.recording.video_filename.lock().unwrap().clone(),
+        )],
+        sync_points: Vec::new(),
+        pauses: Vec::new(),
+    };
+    let mut session = ActiveSession {
+        metadata,
+        directory: directory.to_path_buf(),
+        saved_at: Instant::now(),
+    };
+    session.save();
+    println!("[Session] Started session {} in {}", id, directory.display());
+    *state.session.active.lock().unwrap() = Some(session);
+}
+
+/// Closes the current segment of the running session and starts the next one.
+pub fn add_segment(state: &AppState, filename: &str) {
+    if let Some(ref mut session) = *state.session.active.lock().unwrap() {
+        if let Some(segment) = session.metadata.segments.last_mut() {
+            segment.ended_ms = Some(now_ms());
+        }
+        session.metadata.segments.push(SessionSegment::new(filename, None));
+        session.save();
+    }
+}
+
+/// Counts samples written to the current segment.
+pub fn count_samples(state: &AppState, samples: usize) {
+    if let Some(ref mut session) = *state.session.active.lock().unwrap() {
+        if let Some(segment) = session.metadata.segments.last_mut() {
+            segment.samples += samples as u64;
+        }
+        if session.saved_at.elapsed() >= SAVE_INTERVAL {
+            session.save();
+        }
+    }
+}
+
+/// Directory for a video file: the running session's directory, so the video is
+/// kept with its segments, or `requested` when nothing is being recorded.
+pub fn video_directory(state: &AppState, requested: &str) -> String {
+    match *state.session.active.lock().unwrap() {
+        Some(ref session) => session.directory.to_string_lossy().into_owned(),
+        None => requested.to_string(),
+    }
+}
+
+/// Adds sync points of the session clock; they are saved with the next manifest update.
+pub fn add_sync_points(state: &AppState, points: Vec<SyncPoint>) {
+    if points.is_empty() {
+        return;
+    }
+    if let Some(ref mut session) = *state.session.active.lock().unwrap() {
+        session.metadata.sync_points.extend(points);
+    }
+}
+
+/// Records a pause of the running session.
+pub fn add_pause(state: &AppState, started_ms: u64, duration_ms: f64) {
+    if let Some(ref mut session) = *state.session.active.lock().unwrap() {
+        let segment = session.metadata.segments.last().map(|s| s.file.clone()).unwrap_or_default();
+        session.metadata.pauses.push(SessionPause {
+            started_ms,
+            duration_ms,
+            segment,
+        });
+        session.save();
+    }
+}
+
+/// Pairs a video file with the newest segment of the running session.
+pub fn attach_video(state: &AppState, video_file: &str) {
+    if !state.recording.recording_active.load(Ordering::SeqCst) {
+        return;
+    }
+    if let Some(ref mut session) = *state.session.active.lock().unwrap() {
+        if let Some(segment) = session.metadata.segments.last_mut() {
+            segment.video_file = Some(video_file.to_string());
+            session.save();
+        }
+    }
+}
+
+/// Stamps the end time on the running session's manifest and closes it.
+pub fn end_session(state: &AppState) {
+    if let Some(mut session) = state.session.active.lock().unwrap().take() {
+        let now = now_ms();
+        if let Some(segment) = session.metadata.segments.last_mut() {
+            segment.ended_ms = Some(now);
+        }
+        session.metadata.stopped_ms = Some(now);
+        session.save();
+        println!("[Session] Finished session {}", session.metadata.id);
+    }
+}
+
+/// Updates the operator-entered details. They apply to the running session,
+/// whose manifest is rewritten, and to the sessions started after it.
+pub fn update_details(state: &AppState, update: SessionDetailsUpdate) -> SessionDetails {
+    let details = {
+        let mut details = state.session.details.lock().unwrap();
+        details.apply(update);
+        details.clone()
+    };
+    if let Some(ref mut session) = *state.session.active.lock().unwrap() {
+        session.metadata.details = details.clone();
+        session.save();
+    }
+    details
+}
+
+/// Updates the details stored in the manifest of an earlier session.
+pub fn update_stored(directory: &Path, id: &str, update: SessionDetailsUpdate) -> Result<SessionDetails, String> {
+    let path = session_directory(directory, id)?.join(MANIFEST_FILE);
+    let mut metadata = read_metadata(&path)?;
+    metadata.details.apply(update);
+    write_metadata(&path, &metadata);
+    Ok(metadata.details)
+}
+
+fn active_id(state: &AppState) -> Option<String> {
+    state.session.active.lock().unwrap().as_ref().map(|s| s.metadata.id.clone())
+}
+
+fn directory_files(directory: &Path) -> Vec<SessionFile> {
+    let mut files: Vec<SessionFile> = fs::read_dir(directory)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| {
+                    let metadata = entry.metadata().ok().filter(|m| m.is_file())?;
+                    Some(SessionFile {
+                        name: entry.file_name().to_string_lossy().into_owned(),
+                        size_bytes: metadata.len(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+    files
+}
+
+/// Sessions stored under the recording directory, oldest first.
+pub fn list_sessions(state: &AppState, directory: &Path) -> Result<Vec<SessionSummary>, String> {
+    let entries = fs::read_dir(directory).map_err(|e| format!("Failed to read {}: {}", directory.display(), e))?;
+    let active = active_id(state);
+    let mut sessions = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !is_session_directory(&path) {
+            continue;
+        }
+        let metadata = match read_metadata(&path.join(MANIFEST_FILE)) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                eprintln!("[Session] Skipping {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        sessions.push(SessionSummary {
+            active: active.as_deref() == Some(metadata.id.as_str()),
+            directory: path.to_string_lossy().into_owned(),
+            subject_id: metadata.details.subject_id.clone(),
+            started_ms: metadata.started_ms,
+            stopped_ms: metadata.stopped_ms,
+            segments: metadata.segments.len(),
+            samples: metadata.segments.iter().map(|s| s.samples).sum(),
+            size_bytes: directory_files(&path).iter().map(|f| f.size_bytes).sum(),
+            id: metadata.id,
+        });
+    }
+    sessions.sort_by_key(|s| s.started_ms);
+    Ok(sessions)
+}
+
+/// Manifest and files of session `id`.
+pub fn inspect_session(directory: &Path, id: &str) -> Result<SessionInspection, String> {
+    let path = session_directory(directory, id)?;
+    let metadata = read_metadata(&path.join(MANIFEST_FILE))?;
+    let files = directory_files(&path);
+    let missing = metadata
+        .segments
+        .iter()
+        .flat_map(|s| std::iter::once(&s.file).chain(s.video_file.as_ref()))
+        .filter(|name| !files.iter().any(|f| &&f.name == name))
+        .cloned()
+        .collect();
+    Ok(SessionInspection {
+        metadata,
+        directory: path.to_string_lossy().into_owned(),
+        files,
+        missing,
+    })
+}
+
+/// Deletes session `id` with all its segments, videos and sidecars.
+pub fn delete_session(state: &AppState, directory: &Path, id: &str) -> Result<(), String> {
+    if active_id(state).as_deref() == Some(id) {
+        return Err("Cannot delete the session being recorded".to_string());
+    }
+    let path = session_directory(directory, id)?;
+    if !path.join(MANIFEST_FILE).is_file() {
+        return Err(format!("Session {} not found", id));
+    }
+    fs::remove_dir_all(&path).map_err(|e| format!("Failed to delete session {}: {}", id, e))?;
+    println!("[Session] Deleted session {}", id);
+    Ok(())
+}
@@ -0,0 +1,357 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use serde::{Deserialize, Serialize};
+
+use crate::montage::RAW_CHANNELS;
+use crate::types::ChannelData;
+
+/// Taper applied to every segment before the FFT
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Taper {
+    Hann,
+    Hamming,
+    Blackman,
+    Rectangular,
+}
+
+impl Taper {
+    /// Build the window coefficients for a segment of `len` samples
+    pub fn coefficients(&self, len: usize) -> Vec<f64> {
+        if len <= 1 {
+            return vec![1.0; len];
+        }
+        let n = (len - 1) as f64;
+        (0..len)
+            .map(|i| {
+                let x = 2.0 * std::f64::consts::PI * i as f64 / n;
+                match self {
+                    Taper::Hann => 0.5 - 0.5 * x.cos(),
+                    Taper::Hamming => 0.54 - 0.46 * x.cos(),
+                    Taper::Blackman => 0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos(),
+                    Taper::Rectangular => 1.0,
+                }
+            })
+            .collect()
+    }
+}
+
+/// A named frequency band in Hz, `low` inclusive and `high` exclusive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrequencyBand {
+    pub name: String,
+    pub low: f64,
+    pub high: f64,
+}
+
+impl FrequencyBand {
+    fn new(name: &str, low: f64, high: f64) -> Self {
+        Self { name: name.to_string(), low, high }
+    }
+}
+
+/// Classic EEG bands reported with every spectrum
+pub fn default_bands() -> Vec<FrequencyBand> {
+    vec![
+        FrequencyBand::new("delta", 0.5, 4.0),
+        FrequencyBand::new("theta", 4.0, 8.0),
+        FrequencyBand::new("alpha", 8.0, 13.0),
+        FrequencyBand::new("beta", 13.0, 30.0),
+        FrequencyBand::new("gamma", 30.0, 45.0),
+    ]
+}
+
+/// User-tunable settings for the Welch estimator
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpectrumConfig {
+    pub enabled: bool,
+    pub window_size: usize,      // Samples per FFT segment
+    pub overlap: f64,            // Fraction of overlap between consecutive segments (0.0 - 0.95)
+    pub segments: usize,         // Number of segments averaged per estimate
+    pub taper: Taper,
+    pub update_interval_ms: u64, // How often a `spectrum` event is published
+    pub custom_bands: Vec<FrequencyBand>,
+}
+
+impl Default for SpectrumConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            window_size: 256,
+            overlap: 0.5,
+            segments: 4,
+            taper: Taper::Hann,
+            update_interval_ms: 500,
+            custom_bands: Vec::new(),
+        }
+    }
+}
+
+impl SpectrumConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.window_size < 8 {
+            return Err("Spectrum window size must be at least 8 samples".to_string());
+        }
+        if !(0.0..=0.95).contains(&self.overlap) {
+            return Err("Spectrum overlap must be between 0.0 and 0.95".to_string());
+        }
+        if self.segments == 0 {
+            return Err("Spectrum needs at least one segment".to_string());
+        }
+        for band in &self.custom_bands {
+            if band.low < 0.0 || band.high <= band.low {
+                return Err(format!("Invalid frequency band '{}'", band.name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Distance in samples between the starts of two consecutive segments
+    pub fn hop(&self) -> usize {
+        ((self.window_size as f64 * (1.0 - self.overlap)).round() as usize).max(1)
+    }
+
+    /// Number of samples needed for a full estimate
+    pub fn span(&self) -> usize {
+        self.window_size + (self.segments - 1) * self.hop()
+    }
+
+    /// Default bands followed by the user-defined ones
+    pub fn bands(&self) -> Vec<FrequencyBand> {
+        let mut bands = default_bands();
+        bands.extend(self.custom_bands.iter().cloned());
+        bands
+    }
+}
+
+/// Absolute and relative power of one band for every channel
+#[derive(Debug, Clone, Serialize)]
+pub struct BandPower {
+    pub name: String,
+    pub low: f64,
+    pub high: f64,
+    pub absolute: Vec<f64>, // Integrated PSD per channel (unit^2)
+    pub relative: Vec<f64>, // Fraction of the total 0.5 Hz - Nyquist power per channel
+}
+
+/// Payload of the `spectrum` event
+#[derive(Debug, Clone, Serialize)]
+pub struct SpectrumResult {
+    pub sample_rate: f64,
+    pub frequencies: Vec<f64>,
+    pub psd: Vec<Vec<f64>>, // One power spectral density per channel (unit^2/Hz)
+    pub band_powers: Vec<BandPower>,
+}
+
+/// Welch PSD of a single channel using a pre-planned FFT.
+/// Each segment is mean-removed and tapered; the result is one-sided density.
+pub fn welch_psd(
+    samples: &[f64],
+    sample_rate: f64,
+    window: &[f64],
+    hop: usize,
+    fft: &dyn Fft<f64>,
+) -> Vec<f64> {
+    let n = window.len();
+    let bins = n / 2 + 1;
+    let mut psd = vec![0.0; bins];
+    if samples.len() < n || n == 0 {
+        return psd;
+    }
+
+    let window_power: f64 = window.iter().map(|w| w * w).sum();
+    let scale = 1.0 / (sample_rate * window_power);
+    let mut buffer = vec![Complex::new(0.0, 0.0); n];
+    let mut count = 0usize;
+    let mut start = 0usize;
+    while start + n <= samples.len() {
+        let segment = &samples[start..start + n];
+        let mean = segment.iter().sum::<f64>() / n as f64;
+        for (slot, (&x, &w)) in buffer.iter_mut().zip(segment.iter().zip(window)) {
+            *slot = Complex::new((x - mean) * w, 0.0);
+        }
+        fft.process(&mut buffer);
+        for (k, value) in psd.iter_mut().enumerate() {
+            *value += buffer[k].norm_sqr() * scale;
+        }
+        count += 1;
+        start += hop;
+    }
+
+    for (k, value) in psd.iter_mut().enumerate() {
+        *value /= count as f64;
+        // Fold negative frequencies into the one-sided spectrum, except DC and Nyquist
+        if k != 0 && !(n.is_multiple_of(2) && k == bins - 1) {
+            *value *= 2.0;
+        }
+    }
+    psd
+}
+
+/// Integrate a PSD over `[low, high)` Hz
+pub fn band_power(frequencies: &[f64], psd: &[f64], low: f64, high: f64) -> f64 {
+    if frequencies.len() < 2 {
+        return 0.0;
+    }
+    let df = frequencies[1] - frequencies[0];
+    frequencies
+        .iter()
+        .zip(psd)
+        .filter(|(&f, _)| f >= low && f < high)
+        .map(|(_, &p)| p * df)
+        .sum()
+}
+
+/// Frequency of each PSD bin for a segment length and sample rate
+pub fn frequency_bins(window_size: usize, sample_rate: f64) -> Vec<f64> {
+    (0..window_size / 2 + 1)
+        .map(|k| k as f64 * sample_rate / window_size as f64)
+        .collect()
+}
+
+/// Short-time Welch engine running over the live sample stream
+pub struct SpectrumEngine {
+    config: SpectrumConfig,
+    window: Vec<f64>,
+    fft: Arc<dyn Fft<f64>>,
+    history: VecDeque<ChannelData>,
+    samples_since_update: usize,
+    latest: Option<SpectrumResult>,
+}
+
+impl SpectrumEngine {
+    pub fn new(config: SpectrumConfig) -> Self {
+        let window = config.taper.coefficients(config.window_size);
+        let fft = FftPlanner::new().plan_fft_forward(config.window_size);
+        Self {
+            history: VecDeque::with_capacity(config.span()),
+            config,
+            window,
+            fft,
+            samples_since_update: 0,
+            latest: None,
+        }
+    }
+
+    pub fn config(&self) -> &SpectrumConfig {
+        &self.config
+    }
+
+    /// Swap the configuration, re-planning the FFT and keeping buffered samples where possible
+    pub fn set_config(&mut self, config: SpectrumConfig) {
+        let history = std::mem::take(&mut self.history);
+        *self = Self::new(config);
+        for data in history {
+            self.push_history(data);
+        }
+    }
+
+    pub fn latest(&self) -> Option<&SpectrumResult> {
+        self.latest.as_ref()
+    }
+
+    fn push_history(&mut self, data: ChannelData) {
+        if self.history.len() >= self.config.span() {
+            self.history.pop_front();
+        }
+        self.history.push_back(data);
+    }
+
+    /// Feed one sample; returns a fresh estimate whenever the update interval elapses
+    pub fn add_data(&mut self, data: ChannelData, sample_rate: f64) -> Option<SpectrumResult> {
+        self.push_history(data);
+        if !self.config.enabled || sample_rate <= 0.0 {
+            return None;
+        }
+
+        self.samples_since_update += 1;
+        let interval = ((self.config.update_interval_ms as f64 * sample_rate / 1000.0).round()
+            as usize)
+            .max(1);
+        if self.samples_since_update < interval || self.history.len() < self.config.window_size {
+            return None;
+        }
+        self.samples_since_update = 0;
+
+        let result = self.compute(sample_rate, &self.config.bands())?;
+        self.latest = Some(result.clone());
+        Some(result)
+    }
+
+    /// Number of samples buffered, up to the span of the Welch segments
+    pub fn buffered(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Estimate the spectrum of the buffered samples for the given bands; None
+    /// until a full window is buffered, so no estimate is made from missing data
+    pub fn compute(&self, sample_rate: f64, bands: &[FrequencyBand]) -> Option<SpectrumResult> {
+        if self.history.len() < self.config.window_size || sample_rate <= 0.0 {
+            return None;
+        }
+        let frequencies = frequency_bins(self.config.window_size, sample_rate);
+        let psd: Vec<Vec<f64>> = (0..RAW_CHANNELS)
+            .map(|channel| {
+                let values: Vec<f64> = self.history.iter().map(|d| d[channel] as f64).collect();
+                welch_psd(&values, sample_rate, &self.window, self.config.hop(), self.fft.as_ref())
+            })
+            .collect();
+
+        let nyquist = sample_rate / 2.0;
+        let totals: Vec<f64> = psd
+            .iter()
+            .map(|p| band_power(&frequencies, p, 0.5, nyquist + f64::EPSILON))
+            .collect();
+        let band_powers = bands
+            .iter()
+            .map(|band| {
+                let absolute: Vec<f64> = psd
+                    .iter()
+                    .map(|p| band_power(&frequencies, p, band.low, band.high))
+                    .collect();
+                let relative = absolute
+                    .iter()
+                    .zip(&totals)
+                    .map(|(&a, &t)| if t > 0.0 { a / t } else { 0.0 })
+                    .collect();
+                BandPower {
+                    name: band.name.clone(),
+                    low: band.low,
+                    high: band.high,
+                    absolute,
+                    relative,
+                }
+            })
+            .collect();
+
+        Some(SpectrumResult {
+            sample_rate,
+            frequencies,
+            psd,
+            band_powers,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_estimate_until_a_full_window_is_buffered() {
+        let mut engine = SpectrumEngine::new(SpectrumConfig::default());
+        let bands = engine.config().bands();
+        assert!(engine.compute(250.0, &bands).is_none());
+
+        for i in 0..engine.config().window_size {
+            let value = (i as f32 * 0.3).sin();
+            engine.add_data([value; 8], 250.0);
+        }
+        let result = engine.compute(250.0, &bands).expect("a full window is buffered");
+        assert_eq!(result.psd.len(), RAW_CHANNELS);
+        assert!(result.band_powers.iter().any(|band| band.absolute.iter().any(|&p| p > 0.0)));
+    }
+}
@@ -1,15 +1,38 @@
 use std::{
-    collections::VecDeque,
+    collections::{HashMap, VecDeque},
     fs::File,
+    path::PathBuf,
     sync::{atomic::AtomicBool, mpsc::Sender, Arc, Mutex},
     thread::JoinHandle,
-    time::SystemTime,
+    time::{Duration, SystemTime},
 };
 
 use tauri::{AppHandle};
 use libmdns::Responder;
 
-use crate::types::ChannelData;
+use crate::artifact::{ArtifactConfig, ArtifactEngine, ArtifactEvent};
+use crate::binary::BinaryOptions;
+use crate::brainvision::BrainVisionOptions;
+use crate::clock::{SessionClock, SyncPoint};
+use crate::compression::CompressionConfig;
+use crate::edf::EdfOptions;
+use crate::expression::{CompiledExpression, DerivedExpression, EvalContext};
+use crate::impedance::{ImpedanceConfig, ImpedanceEngine, ImpedanceReport};
+use crate::markers::MarkerBus;
+use crate::montage::{self, CompiledMontage};
+use crate::pipeline::RecordingPipeline;
+use crate::quality::{QualityEngine, QualityReport, QualityThresholds};
+use crate::recording::Annotation;
+use crate::recording_reader::OpenedRecording;
+use crate::resample::{ResampleConfig, Resampler};
+use crate::rotation::RotationConfig;
+use crate::sample_output::SampleOutput;
+use crate::session::{ActiveSession, SessionDetails};
+use crate::spectrum::{SpectrumConfig, SpectrumEngine, SpectrumResult};
+use crate::storage::StorageConfig;
+use crate::trigger::{TriggerCondition, TriggerConfig, TriggerSession};
+use crate::types::{ChannelData, DeviceInfo};
+use crate::writer::{RecordingWriter, Row};
 
 // ==== Communication State ====
 /// Manages serial/socket communication channels
@@ -29,9 +52,10 @@ impl CommunicationState {
 
 // ==== Buffer State ====
 /// Manages data buffers for visualization and processing
+/// Rows hold the display channels (raw channels, or the active montage output)
 pub struct BufferState {
-    pub active_buffer: Mutex<VecDeque<ChannelData>>,
-    pub read_buffer: Mutex<VecDeque<ChannelData>>,
+    pub active_buffer: Mutex<VecDeque<Vec<f32>>>,
+    pub read_buffer: Mutex<VecDeque<Vec<f32>>>,
     pub buffer_lock: Mutex<()>,
 }
 
@@ -44,7 +68,7 @@ impl BufferState {
         }
     }
 
-    pub fn add_data(&self, data: ChannelData) {
+    pub fn add_data(&self, data: Vec<f32>) {
         let _guard = self.buffer_lock.lock().unwrap();
 
         // Update active buffer for visualization
@@ -52,7 +76,7 @@ impl BufferState {
         if active_buf.len() >= 2000 {
             active_buf.pop_front();
         }
-        active_buf.push_back(data);
+        active_buf.push_back(data.clone());
 
         // Update read buffer for regular data retrieval
         let mut read_buf = self.read_buffer.lock().unwrap();
@@ -62,11 +86,11 @@ impl BufferState {
         read_buf.push_back(data);
     }
 
-    pub fn get_data(&self) -> Vec<ChannelData> {
+    pub fn get_data(&self) -> Vec<Vec<f32>> {
         let _guard = self.buffer_lock.lock().unwrap();
 
         let mut read_buf = self.read_buffer.lock().unwrap();
-        let result: Vec<ChannelData> = read_buf.iter().cloned().collect();
+        let result: Vec<Vec<f32>> = read_buf.iter().cloned().collect();
 
         read_buf.clear();
 
@@ -77,71 +101,31 @@ impl BufferState {
 // ==== Signal Quality State ====
 /// Manages signal quality monitoring and analysis
 pub struct SignalQualityState {
-    pub quality_check_buffer: Arc<Mutex<VecDeque<ChannelData>>>,
-    pub signal_quality: Arc<Mutex<Vec<bool>>>, // Signal quality indicators for each channel (true = good, false = bad)
+    pub engine: Mutex<QualityEngine>,
 }
 
 impl SignalQualityState {
     pub fn new() -> Self {
         Self {
-            quality_check_buffer: Arc::new(Mutex::new(VecDeque::with_capacity(500))),
-            signal_quality: Arc::new(Mutex::new(vec![true; 8])), // Initialize with 8 channels, all good quality
+            engine: Mutex::new(QualityEngine::new(QualityThresholds::default())),
         }
     }
 
-    pub fn add_data(&self, data: ChannelData) {
-        // Update quality check buffer
-        let mut quality_buf = self.quality_check_buffer.lock().unwrap();
-        if quality_buf.len() >= 500 {
-            quality_buf.pop_front();
-        }
-        quality_buf.push_back(data);
+    // Feed a sample; returns a new report when one is due for publishing
+    pub fn add_data(&self, data: ChannelData, sample_rate: f64) -> Option<QualityReport> {
+        self.engine.lock().unwrap().add_data(data, sample_rate)
     }
 
-    // Get the current signal quality indicators for all channels
+    // Get the latest good/bad flags for all channels (all good until the first report)
     pub fn get_signal_quality(&self) -> Vec<bool> {
-        let signal_quality = self.signal_quality.lock().unwrap();
-        signal_quality.clone()
-    }
-    
-    // Check signal quality of the channel data (runs on-demand, not in a separate thread)
-    pub fn check_signal_quality(&self) -> Vec<bool> {
-        // Get a copy of the quality check buffer
-        let quality_data: Vec<ChannelData> = {
-            let buffer = self.quality_check_buffer.lock().unwrap();
-            buffer.iter().cloned().collect()
-        };
-        
-        // If we don't have enough data, return current quality status
-        if quality_data.len() < 10 {
-            return self.get_signal_quality();
-        }
-        
-        // Calculate statistics for each channel
-        let mut signal_quality_guard = self.signal_quality.lock().unwrap();
-        
-        // For each of the 8 channels
-        for channel in 0..8 {
-            // Extract this channel's data
-            let values: Vec<f32> = quality_data.iter().map(|data| data[channel]).collect();
-            
-            // Calculate mean
-            let sum: f32 = values.iter().sum();
-            let mean = sum / values.len() as f32;
-            
-            // Calculate standard deviation
-            let var_sum: f32 = values.iter()
-                .map(|&v| (v - mean).powi(2))
-                .sum();
-            let std_dev = (var_sum / values.len() as f32).sqrt();
-            
-            // Update quality flag based on criteria
-            // Flag as poor quality (false) if std_dev > 2.235e4 or mean > 1.341e5
-            signal_quality_guard[channel] = std_dev <= 2.235e4 && mean <= 1.341e5;
-        }
-        
-        // Return a copy of the updated quality indicators
-        signal_quality_guard.clone()
+        match self.engine.lock().unwrap().latest() {
+            Some(report) => report.flags(),
+            None => vec![true; 8],
+        }
+    }
+
+    pub fn latest_report(&self) -> Option<QualityReport> {
+        self.engine.lock().unwrap().latest().cloned()
     }
 }
 
@@ -155,6 +139,8 @@ pub struct StreamState {
     pub fake_signal_enabled: Arc<AtomicBool>, // Flag for fake signal data (used in SignalConfigView)
     pub fake_camera_enabled: Arc<AtomicBool>, // Flag for fake camera stream (used in StreamingView)
     pub default_stream_url: Mutex<String>, // Store the default stream URL
+    pub sample_rate: Mutex<f64>, // Nominal sample rate of the signal stream in Hz
+    pub device: Mutex<DeviceInfo>, // Connected data source and its settings, stored with recordings
 }
 
 impl StreamState {
@@ -167,53 +153,494 @@ impl StreamState {
             fake_signal_enabled: Arc::new(AtomicBool::new(false)), // Initialize fake signal data as disabled
             fake_camera_enabled: Arc::new(AtomicBool::new(false)), // Initialize fake camera as disabled
             default_stream_url: Mutex::new(String::new()), // Initialize with empty string
+            sample_rate: Mutex::new(250.0),
+            device: Mutex::new(DeviceInfo::default()),
         }
     }
+
+    pub fn get_sample_rate(&self) -> f64 {
+        *self.sample_rate.lock().unwrap()
+    }
+}
+
+// ==== Spectrum State ====
+/// Manages the streaming spectral analysis of the live signal
+pub struct SpectrumState {
+    pub engine: Mutex<SpectrumEngine>,
+}
+
+impl SpectrumState {
+    pub fn new() -> Self {
+        Self {
+            engine: Mutex::new(SpectrumEngine::new(SpectrumConfig::default())),
+        }
+    }
+
+    // Feed a sample; returns a new estimate when one is due for publishing
+    pub fn add_data(&self, data: ChannelData, sample_rate: f64) -> Option<SpectrumResult> {
+        self.engine.lock().unwrap().add_data(data, sample_rate)
+    }
 }
 
 // ==== Recording State ====
 /// Manages recording functionality (both signal and video)
 pub struct RecordingState {
-    pub recording_buffer: Mutex<VecDeque<(SystemTime, ChannelData)>>, // Dedicated buffer for recording with timestamps
+    pub pipeline: RecordingPipeline, // Rows on their way from acquisition to the recording thread
+    pub recording_montage: Mutex<Option<CompiledMontage>>, // Montage applied to recorded samples, fixed at start
+    pub recording_derived: Mutex<Vec<String>>, // Derived channels appended to recorded samples, fixed at start
+    pub resample_config: Mutex<ResampleConfig>, // Output rate requested for recordings
+    pub recording_resampler: Mutex<Option<Resampler>>, // Active resampler, fixed at start
+    pub clock: Mutex<SessionClock>, // Sample index and nominal time of recorded rows, restarted with each session
+    pub recording_sample_rate: Mutex<f64>, // Sample rate of the recorded rows, fixed at start
+    pub pre_trigger_buffer: Mutex<VecDeque<Row>>, // Rows kept while a trigger is armed
+    pub pre_trigger_window: Mutex<Option<Duration>>, // Length of the pre-trigger window; Some while armed
+    pub pre_trigger_held: AtomicBool, // A trigger fired; the window stops rolling until the recording takes it
     pub recording_active: Arc<AtomicBool>,
+    pub paused: AtomicBool, // Samples and video frames are discarded while set
+    pub paused_at: Mutex<Option<SystemTime>>, // Start of the current pause
     pub recording_handle: Mutex<Option<JoinHandle<()>>>,
-    pub recording_file: Mutex<Option<(File, String)>>,
+    pub recording_writer: Mutex<Option<(Box<dyn RecordingWriter>, String)>>, // Writer of the current segment and its format
+    pub compression: Mutex<CompressionConfig>, // Compression of recording files started from now on
+    pub binary_options: Mutex<BinaryOptions>, // Sample encoding and chunking of binary recordings
+    pub rotation: Mutex<RotationConfig>, // Size, sample count and clock limits of a segment
+    pub edf_options: Mutex<EdfOptions>, // Header fields and scaling of EDF+/BDF+ recordings
+    pub brainvision_options: Mutex<BrainVisionOptions>, // Data encoding of BrainVision recordings
     pub recording_filename: Mutex<Option<String>>, // Store current recording filename
+    pub recording_directory: Mutex<Option<String>>, // Directory of the current recording
+    pub annotations: Mutex<Vec<Annotation>>, // Annotations waiting to be written by the recording thread
+    pub annotation_file: Mutex<Option<(File, String)>>, // Annotation sidecar and the segment it belongs to
     pub video_recording_active: Arc<AtomicBool>, // Flag for video recording
+    pub video_filename: Mutex<Option<String>>, // File name of the video being recorded
+    pub video_path: Mutex<Option<PathBuf>>, // Full path of that video, sealed when it stops
+    pub video_frames: Mutex<Vec<SystemTime>>, // Capture times of video frames waiting to be written
 }
 
 impl RecordingState {
     pub fn new() -> Self {
         Self {
-            recording_buffer: Mutex::new(VecDeque::new()),
+            pipeline: RecordingPipeline::new(),
+            recording_montage: Mutex::new(None),
+            recording_derived: Mutex::new(Vec::new()),
+            resample_config: Mutex::new(ResampleConfig::default()),
+            recording_resampler: Mutex::new(None),
+            clock: Mutex::new(SessionClock::new(250.0)),
+            recording_sample_rate: Mutex::new(250.0),
+            pre_trigger_buffer: Mutex::new(VecDeque::new()),
+            pre_trigger_window: Mutex::new(None),
+            pre_trigger_held: AtomicBool::new(false),
             recording_active: Arc::new(AtomicBool::new(false)),
+            paused: AtomicBool::new(false),
+            paused_at: Mutex::new(None),
             recording_handle: Mutex::new(None),
-            recording_file: Mutex::new(None),
+            recording_writer: Mutex::new(None),
+            compression: Mutex::new(CompressionConfig::default()),
+            binary_options: Mutex::new(BinaryOptions::default()),
+            rotation: Mutex::new(RotationConfig::default()),
+            edf_options: Mutex::new(EdfOptions::default()),
+            brainvision_options: Mutex::new(BrainVisionOptions::default()),
             recording_filename: Mutex::new(None),
+            recording_directory: Mutex::new(None),
+            annotations: Mutex::new(Vec::new()),
+            annotation_file: Mutex::new(None),
             video_recording_active: Arc::new(AtomicBool::new(false)),
+            video_filename: Mutex::new(None),
+            video_path: Mutex::new(None),
+            video_frames: Mutex::new(Vec::new()),
         }
     }
 
-    pub fn add_data(&self, data: ChannelData) {
-        // Add to recording buffer if recording is active
-        if self.recording_active.load(std::sync::atomic::Ordering::SeqCst) {
-            let mut recording_buf = self.recording_buffer.lock().unwrap();
-            let timestamp = SystemTime::now();
-            recording_buf.push_back((timestamp, data));
-            
-            // Limit the recording buffer size to prevent memory issues
-            // This is a large size to ensure we don't lose data during recording
-            if recording_buf.len() >= 10000 {
-                recording_buf.pop_front();
+    pub fn add_data(&self, data: ChannelData, derived: &[(String, f32)]) {
+        // Add to recording buffer if recording is active, or to the pre-trigger buffer while armed
+        let active = self.recording_active.load(std::sync::atomic::Ordering::SeqCst);
+        let pre_trigger_window = *self.pre_trigger_window.lock().unwrap();
+        if !active && pre_trigger_window.is_none() {
+            return;
+        }
+
+        let mut row = match self.recording_montage.lock().unwrap().as_ref() {
+            Some(montage) => montage.apply(&data),
+            None => data.to_vec(),
+        };
+        // Derived channels removed since the start are recorded as NaN to keep columns stable
+        for name in self.recording_derived.lock().unwrap().iter() {
+            let value = derived.iter().find(|(n, _)| n == name).map(|(_, v)| *v);
+            row.push(value.unwrap_or(f32::NAN));
+        }
+        // Rows are stamped from their index on the nominal grid rather than by arrival time
+        let mut clock = self.clock.lock().unwrap();
+        let index = clock.tick();
+        let rows: Vec<Row> = match self.recording_resampler.lock().unwrap().as_mut() {
+            Some(resampler) => {
+                let rate = resampler.target_rate();
+                // Outputs lag their input by the filter delay, which is taken off their times
+                let delay = Duration::from_secs_f64(resampler.delay_s());
+                resampler
+                    .process(&row)
+                    .into_iter()
+                    .map(|(index, output)| (index, clock.time_at(index, rate) - delay, output))
+                    .collect()
+            }
+            None => vec![(index, clock.time_at(index, clock.rate()), row)],
+        };
+        drop(clock);
+
+        if let (false, Some(window)) = (active, pre_trigger_window) {
+            let mut pre_buf = self.pre_trigger_buffer.lock().unwrap();
+            // The trigger may have fired and taken the window since the check above
+            if self.recording_active.load(std::sync::atomic::Ordering::SeqCst) {
+                drop(pre_buf);
+                self.pipeline.push(rows);
+                return;
+            }
+            pre_buf.extend(rows);
+            if self.pre_trigger_held.load(std::sync::atomic::Ordering::SeqCst) {
+                return;
             }
+            // Keep only the configured window behind the newest row
+            if let Some(newest) = pre_buf.back().map(|(_, t, _)| *t) {
+                while pre_buf
+                    .front()
+                    .map(|(_, t, _)| newest.duration_since(*t).unwrap_or_default() > window)
+                    .unwrap_or(false)
+                {
+                    pre_buf.pop_front();
+                }
+            }
+            return;
+        }
+
+        // The clock keeps counting through a pause, so it shows as a gap in the sample index
+        if self.paused.load(std::sync::atomic::Ordering::SeqCst) {
+            return;
         }
+        self.pipeline.push(rows);
     }
 
-    // Get recording data with timestamps
-    pub fn get_recording_data(&self) -> Vec<(SystemTime, ChannelData)> {
-        let mut recording_buf = self.recording_buffer.lock().unwrap();
-        let result: Vec<(SystemTime, ChannelData)> = recording_buf.drain(..).collect();
-        result
+    // Start keeping a rolling pre-trigger window
+    pub fn begin_pre_trigger(&self, window: Duration) {
+        self.pre_trigger_buffer.lock().unwrap().clear();
+        *self.pre_trigger_window.lock().unwrap() = Some(window);
+    }
+
+    // Keep every row from now on, and accept annotations, until the recording of a fired trigger opens
+    pub fn hold_pre_trigger(&self) {
+        self.pre_trigger_held.store(true, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    // Stop keeping the pre-trigger window and drop its rows
+    pub fn end_pre_trigger(&self) {
+        *self.pre_trigger_window.lock().unwrap() = None;
+        // Annotations queued for a recording that did not open go with it
+        if self.pre_trigger_held.swap(false, std::sync::atomic::Ordering::SeqCst) {
+            self.annotations.lock().unwrap().clear();
+        }
+        self.pre_trigger_buffer.lock().unwrap().clear();
+    }
+
+    // Open the pipeline and mark the recording active; rows of a fired trigger's
+    // pre-trigger window are queued first so they precede every live row
+    pub fn begin_writing(&self) {
+        self.pipeline.open();
+        self.paused.store(false, std::sync::atomic::Ordering::SeqCst);
+        *self.paused_at.lock().unwrap() = None;
+        let mut pre_buf = self.pre_trigger_buffer.lock().unwrap();
+        self.pipeline.push(pre_buf.drain(..).collect());
+        self.recording_active.store(true, std::sync::atomic::Ordering::SeqCst);
+        *self.pre_trigger_window.lock().unwrap() = None;
+        self.pre_trigger_held.store(false, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    // Wait up to `timeout` for rows to record
+    pub fn get_recording_data(&self, timeout: Duration) -> Vec<Row> {
+        self.pipeline.receive(timeout)
+    }
+
+    // Queue an annotation for the active recording, or for the one a fired trigger is opening;
+    // ignored when not recording
+    pub fn add_annotation(&self, annotation: Annotation) {
+        if self.recording_active.load(std::sync::atomic::Ordering::SeqCst)
+            || self.pre_trigger_held.load(std::sync::atomic::Ordering::SeqCst)
+        {
+            self.annotations.lock().unwrap().push(annotation);
+        }
+    }
+
+    pub fn take_annotations(&self) -> Vec<Annotation> {
+        std::mem::take(&mut *self.annotations.lock().unwrap())
+    }
+
+    // Whether frames should go to the video recorder; a paused recording pauses its video too
+    pub fn video_capturing(&self) -> bool {
+        self.video_recording_active.load(std::sync::atomic::Ordering::SeqCst)
+            && !self.paused.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    // Note the capture time of a frame handed to the video recorder; frames
+    // outside a video recording of the running session are not part of it
+    pub fn add_video_frame(&self, timestamp: SystemTime) {
+        if !self.video_capturing() {
+            return;
+        }
+        if self.recording_active.load(std::sync::atomic::Ordering::SeqCst) {
+            self.video_frames.lock().unwrap().push(timestamp);
+        }
+    }
+
+    pub fn take_video_frames(&self) -> Vec<SystemTime> {
+        std::mem::take(&mut *self.video_frames.lock().unwrap())
+    }
+
+    pub fn take_sync_points(&self) -> Vec<SyncPoint> {
+        self.clock.lock().unwrap().take_sync_points()
+    }
+
+    // Labels of the recorded channels, in column order
+    pub fn channel_labels(&self) -> Vec<String> {
+        let mut labels = match self.recording_montage.lock().unwrap().as_ref() {
+            Some(montage) => montage.labels.clone(),
+            None => montage::default_labels(),
+        };
+        labels.extend(self.recording_derived.lock().unwrap().iter().cloned());
+        labels
+    }
+}
+
+// ==== Session State ====
+/// Operator-entered session details and the metadata of the running session
+pub struct SessionState {
+    pub details: Mutex<SessionDetails>,
+    pub active: Mutex<Option<ActiveSession>>, // Manifest and directory of the running session
+}
+
+impl SessionState {
+    pub fn new() -> Self {
+        Self {
+            details: Mutex::new(SessionDetails::default()),
+            active: Mutex::new(None),
+        }
+    }
+}
+
+// ==== Montage State ====
+/// Manages the active montage applied to visualized (and optionally recorded) data
+pub struct MontageState {
+    pub active: Mutex<Option<CompiledMontage>>,
+    pub apply_to_recording: Arc<AtomicBool>,
+}
+
+impl MontageState {
+    pub fn new() -> Self {
+        Self {
+            active: Mutex::new(None),
+            apply_to_recording: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    // Labels of the display channels
+    pub fn labels(&self) -> Vec<String> {
+        match self.active.lock().unwrap().as_ref() {
+            Some(montage) => montage.labels.clone(),
+            None => montage::default_labels(),
+        }
+    }
+
+    // Apply the active montage; raw channels pass through when none is set
+    pub fn apply(&self, data: &ChannelData) -> Vec<f32> {
+        match self.active.lock().unwrap().as_ref() {
+            Some(montage) => montage.apply(data),
+            None => data.to_vec(),
+        }
+    }
+}
+
+// ==== Expression State ====
+/// Manages the user-defined derived channel expressions
+pub struct ExpressionState {
+    pub definitions: Mutex<Vec<DerivedExpression>>,
+    pub compiled: Mutex<Vec<CompiledExpression>>,
+}
+
+impl ExpressionState {
+    pub fn new() -> Self {
+        Self {
+            definitions: Mutex::new(Vec::new()),
+            compiled: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.compiled.lock().unwrap().iter().map(|e| e.name.clone()).collect()
+    }
+
+    // Evaluate every derived channel for one sample; montage labels name the display channels
+    pub fn evaluate(
+        &self,
+        raw: &ChannelData,
+        display: &[f32],
+        montage: &MontageState,
+        sample_rate: f64,
+    ) -> Vec<(String, f32)> {
+        let mut compiled = self.compiled.lock().unwrap();
+        if compiled.is_empty() {
+            return Vec::new();
+        }
+        let labels = montage.labels();
+        let ctx = EvalContext {
+            raw,
+            display,
+            labels: &labels,
+            sample_rate,
+        };
+        compiled
+            .iter_mut()
+            .map(|expression| (expression.name.clone(), expression.evaluate(&ctx)))
+            .collect()
+    }
+}
+
+// ==== Impedance State ====
+/// Manages the electrode impedance measurement mode
+pub struct ImpedanceState {
+    pub active: Arc<AtomicBool>,
+    pub config: Arc<Mutex<ImpedanceConfig>>, // Shared with the fake reader for simulation
+    pub engine: Mutex<ImpedanceEngine>,
+}
+
+impl ImpedanceState {
+    pub fn new() -> Self {
+        Self {
+            active: Arc::new(AtomicBool::new(false)),
+            config: Arc::new(Mutex::new(ImpedanceConfig::default())),
+            engine: Mutex::new(ImpedanceEngine::new()),
+        }
+    }
+
+    // Feed a sample while impedance mode is on; returns a report when one is due
+    pub fn add_data(&self, data: ChannelData, sample_rate: f64) -> Option<ImpedanceReport> {
+        if !self.active.load(std::sync::atomic::Ordering::SeqCst) {
+            return None;
+        }
+        let config = self.config.lock().unwrap().clone();
+        self.engine.lock().unwrap().add_data(data, sample_rate, &config)
+    }
+}
+
+// ==== Artifact State ====
+/// Manages automatic artifact detection on the live stream
+pub struct ArtifactState {
+    pub engine: Mutex<ArtifactEngine>,
+}
+
+impl ArtifactState {
+    pub fn new() -> Self {
+        Self {
+            engine: Mutex::new(ArtifactEngine::new(ArtifactConfig::default())),
+        }
+    }
+
+    // Feed a sample; returns the artifacts that ended with it
+    pub fn add_data(&self, data: ChannelData, sample_rate: f64) -> Vec<ArtifactEvent> {
+        self.engine.lock().unwrap().add_data(data, sample_rate)
+    }
+
+    pub fn annotate_recording(&self) -> bool {
+        self.engine.lock().unwrap().config().annotate_recording
+    }
+}
+
+// ==== Trigger State ====
+/// Manages triggered recording: the armed condition and the pending post-trigger stop
+pub struct TriggerState {
+    pub config: Mutex<TriggerConfig>,
+    pub armed: Arc<AtomicBool>,
+    pub session: Mutex<Option<TriggerSession>>, // Recording parameters used when the trigger fires
+    pub previous: Mutex<Option<f32>>, // Last value of the threshold channel, for crossing detection
+    pub stop_deadline: Mutex<Option<SystemTime>>, // End of the post-trigger window of a running recording
+}
+
+impl TriggerState {
+    pub fn new() -> Self {
+        Self {
+            config: Mutex::new(TriggerConfig::default()),
+            armed: Arc::new(AtomicBool::new(false)),
+            session: Mutex::new(None),
+            previous: Mutex::new(None),
+            stop_deadline: Mutex::new(None),
+        }
+    }
+
+    fn is_armed(&self) -> bool {
+        self.armed.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    // Returns a reason when a displayed sample fires the armed trigger
+    pub fn check_sample(&self, display: &[f32]) -> Option<String> {
+        if !self.is_armed() {
+            return None;
+        }
+        let config = self.config.lock().unwrap();
+        let TriggerCondition::Threshold { channel, .. } = config.condition else {
+            return None;
+        };
+        let previous = self.previous.lock().unwrap().replace(display.get(channel).copied()?);
+        config.condition.check_sample(display, previous)
+    }
+
+    pub fn check_artifact(&self, artifact: &ArtifactEvent) -> Option<String> {
+        if !self.is_armed() {
+            return None;
+        }
+        self.config.lock().unwrap().condition.check_artifact(artifact)
+    }
+
+    pub fn check_quality(&self, report: &QualityReport) -> Option<String> {
+        if !self.is_armed() {
+            return None;
+        }
+        self.config.lock().unwrap().condition.check_quality(report)
+    }
+}
+
+/// Stored recordings opened for plotting, kept while their files are unchanged
+pub struct ReaderState {
+    pub opened: Mutex<Vec<OpenedRecording>>, // Most recently used last
+}
+
+impl ReaderState {
+    pub fn new() -> Self {
+        Self {
+            opened: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+// ==== Conversion State ====
+/// Offline format conversions in progress
+pub struct ConversionState {
+    pub running: Mutex<HashMap<PathBuf, Arc<AtomicBool>>>, // Cancel flag by input file
+}
+
+impl ConversionState {
+    pub fn new() -> Self {
+        Self {
+            running: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+// ==== Storage State ====
+/// Free space thresholds and retention of the recording directory
+pub struct StorageState {
+    pub config: Mutex<StorageConfig>,
+    pub write_failed: AtomicBool, // Set by a failed write so free space is checked right away
+}
+
+impl StorageState {
+    pub fn new() -> Self {
+        Self {
+            config: Mutex::new(StorageConfig::default()),
+            write_failed: AtomicBool::new(false),
+        }
     }
 }
 
@@ -255,6 +682,18 @@ pub struct AppState {
     pub stream: StreamState,
     pub recording: RecordingState,
     pub mdns: MdnsState,
+    pub spectrum: SpectrumState,
+    pub montage: MontageState,
+    pub impedance: ImpedanceState,
+    pub artifact: ArtifactState,
+    pub expressions: ExpressionState,
+    pub trigger: TriggerState,
+    pub session: SessionState,
+    pub markers: MarkerBus,
+    pub sample_output: SampleOutput,
+    pub reader: ReaderState,
+    pub conversion: ConversionState,
+    pub storage: StorageState,
 }
 
 
@@ -267,10 +706,22 @@ impl AppState {
             stream: StreamState::new(),
             recording: RecordingState::new(),
             mdns: MdnsState::new(),
+            spectrum: SpectrumState::new(),
+            montage: MontageState::new(),
+            impedance: ImpedanceState::new(),
+            artifact: ArtifactState::new(),
+            expressions: ExpressionState::new(),
+            trigger: TriggerState::new(),
+            session: SessionState::new(),
+            markers: MarkerBus::new(),
+            sample_output: SampleOutput::new(),
+            reader: ReaderState::new(),
+            conversion: ConversionState::new(),
+            storage: StorageState::new(),
             }
     }
     // Forward methods to appropriate sub-states for backward compatibility
-    pub fn get_data(&self) -> Vec<ChannelData> {
+    pub fn get_data(&self) -> Vec<Vec<f32>> {
         self.buffer.get_data()
     }
 }
@@ -0,0 +1,323 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use tauri::Emitter;
+
+use crate::recording;
+use crate::session::{self, SessionSummary};
+use crate::state::AppState;
+
+/// Free space is checked this often while recording, and right away after a failed write
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+const GIB: u64 = 1024 * 1024 * 1024;
+const MIB: u64 = 1024 * 1024;
+
+/// Free space thresholds of the recording directory and the retention policy
+/// applied to its sessions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    pub warning_free_bytes: u64,  // `storage_warning` below this
+    pub critical_free_bytes: u64, // `storage_warning` again, at critical level
+    pub stop_free_bytes: u64,     // Recording stops below this, and doesn't start
+    pub retention: RetentionPolicy,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            warning_free_bytes: 5 * GIB,
+            critical_free_bytes: GIB,
+            stop_free_bytes: 200 * MIB,
+            retention: RetentionPolicy::default(),
+        }
+    }
+}
+
+impl StorageConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.stop_free_bytes == 0 {
+            return Err("Free space to stop at must be positive".to_string());
+        }
+        if self.critical_free_bytes < self.stop_free_bytes || self.warning_free_bytes < self.critical_free_bytes {
+            return Err("Free space thresholds must satisfy stop <= critical <= warning".to_string());
+        }
+        self.retention.validate()
+    }
+}
+
+/// Which stored sessions to keep. Limits left out are not applied; the ones
+/// given are applied automatically when a session starts or ends, and before
+/// recording stops for lack of space. The session being recorded and the most
+/// recent one are never deleted.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    pub keep_sessions: Option<usize>, // Most recent sessions kept
+    pub max_total_bytes: Option<u64>, // Total size of the stored sessions
+    pub max_age_days: Option<u32>,    // Sessions that ended longer ago are deleted
+}
+
+impl RetentionPolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.keep_sessions == Some(0) {
+            return Err("Sessions to keep must be positive".to_string());
+        }
+        if self.max_total_bytes == Some(0) {
+            return Err("Maximum total size must be positive".to_string());
+        }
+        if self.max_age_days == Some(0) {
+            return Err("Maximum age must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    fn is_set(&self) -> bool {
+        self.keep_sessions.is_some() || self.max_total_bytes.is_some() || self.max_age_days.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageLevel {
+    Ok,
+    Warning,
+    Critical,
+    Full, // Below the stop threshold
+}
+
+/// Free space of the file system holding a directory
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageStatus {
+    pub directory: String,
+    pub free_bytes: u64, // Available to the app, without space reserved for the system
+    pub total_bytes: u64,
+    pub level: StorageLevel,
+}
+
+/// Sessions deleted by a retention run, or that would be on a dry run
+#[derive(Debug, Clone, Serialize)]
+pub struct RetentionReport {
+    pub deleted: Vec<SessionSummary>,
+    pub freed_bytes: u64,
+    pub dry_run: bool,
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+/// Free space in `directory` against the configured thresholds
+pub fn storage_status(state: &AppState, directory: &Path) -> Result<StorageStatus, String> {
+    // A recording directory not created yet ends up on the file system of its nearest existing parent
+    let existing = directory.ancestors().find(|path| path.exists()).unwrap_or(directory);
+    let stats = fs4::statvfs(existing).map_err(|e| format!("Failed to read free space of {}: {}", directory.display(), e))?;
+    let config = state.storage.config.lock().unwrap().clone();
+    let free_bytes = stats.available_space();
+    let level = if free_bytes < config.stop_free_bytes {
+        StorageLevel::Full
+    } else if free_bytes < config.critical_free_bytes {
+        StorageLevel::Critical
+    } else if free_bytes < config.warning_free_bytes {
+        StorageLevel::Warning
+    } else {
+        StorageLevel::Ok
+    };
+    Ok(StorageStatus {
+        directory: directory.to_string_lossy().into_owned(),
+        free_bytes,
+        total_bytes: stats.total_space(),
+        level,
+    })
+}
+
+/// Sessions of `directory` the retention policy doesn't keep, oldest first.
+/// `upcoming` sessions about to be created count against `keep_sessions`.
+fn expired_sessions(
+    state: &AppState,
+    directory: &Path,
+    policy: &RetentionPolicy,
+    upcoming: usize,
+) -> Result<Vec<SessionSummary>, String> {
+    let mut sessions = session::list_sessions(state, directory)?;
+    sessions.sort_by_key(|s| s.started_ms);
+    let newest = sessions.last().map(|s| s.id.clone());
+    let mut total: u64 = sessions.iter().map(|s| s.size_bytes).sum();
+    let mut remaining = sessions.len() + upcoming;
+    let oldest_kept = policy
+        .max_age_days
+        .map(|days| now_ms().saturating_sub(days as u64 * 24 * 60 * 60 * 1000));
+
+    let mut expired = Vec::new();
+    for summary in sessions {
+        if summary.active || Some(&summary.id) == newest.as_ref() {
+            continue;
+        }
+        let too_many = policy.keep_sessions.map(|keep| remaining > keep).unwrap_or(false);
+        let too_large = policy.max_total_bytes.map(|max| total > max).unwrap_or(false);
+        let ended_ms = summary.stopped_ms.unwrap_or(summary.started_ms);
+        let too_old = oldest_kept.map(|oldest| ended_ms < oldest).unwrap_or(false);
+        if too_many || too_large || too_old {
+            remaining -= 1;
+            total = total.saturating_sub(summary.size_bytes);
+            expired.push(summary);
+        }
+    }
+    Ok(expired)
+}
+
+/// Deletes the sessions of `directory` that the retention policy doesn't keep.
+/// A `dry_run` only reports them.
+pub fn apply_retention(state: &AppState, directory: &Path, dry_run: bool) -> Result<RetentionReport, String> {
+    delete_expired(state, directory, dry_run, 0)
+}
+
+fn delete_expired(
+    state: &AppState,
+    directory: &Path,
+    dry_run: bool,
+    upcoming: usize,
+) -> Result<RetentionReport, String> {
+    let policy = state.storage.config.lock().unwrap().retention.clone();
+    let expired = expired_sessions(state, directory, &policy, upcoming)?;
+    let mut report = RetentionReport {
+        deleted: Vec::new(),
+        freed_bytes: 0,
+        dry_run,
+    };
+    for summary in expired {
+        if !dry_run {
+            if let Err(e) = session::delete_session(state, directory, &summary.id) {
+                eprintln!("[Storage] Retention could not delete session {}: {}", summary.id, e);
+                continue;
+            }
+        }
+        report.freed_bytes += summary.size_bytes;
+        report.deleted.push(summary);
+    }
+    if !dry_run && !report.deleted.is_empty() {
+        println!(
+            "[Storage] Retention deleted {} sessions ({} bytes) from {}",
+            report.deleted.len(),
+            report.freed_bytes,
+            directory.display()
+        );
+    }
+    Ok(report)
+}
+
+/// Applies a configured retention policy on its own; failures are only logged
+pub fn apply_retention_automatically(state: &AppState, directory: &Path) {
+    if !state.storage.config.lock().unwrap().retention.is_set() {
+        return;
+    }
+    if let Err(e) = apply_retention(state, directory, false) {
+        eprintln!("[Storage] Retention failed: {}", e);
+    }
+}
+
+/// Called before a new session is created in `directory`: refuses to start when
+/// the disk is below the stop threshold even after retention made room. Retention
+/// otherwise runs once the session has begun, so it counts among the kept ones.
+pub fn prepare_session(state: &AppState, directory: &Path) -> Result<(), String> {
+    let mut status = storage_status(state, directory)?;
+    if status.level == StorageLevel::Full
+        && directory.is_dir()
+        && state.storage.config.lock().unwrap().retention.is_set()
+    {
+        if let Err(e) = delete_expired(state, directory, false, 1) {
+            eprintln!("[Storage] Retention failed: {}", e);
+        }
+        status = storage_status(state, directory)?;
+    }
+    if status.level == StorageLevel::Full {
+        return Err(format!(
+            "Not enough free space in {}: {} MB left",
+            status.directory,
+            status.free_bytes / MIB
+        ));
+    }
+    Ok(())
+}
+
+/// Watches free space from the recording thread: warns as thresholds are
+/// crossed and stops the recording before the disk is full
+pub struct StorageMonitor {
+    directory: PathBuf, // Recording directory holding the session directories
+    checked_at: Option<Instant>,
+    level: StorageLevel,
+    stopping: bool,
+}
+
+impl StorageMonitor {
+    pub fn new(session_directory: &Path) -> Self {
+        Self {
+            directory: session_directory.parent().unwrap_or(session_directory).to_path_buf(),
+            checked_at: None,
+            level: StorageLevel::Ok,
+            stopping: false,
+        }
+    }
+
+    pub fn check(&mut self, state: &Arc<AppState>) {
+        let write_failed = state.storage.write_failed.swap(false, Ordering::SeqCst);
+        let due = self.checked_at.map(|at| at.elapsed() >= CHECK_INTERVAL).unwrap_or(true);
+        if self.stopping || !(due || write_failed) {
+            return;
+        }
+        self.checked_at = Some(Instant::now());
+
+        let mut status = match storage_status(state, &self.directory) {
+            Ok(status) => status,
+            Err(e) => {
+                eprintln!("[Storage] {}", e);
+                return;
+            }
+        };
+        if status.level == StorageLevel::Full && state.storage.config.lock().unwrap().retention.is_set() {
+            // Older sessions the policy gives up make room before the recording is stopped
+            apply_retention_automatically(state, &self.directory);
+            match storage_status(state, &self.directory) {
+                Ok(after) => status = after,
+                Err(e) => eprintln!("[Storage] {}", e),
+            }
+        }
+
+        let app_handle = state.communication.app_handle.lock().unwrap().clone();
+        if status.level > self.level && status.level != StorageLevel::Full {
+            eprintln!(
+                "[Storage] {:?}: {} MB free in {}",
+                status.level,
+                status.free_bytes / MIB,
+                status.directory
+            );
+            if let Some(app_handle) = app_handle.as_ref() {
+                let _ = app_handle.emit("storage_warning", &status);
+            }
+        }
+        self.level = status.level;
+
+        if status.level == StorageLevel::Full {
+            self.stopping = true;
+            eprintln!(
+                "[Storage] Stopping recording: {} MB free in {}",
+                status.free_bytes / MIB,
+                status.directory
+            );
+            // Stopping joins the recording thread, so it runs on a thread of its own
+            let stopping = recording::stop_recording_in_background(state);
+            if let Some(app_handle) = app_handle {
+                // Announced once the recording is finalized
+                thread::spawn(move || {
+                    let _ = stopping.join();
+                    let _ = app_handle.emit("recording_stopped_low_space", &status);
+                });
+            }
+        }
+    }
+}
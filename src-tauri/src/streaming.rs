@@ -8,7 +8,7 @@ use rand::{Rng, thread_rng};
 use reqwest::blocking::Client;
 use std::io::{BufRead, BufReader, Read};
 use std::sync::{atomic::Ordering, Arc};
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use std::thread;
 use tauri::{AppHandle, Emitter, Manager};
 
@@ -106,9 +106,10 @@ fn start_fake_stream(
             let _ = app_clone.emit("frame", Arc::new(b64.clone()));
             
             // Check recording state
-            if state_clone.recording.video_recording_active.load(Ordering::SeqCst) {
+            if state_clone.recording.video_capturing() {
                 match tauri_plugin_record_stream::push_frame(app_clone.clone(), raw.clone(), W, H) {
                     Ok(analysis) => {
+                        state_clone.recording.add_video_frame(SystemTime::now());
                         let _ = app_clone.emit("frame_analysis", Arc::new(!analysis.is_covered));
                     },
                     Err(e) => println!("Error pushing frame to video recorder: {}", e)
@@ -250,14 +251,15 @@ fn start_real_stream(
                                 let b64 = STANDARD.encode(&buffer);
                                 let _ = app_clone.emit("frame", Arc::new(b64.clone()));
                                 
-                                // Also push frame to video recorder if recording is active
-                                if state_clone.recording.video_recording_active.load(Ordering::SeqCst) {
+                                // Also push frame to video recorder if recording is active and not paused
+                                if state_clone.recording.video_capturing() {
                                     // Convert to raw RGB bytes for recording instead of using base64 PNG
                                     if let Ok(img) = image::load_from_memory(&buffer) {
                                         let rgb = img.to_rgb8();
                                         let dims = img.dimensions();
                                         match tauri_plugin_record_stream::push_frame(app_clone.clone(), rgb.into_raw(), dims.0, dims.1) {
                                             Ok(analysis) => {
+                                                state_clone.recording.add_video_frame(SystemTime::now());
                                                 let _ = app_clone.emit("frame_analysis", Arc::new(!analysis.is_covered));
                                             },
                                             Err(e) => println!("Error pushing frame to video recorder: {}", e)
@@ -0,0 +1,41 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+
+use crate::writer::Row;
+
+static NEXT_DIRECTORY: AtomicUsize = AtomicUsize::new(0);
+
+/// A new empty directory under the system temp directory
+pub fn temp_dir(name: &str) -> PathBuf {
+    let number = NEXT_DIRECTORY.fetch_add(1, Ordering::SeqCst);
+    let path = std::env::temp_dir().join(format!("serial-brain-{}-{}-{}", name, std::process::id(), number));
+    let _ = fs::remove_dir_all(&path);
+    fs::create_dir_all(&path).unwrap();
+    path
+}
+
+/// `count` rows of `channels` sine waves at `rate` Hz, starting at a whole second
+pub fn rows(count: usize, channels: usize, rate: f64) -> Vec<Row> {
+    let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    (0..count)
+        .map(|i| {
+            let t = i as f64 / rate;
+            let values = (0..channels)
+                .map(|c| (100.0 * (c + 1) as f64 * (2.0 * std::f64::consts::PI * 10.0 * t).sin()) as f32)
+                .collect();
+            (i as u64, start + Duration::from_secs_f64(t), values)
+        })
+        .collect()
+}
+
+/// Milliseconds since the Unix epoch, as the readers report sample times
+pub fn millis(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis() as u64
+}
+
+/// Microseconds between two times, either way round
+pub fn micros_apart(a: SystemTime, b: SystemTime) -> u128 {
+    a.duration_since(b).or_else(|_| b.duration_since(a)).unwrap().as_micros()
+}
@@ -0,0 +1,302 @@
+use std::sync::{atomic::Ordering, Arc};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::artifact::{ArtifactEvent, ArtifactKind};
+use crate::quality::{QualityGrade, QualityReport};
+use crate::recording;
+use crate::state::AppState;
+
+/// Longest pre-trigger window; its rows are held in memory while the trigger is armed
+const MAX_PRE_TRIGGER_MS: u64 = 30_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Crossing {
+    Rising,
+    Falling,
+    Either,
+}
+
+/// Condition that starts an armed recording
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TriggerCondition {
+    /// A displayed channel crosses `level` in the given direction
+    Threshold { channel: usize, level: f64, crossing: Crossing },
+    /// Any detected artifact, or only artifacts of `kind`
+    Artifact { kind: Option<ArtifactKind> },
+    /// An external marker, or only markers with `label`
+    Marker { label: Option<String> },
+    /// All listed electrode channels (all when empty) are graded good
+    QualityGood { channels: Vec<usize> },
+}
+
+/// Settings of triggered recording
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TriggerConfig {
+    pub condition: TriggerCondition,
+    pub pre_trigger_ms: u64,          // Data before the trigger included in the recording
+    pub post_trigger_ms: Option<u64>, // Stop this long after the trigger; None records until stopped
+    pub auto_rearm: bool,             // Arm again once a post-trigger recording has stopped
+}
+
+impl Default for TriggerConfig {
+    fn default() -> Self {
+        Self {
+            condition: TriggerCondition::Marker { label: None },
+            pre_trigger_ms: 2000,
+            post_trigger_ms: None,
+            auto_rearm: false,
+        }
+    }
+}
+
+impl TriggerConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.pre_trigger_ms > MAX_PRE_TRIGGER_MS {
+            return Err(format!("Pre-trigger window must not exceed {} ms", MAX_PRE_TRIGGER_MS));
+        }
+        if self.post_trigger_ms == Some(0) {
+            return Err("Post-trigger duration must be positive".to_string());
+        }
+        if let TriggerCondition::QualityGood { channels } = &self.condition {
+            if channels.iter().any(|&c| c >= 8) {
+                return Err("Quality trigger channels must be between 0 and 7".to_string());
+            }
+        }
+        if self.auto_rearm && self.post_trigger_ms.is_none() {
+            return Err("Auto re-arm requires a post-trigger duration".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Recording parameters remembered while armed, used when the trigger fires
+#[derive(Debug, Clone, Serialize)]
+pub struct TriggerSession {
+    pub format: String,
+    pub directory: String,
+    pub max_duration_minutes: u32,
+}
+
+/// Payload of the `recording_triggered` event
+#[derive(Debug, Clone, Serialize)]
+pub struct TriggerFired {
+    pub reason: String,
+    pub filename: String,
+    pub trigger_ms: u64, // Wall-clock time of the trigger in milliseconds since the Unix epoch
+}
+
+impl TriggerCondition {
+    /// Returns a reason when a displayed sample satisfies a threshold condition
+    pub fn check_sample(&self, display: &[f32], previous: Option<f32>) -> Option<String> {
+        let TriggerCondition::Threshold { channel, level, crossing } = self else {
+            return None;
+        };
+        let (current, previous) = (*display.get(*channel)? as f64, previous? as f64);
+        let rising = previous < *level && current >= *level;
+        let falling = previous > *level && current <= *level;
+        let fired = match crossing {
+            Crossing::Rising => rising,
+            Crossing::Falling => falling,
+            Crossing::Either => rising || falling,
+        };
+        fired.then(|| format!("threshold {} on channel {}", level, channel))
+    }
+
+    pub fn check_artifact(&self, artifact: &ArtifactEvent) -> Option<String> {
+        match self {
+            TriggerCondition::Artifact { kind } if kind.map(|k| k == artifact.kind).unwrap_or(true) => {
+                Some(format!("artifact {} on channel {}", artifact.kind.label(), artifact.channel))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn check_marker(&self, marker: &str) -> Option<String> {
+        match self {
+            TriggerCondition::Marker { label } if label.as_deref().map(|l| l == marker).unwrap_or(true) => {
+                Some(format!("marker {}", marker))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn check_quality(&self, report: &QualityReport) -> Option<String> {
+        let TriggerCondition::QualityGood { channels } = self else {
+            return None;
+        };
+        let good = report
+            .channels
+            .iter()
+            .filter(|c| channels.is_empty() || channels.contains(&c.channel))
+            .all(|c| c.grade == QualityGrade::Good);
+        good.then(|| "signal quality good".to_string())
+    }
+}
+
+/// Arms a recording that starts once the configured condition fires.
+/// The montage, derived channels and output rate are frozen now so the
+/// pre-trigger rows already have the recorded layout.
+pub fn arm_trigger(
+    app_handle: &AppHandle,
+    format: String,
+    directory: String,
+    max_duration_minutes: u32,
+) -> Result<(), String> {
+    let state = app_handle.state::<Arc<AppState>>();
+    if state.recording.recording_active.load(Ordering::SeqCst)
+        || state.recording.pre_trigger_held.load(Ordering::SeqCst)
+    {
+        return Err("A recording is already running".to_string());
+    }
+    recording::validate_format(&format)?;
+
+    let config = state.trigger.config.lock().unwrap().clone();
+    config.validate()?;
+    recording::prepare_layout(&state)?;
+    state
+        .recording
+        .begin_pre_trigger(Duration::from_millis(config.pre_trigger_ms));
+    *state.trigger.session.lock().unwrap() = Some(TriggerSession {
+        format,
+        directory,
+        max_duration_minutes,
+    });
+    *state.trigger.previous.lock().unwrap() = None;
+    state.trigger.armed.store(true, Ordering::SeqCst);
+    println!("[Trigger] Armed: {:?}", config.condition);
+    let _ = app_handle.emit("recording_trigger_armed", &config);
+    Ok(())
+}
+
+/// Drops an armed trigger and its pre-trigger data; a running recording is left alone.
+pub fn disarm_trigger(app_handle: &AppHandle) {
+    let state = app_handle.state::<Arc<AppState>>();
+    if state.trigger.armed.swap(false, Ordering::SeqCst) {
+        state.recording.end_pre_trigger();
+        println!("[Trigger] Disarmed");
+    }
+    *state.trigger.session.lock().unwrap() = None;
+    *state.trigger.stop_deadline.lock().unwrap() = None;
+}
+
+/// Starts the armed recording, including the buffered pre-trigger window.
+/// Only the hand-off of the window happens on the caller's thread, usually the
+/// acquisition loop; the files are set up on a worker thread.
+pub fn fire_trigger(app_handle: &AppHandle, reason: String) {
+    let state = app_handle.state::<Arc<AppState>>();
+    // Holding the session serializes callers; only the first one finds the trigger armed
+    let session_guard = state.trigger.session.lock().unwrap();
+    let Some(session) = session_guard.clone() else {
+        return;
+    };
+    if !state.trigger.armed.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    // Rows from now on queue behind the window until the recording takes them
+    state.recording.hold_pre_trigger();
+    drop(session_guard);
+
+    let trigger_time = SystemTime::now();
+    let app_handle = app_handle.clone();
+    thread::spawn(move || start_triggered_recording(&app_handle, session, reason, trigger_time));
+}
+
+/// Opens the recording of a fired trigger, which takes the held window first
+fn start_triggered_recording(
+    app_handle: &AppHandle,
+    session: TriggerSession,
+    reason: String,
+    trigger_time: SystemTime,
+) {
+    let state = app_handle.state::<Arc<AppState>>();
+    let result = recording::start_recording(
+        session.format,
+        session.directory,
+        session.max_duration_minutes,
+        false,
+        app_handle.clone(),
+    );
+
+    match result {
+        Ok(filename) => {
+            let post = state.trigger.config.lock().unwrap().post_trigger_ms;
+            *state.trigger.stop_deadline.lock().unwrap() =
+                post.map(|ms| trigger_time + Duration::from_millis(ms));
+            println!("[Trigger] Fired ({}), recording to {}", reason, filename);
+            let trigger_ms = trigger_time
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64;
+            let _ = app_handle.emit(
+                "recording_triggered",
+                TriggerFired {
+                    reason,
+                    filename,
+                    trigger_ms,
+                },
+            );
+        }
+        Err(e) => {
+            eprintln!("[Trigger] Failed to start triggered recording: {}", e);
+            state.recording.end_pre_trigger();
+            *state.trigger.session.lock().unwrap() = None;
+            let _ = app_handle.emit("recording_trigger_error", e);
+        }
+    }
+}
+
+/// Stops a triggered recording once its post-trigger duration has elapsed,
+/// re-arming it when configured. Called from the acquisition loop.
+pub fn poll_trigger(app_handle: &AppHandle) {
+    let state = app_handle.state::<Arc<AppState>>();
+    let due = state
+        .trigger
+        .stop_deadline
+        .lock()
+        .unwrap()
+        .map(|deadline| SystemTime::now() >= deadline)
+        .unwrap_or(false);
+    if !due {
+        return;
+    }
+    *state.trigger.stop_deadline.lock().unwrap() = None;
+
+    if let Err(e) = recording::stop_recording(app_handle.clone()) {
+        eprintln!("[Trigger] Failed to stop triggered recording: {}", e);
+    }
+    let _ = app_handle.emit("recording_trigger_finished", json!({}));
+
+    let rearm = state.trigger.config.lock().unwrap().auto_rearm;
+    let session = state.trigger.session.lock().unwrap().take();
+    if let (true, Some(session)) = (rearm, session) {
+        if let Err(e) = arm_trigger(
+            app_handle,
+            session.format,
+            session.directory,
+            session.max_duration_minutes,
+        ) {
+            eprintln!("[Trigger] Failed to re-arm: {}", e);
+        }
+    }
+}
+
+/// Fires an armed marker trigger when `label` matches its condition
+pub fn check_marker(app_handle: &AppHandle, label: &str) {
+    let state = app_handle.state::<Arc<AppState>>();
+    let reason = if state.trigger.armed.load(Ordering::SeqCst) {
+        state.trigger.config.lock().unwrap().condition.check_marker(label)
+    } else {
+        None
+    };
+    if let Some(reason) = reason {
+        fire_trigger(app_handle, reason);
+    }
+}
@@ -1,8 +1,14 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 /// Alias for eight-channel float data
 pub type ChannelData = [f32; 8];
 
+/// Microvolts per count of the device's 24-bit converter
+pub const MICROVOLTS_PER_COUNT: f64 = 0.5364 / 12.0;
+
+/// Largest magnitude the device reports, in microvolts
+pub const FULL_SCALE_MICROVOLTS: f64 = 8_388_607.0 * MICROVOLTS_PER_COUNT;
+
 /// Configuration for fake data generation
 #[derive(Debug, Deserialize, Clone)]
 pub struct FakeDataConfig {
@@ -12,3 +18,27 @@ pub struct FakeDataConfig {
     pub channel_count: usize,
     pub waveform: String,
 }
+
+/// Data source of the signal stream and its connection settings
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DeviceInfo {
+    pub kind: String, // "serial", "socket" or "fake"
+    pub port: Option<String>,
+    pub baud_rate: Option<u32>,
+    pub stop_bits: Option<u8>,
+    pub parity: Option<String>,
+    pub data_bits: Option<u8>,
+    pub host: Option<String>,
+    pub tcp_port: Option<u16>,
+}
+
+impl DeviceInfo {
+    /// Short form stored in recording headers, e.g. `serial:/dev/ttyUSB0@115200`
+    pub fn describe(&self) -> String {
+        match (self.kind.as_str(), &self.port, &self.host) {
+            ("serial", Some(port), _) => format!("serial:{}@{}", port, self.baud_rate.unwrap_or_default()),
+            ("socket", _, Some(host)) => format!("socket:{}:{}", host, self.tcp_port.unwrap_or_default()),
+            (kind, _, _) => kind.to_string(),
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use crate::binary::{BinaryOptions, BinaryWriter};
+use crate::compression::{self, CompressedFile, CompressionConfig};
+use crate::brainvision::{BrainVisionOptions, BrainVisionWriter};
+use crate::edf::{EdfOptions, EdfWriter, EdfVariant};
+use crate::jsonl::JsonlWriter;
+use crate::recording::Annotation;
+use crate::xdf::XdfWriter;
+
+/// Recording formats accepted by `start_recording`
+pub const FORMATS: [&str; 8] = ["csv", "json", "jsonl", "binary", "edf", "bdf", "brainvision", "xdf"];
+
+/// File extension used for a recording format
+pub fn extension(format: &str) -> &'static str {
+    match format {
+        "csv" => "csv",
+        "json" => "json",
+        "jsonl" => "jsonl",
+        "edf" => "edf",
+        "bdf" => "bdf",
+        "brainvision" => "vhdr",
+        "xdf" => "xdf",
+        _ => "bin",
+    }
+}
+
+/// Name of a segment file: `<stem>.<extension>` plus the compression suffix.
+/// BrainVision compresses only its `.eeg` data file, so its header keeps the plain name.
+pub fn file_name(stem: &str, format: &str, compression: &CompressionConfig) -> String {
+    let suffix = if format == "brainvision" { "" } else { compression.suffix() };
+    format!("{}.{}{}", stem, extension(format), suffix)
+}
+
+/// What a writer needs to know about the recorded channels
+#[derive(Debug, Clone, Default)]
+pub struct RecordingInfo {
+    pub labels: Vec<String>,
+    pub sample_rate: f64,
+    pub device: String, // Data source the samples come from
+    pub binary: BinaryOptions,
+    pub edf: EdfOptions,
+    pub brainvision: BrainVisionOptions,
+    pub compression: CompressionConfig,
+}
+
+/// A recorded row: its sample index in the session, its nominal time and the channel values
+pub type Row = (u64, SystemTime, Vec<f32>);
+
+/// Label of the annotation giving the session sample index of the row where a
+/// positional format starts, or resumes after `missing` samples
+pub fn index_label(index: u64, missing: u64) -> String {
+    if missing == 0 {
+        format!("Sample index {}", index)
+    } else {
+        format!("Sample index {} after {} missing samples", index, missing)
+    }
+}
+
+/// The rows a positional format has written, as runs of consecutive sample
+/// indices. Samples lost by the device, dropped by a full pipeline or skipped
+/// by a pause leave a gap in the index that starts a new run.
+#[derive(Default)]
+pub struct Timeline {
+    runs: Vec<(u64, SystemTime)>, // Position and time of the first row of each run
+    next_index: Option<u64>,
+    written: u64,
+    last: Option<SystemTime>, // Time of the last row
+}
+
+impl Timeline {
+    /// Adds the next written row; returns the number of samples missing before it
+    pub fn add(&mut self, index: u64, time: SystemTime) -> u64 {
+        let missing = self.next_index.map(|next| index.saturating_sub(next)).unwrap_or(0);
+        if self.next_index.is_none() || missing > 0 {
+            self.runs.push((self.written, time));
+        }
+        self.next_index = Some(index + 1);
+        self.written += 1;
+        self.last = Some(time);
+        missing
+    }
+
+    /// Number of rows written
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Whether rows up to `time` have been written, so a gap can't come before it
+    pub fn reaches(&self, time: SystemTime) -> bool {
+        self.last.map(|last| last >= time).unwrap_or(false)
+    }
+
+    /// Position of the row closest to `time`, counting written rows from 0.
+    /// A time within a gap gives the first row after it.
+    pub fn position(&self, time: SystemTime, sample_rate: f64) -> u64 {
+        let run = self.runs.partition_point(|(_, start)| *start <= time);
+        let Some(&(first, start)) = run.checked_sub(1).and_then(|run| self.runs.get(run)) else {
+            return 0;
+        };
+        let offset = (time.duration_since(start).unwrap_or_default().as_secs_f64() * sample_rate).round() as u64;
+        let end = self.runs.get(run).map(|(position, _)| *position).unwrap_or(u64::MAX);
+        (first + offset).min(end)
+    }
+}
+
+/// A recording file being written, one implementation per format
+pub trait RecordingWriter: Send {
+    /// Writes rows in order; formats that store samples by position record the
+    /// index of the first one, and of the first after every gap in the index,
+    /// so every sample keeps both its index and its time
+    fn write_samples(&mut self, rows: &[Row]) -> io::Result<()>;
+
+    /// Stores annotations in the file itself; returns false when the format
+    /// has no place for them and they belong in the sidecar instead
+    fn write_annotations(&mut self, _annotations: &[Annotation]) -> io::Result<bool> {
+        Ok(false)
+    }
+
+    /// Stores the capture times of pushed video frames; formats without a
+    /// stream for them drop them
+    fn write_video_frames(&mut self, _frames: &[SystemTime]) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Completes the file; called once when the segment is closed
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+/// Creates the file at `path` and a writer for `format`. Streamed formats are
+/// compressed as they are written; EDF/BDF patch their header when the segment
+/// closes, so they are written plain next to `path` and compressed then.
+pub fn create_writer(
+    format: &str,
+    path: &Path,
+    info: &RecordingInfo,
+) -> Result<Box<dyn RecordingWriter>, String> {
+    info.compression.validate()?;
+    let seekable = matches!(format, "edf" | "bdf");
+    let file_path = if seekable { compression::logical_path(path) } else { path.to_path_buf() };
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&file_path)
+        .map_err(|e| format!("Failed to create recording file: {}", e))?;
+    let compressed = |file| {
+        CompressedFile::new(file, &info.compression).map_err(|e| format!("Failed to start compression: {}", e))
+    };
+
+    let writer: Box<dyn RecordingWriter> = match format {
+        "csv" => Box::new(
+            CsvWriter::new(compressed(file)?, &info.labels).map_err(|e| format!("Failed to write CSV header: {}", e))?,
+        ),
+        "json" => Box::new(JsonWriter::new(compressed(file)?).map_err(|e| format!("Failed to write JSON opening: {}", e))?),
+        "jsonl" => Box::new(JsonlWriter::new(compressed(file)?, info)?),
+        "binary" => Box::new(BinaryWriter::new(compressed(file)?, info)?),
+        "edf" | "bdf" => {
+            let variant = if format == "edf" { EdfVariant::Edf } else { EdfVariant::Bdf };
+            let writer = EdfWriter::new(file, variant, info)?;
+            if file_path.as_path() != path {
+                Box::new(writer.with_compression(file_path, path.to_path_buf(), info.compression.clone()))
+            } else {
+                Box::new(writer)
+            }
+        }
+        "brainvision" => Box::new(BrainVisionWriter::new(file, path, info)?),
+        "xdf" => Box::new(XdfWriter::new(compressed(file)?, info)?),
+        _ => return Err("Invalid format specified".to_string()),
+    };
+    Ok(writer)
+}
+
+fn timestamp_ms(timestamp: &SystemTime) -> u128 {
+    timestamp
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_else(|_| Duration::from_secs(0))
+        .as_millis()
+}
+
+/// Builds the CSV header line for the recorded channel labels. The sample
+/// index comes last so readers taking `timestamp` and the channels by position
+/// keep working.
+pub fn csv_header(labels: &[String]) -> String {
+    let mut header = String::from("timestamp");
+    for label in labels {
+        header.push(',');
+        header.push_str(&csv_field(label));
+    }
+    header.push_str(",sample_index");
+    header
+}
+
+/// A CSV field, quoted per RFC 4180 when it holds a separator, quote or line break
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// `timestamp,val1,val2,...,sample_index` lines under a label header
+pub struct CsvWriter {
+    file: CompressedFile,
+}
+
+impl CsvWriter {
+    pub fn new(mut file: CompressedFile, labels: &[String]) -> io::Result<Self> {
+        writeln!(file, "{}", csv_header(labels))?;
+        Ok(Self { file })
+    }
+}
+
+impl RecordingWriter for CsvWriter {
+    fn write_samples(&mut self, rows: &[Row]) -> io::Result<()> {
+        for (index, timestamp, channel_data) in rows {
+            // CSV: timestamp,val1,val2,...,sample_index
+            let mut line = timestamp_ms(timestamp).to_string();
+            // One value per recorded channel
+            for &value in channel_data.iter() {
+                line.push_str(&format!(",{}", value));
+            }
+            line.push_str(&format!(",{}", index));
+            writeln!(self.file, "{}", line)?;
+        }
+        // Flush CSV entries to disk in real time
+        self.file.flush()
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.file.finish()
+    }
+}
+
+/// A JSON array of `{"timestamp", "sample_index", "values"}` objects
+pub struct JsonWriter {
+    file: CompressedFile,
+    first_entry: bool,
+}
+
+impl JsonWriter {
+    pub fn new(mut file: CompressedFile) -> io::Result<Self> {
+        file.write_all(b"[")?;
+        Ok(Self { file, first_entry: true })
+    }
+}
+
+impl RecordingWriter for JsonWriter {
+    fn write_samples(&mut self, rows: &[Row]) -> io::Result<()> {
+        for (index, timestamp, channel_data) in rows {
+            let json_entry = format!(
+                "{}{{\"timestamp\": {},\"sample_index\": {},\"values\": {}}}",
+                if self.first_entry { "" } else { "," },
+                timestamp_ms(timestamp),
+                index,
+                serde_json::to_string(channel_data).unwrap()
+            );
+            self.first_entry = false;
+            self.file.write_all(json_entry.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        // Close the JSON array
+        self.file.write_all(b"]")?;
+        self.file.finish()
+    }
+}
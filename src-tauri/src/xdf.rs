@@ -0,0 +1,637 @@
+use std::io::{self, BufWriter, Write};
+use std::time::{Duration, SystemTime};
+
+use chrono::Local;
+
+use crate::compression::CompressedFile;
+use crate::recording::Annotation;
+use crate::recording_reader::{time_from_millis, Contents, Sample, SourceInfo};
+use crate::writer::{self, RecordingInfo, RecordingWriter, Row, Timeline};
+
+// Chunk tags from the XDF 1.0 specification
+const TAG_FILE_HEADER: u16 = 1;
+const TAG_STREAM_HEADER: u16 = 2;
+const TAG_SAMPLES: u16 = 3;
+const TAG_CLOCK_OFFSET: u16 = 4;
+const TAG_BOUNDARY: u16 = 5;
+const TAG_STREAM_FOOTER: u16 = 6;
+
+/// Fixed UUID that marks a boundary chunk, so readers can resync in damaged files
+const BOUNDARY_UUID: [u8; 16] = [
+    0x43, 0xA5, 0x46, 0xDC, 0xCB, 0xF5, 0x41, 0x0F, 0xB3, 0x0E, 0xD5, 0x46, 0x73, 0x83, 0xCB, 0xE4,
+];
+
+/// How often clock offsets and boundary chunks are written
+const CLOCK_OFFSET_INTERVAL: f64 = 5.0;
+
+const SIGNAL_STREAM: u32 = 1;
+const MARKER_STREAM: u32 = 2;
+const VIDEO_STREAM: u32 = 3;
+
+/// Seconds since the Unix epoch; every stream is stamped on this clock
+fn clock_seconds(timestamp: &SystemTime) -> f64 {
+    timestamp
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_else(|_| Duration::from_secs(0))
+        .as_secs_f64()
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Variable-length integer: a byte giving the width (1, 4 or 8) then the value
+fn push_varlen(buffer: &mut Vec<u8>, value: u64) {
+    if value <= u8::MAX as u64 {
+        buffer.push(1);
+        buffer.push(value as u8);
+    } else if value <= u32::MAX as u64 {
+        buffer.push(4);
+        buffer.extend_from_slice(&(value as u32).to_le_bytes());
+    } else {
+        buffer.push(8);
+        buffer.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+/// First/last timestamp and sample count of a stream, reported in its footer
+#[derive(Default)]
+struct StreamStats {
+    first: Option<f64>,
+    last: f64,
+    count: u64,
+}
+
+impl StreamStats {
+    fn add(&mut self, timestamp: f64) {
+        self.first.get_or_insert(timestamp);
+        self.last = timestamp;
+        self.count += 1;
+    }
+}
+
+/// Writes an XDF file with the signal, marker and video frame streams.
+///
+/// All three streams share the system clock, so the clock offsets written
+/// every few seconds are zero; they let XDF readers align the streams
+/// without dejittering guesses.
+pub struct XdfWriter {
+    file: BufWriter<CompressedFile>,
+    signal: StreamStats,
+    markers: StreamStats,
+    video: StreamStats,
+    clock_offsets: Vec<f64>, // Collection times of the written clock offsets
+    first_index: Option<u64>, // Session sample index of the first signal sample
+    timeline: Timeline,
+}
+
+impl XdfWriter {
+    pub fn new(file: CompressedFile, info: &RecordingInfo) -> Result<Self, String> {
+        let mut writer = Self {
+            file: BufWriter::new(file),
+            signal: StreamStats::default(),
+            markers: StreamStats::default(),
+            video: StreamStats::default(),
+            clock_offsets: Vec::new(),
+            first_index: None,
+            timeline: Timeline::default(),
+        };
+        writer.write_header(info).map_err(|e| format!("Failed to write XDF header: {}", e))?;
+        Ok(writer)
+    }
+
+    fn write_header(&mut self, info: &RecordingInfo) -> io::Result<()> {
+        self.file.write_all(b"XDF:")?;
+        let created = Local::now().to_rfc3339();
+        let header = format!(
+            "<?xml version=\"1.0\"?><info><version>1.0</version><datetime>{}</datetime></info>",
+            created
+        );
+        self.write_chunk(TAG_FILE_HEADER, header.as_bytes())?;
+
+        let channels: String = info
+            .labels
+            .iter()
+            .map(|label| {
+                format!(
+                    "<channel><label>{}</label><unit>microvolts</unit><type>EEG</type></channel>",
+                    xml_escape(label)
+                )
+            })
+            .collect();
+        let streams = [
+            (SIGNAL_STREAM, "serial-brain", "EEG", info.labels.len(), info.sample_rate, "float32", channels),
+            (
+                MARKER_STREAM,
+                "serial-brain-markers",
+                "Markers",
+                3,
+                0.0,
+                "string",
+                "<channel><label>label</label></channel><channel><label>duration_ms</label></channel>\
+                 <channel><label>channel</label></channel>"
+                    .to_string(),
+            ),
+            (
+                VIDEO_STREAM,
+                "serial-brain-video",
+                "VideoFrames",
+                1,
+                0.0,
+                "double64",
+                "<channel><label>frame_index</label></channel>".to_string(),
+            ),
+        ];
+        for (id, name, kind, channel_count, rate, channel_format, channels) in streams {
+            let xml = format!(
+                "<?xml version=\"1.0\"?><info><name>{}</name><type>{}</type>\
+                 <channel_count>{}</channel_count><nominal_srate>{}</nominal_srate>\
+                 <channel_format>{}</channel_format><source_id>{}</source_id>\
+                 <version>1.1</version><created_at>0</created_at><hostname></hostname>\
+                 <desc><channels>{}</channels><created>{}</created></desc></info>",
+                name, kind, channel_count, rate, channel_format, name, channels, created
+            );
+            let mut content = id.to_le_bytes().to_vec();
+            content.extend_from_slice(xml.as_bytes());
+            self.write_chunk(TAG_STREAM_HEADER, &content)?;
+        }
+        self.file.flush()
+    }
+
+    fn write_chunk(&mut self, tag: u16, content: &[u8]) -> io::Result<()> {
+        // The chunk length counts the tag as well as the content
+        let length = content.len() as u64 + 2;
+        let mut prefix = Vec::with_capacity(11);
+        push_varlen(&mut prefix, length);
+        prefix.extend_from_slice(&tag.to_le_bytes());
+        self.file.write_all(&prefix)?;
+        self.file.write_all(content)
+    }
+
+    /// Samples chunk; `encode` appends the values of one sample
+    fn write_samples_chunk<T>(
+        &mut self,
+        stream: u32,
+        samples: &[(f64, T)],
+        encode: impl Fn(&mut Vec<u8>, &T),
+    ) -> io::Result<()> {
+        let mut content = stream.to_le_bytes().to_vec();
+        push_varlen(&mut content, samples.len() as u64);
+        for (timestamp, values) in samples {
+            content.push(8);
+            content.extend_from_slice(&timestamp.to_le_bytes());
+            encode(&mut content, values);
+        }
+        self.write_chunk(TAG_SAMPLES, &content)
+    }
+
+    /// Writes a zero clock offset for every stream, followed by a boundary chunk
+    fn write_clock_offsets(&mut self, now: f64) -> io::Result<()> {
+        for stream in [SIGNAL_STREAM, MARKER_STREAM, VIDEO_STREAM] {
+            let mut content = stream.to_le_bytes().to_vec();
+            content.extend_from_slice(&now.to_le_bytes());
+            content.extend_from_slice(&0f64.to_le_bytes());
+            self.write_chunk(TAG_CLOCK_OFFSET, &content)?;
+        }
+        self.write_chunk(TAG_BOUNDARY, &BOUNDARY_UUID)?;
+        self.clock_offsets.push(now);
+        Ok(())
+    }
+
+    fn write_footer(&mut self, stream: u32) -> io::Result<()> {
+        let stats = match stream {
+            SIGNAL_STREAM => &self.signal,
+            MARKER_STREAM => &self.markers,
+            _ => &self.video,
+        };
+        let offsets: String = self
+            .clock_offsets
+            .iter()
+            .map(|time| format!("<offset><time>{}</time><value>0</value></offset>", time))
+            .collect();
+        // Signal samples are numbered by position; the footer gives the index of the first
+        let first_index = match (stream, self.first_index) {
+            (SIGNAL_STREAM, Some(index)) => format!("<first_sample_index>{}</first_sample_index>", index),
+            _ => String::new(),
+        };
+        let xml = format!(
+            "<?xml version=\"1.0\"?><info><first_timestamp>{}</first_timestamp>\
+             <last_timestamp>{}</last_timestamp><sample_count>{}</sample_count>{}\
+             <clock_offsets>{}</clock_offsets></info>",
+            stats.first.unwrap_or(0.0),
+            stats.last,
+            stats.count,
+            first_index,
+            offsets
+        );
+        let mut content = stream.to_le_bytes().to_vec();
+        content.extend_from_slice(xml.as_bytes());
+        self.write_chunk(TAG_STREAM_FOOTER, &content)
+    }
+}
+
+impl RecordingWriter for XdfWriter {
+    fn write_samples(&mut self, rows: &[Row]) -> io::Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        self.first_index.get_or_insert(rows[0].0);
+        // Sample times show a gap; a marker gives the index the samples resume at
+        let mut gaps = Vec::new();
+        for (index, timestamp, _) in rows {
+            let missing = self.timeline.add(*index, *timestamp);
+            if missing > 0 {
+                gaps.push(Annotation {
+                    onset_ms: timestamp.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_millis() as u64,
+                    duration_ms: 0.0,
+                    channel: None,
+                    label: writer::index_label(*index, missing),
+                });
+            }
+        }
+        let samples: Vec<(f64, &Vec<f32>)> = rows.iter().map(|(_, t, row)| (clock_seconds(t), row)).collect();
+        for (timestamp, _) in &samples {
+            self.signal.add(*timestamp);
+        }
+        self.write_samples_chunk(SIGNAL_STREAM, &samples, |content, row| {
+            for value in row.iter() {
+                content.extend_from_slice(&value.to_le_bytes());
+            }
+        })?;
+        if !gaps.is_empty() {
+            self.write_annotations(&gaps)?;
+        }
+        let now = self.signal.last;
+        if self.clock_offsets.last().map(|last| now - last >= CLOCK_OFFSET_INTERVAL).unwrap_or(true) {
+            self.write_clock_offsets(now)?;
+        }
+        self.file.flush()
+    }
+
+    fn write_annotations(&mut self, annotations: &[Annotation]) -> io::Result<bool> {
+        let samples: Vec<(f64, [String; 3])> = annotations
+            .iter()
+            .map(|a| {
+                let channel = a.channel.map(|c| c.to_string()).unwrap_or_default();
+                (a.onset_ms as f64 / 1000.0, [a.label.clone(), a.duration_ms.to_string(), channel])
+            })
+            .collect();
+        for (timestamp, _) in &samples {
+            self.markers.add(*timestamp);
+        }
+        self.write_samples_chunk(MARKER_STREAM, &samples, |content, fields| {
+            for field in fields {
+                push_varlen(content, field.len() as u64);
+                content.extend_from_slice(field.as_bytes());
+            }
+        })?;
+        self.file.flush()?;
+        Ok(true)
+    }
+
+    fn write_video_frames(&mut self, frames: &[SystemTime]) -> io::Result<()> {
+        if frames.is_empty() {
+            return Ok(());
+        }
+        // Frames are numbered from the start of the segment, like the video file next to it
+        let first_index = self.video.count;
+        let samples: Vec<(f64, f64)> = frames
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (clock_seconds(t), (first_index + i as u64) as f64))
+            .collect();
+        for (timestamp, _) in &samples {
+            self.video.add(*timestamp);
+        }
+        self.write_samples_chunk(VIDEO_STREAM, &samples, |content, index| {
+            content.extend_from_slice(&index.to_le_bytes());
+        })?;
+        self.file.flush()
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        // A final offset lets readers interpolate up to the end of the file
+        let end = [self.signal.last, self.markers.last, self.video.last]
+            .into_iter()
+            .fold(0.0, f64::max);
+        if self.clock_offsets.last().map(|last| end > *last).unwrap_or(end > 0.0) {
+            self.write_clock_offsets(end)?;
+        }
+        for stream in [SIGNAL_STREAM, MARKER_STREAM, VIDEO_STREAM] {
+            self.write_footer(stream)?;
+        }
+        self.file.flush()?;
+        self.file.get_mut().finish()
+    }
+}
+
+/// Text of the first `<tag>` element in `xml`
+fn xml_value<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{}>", tag);
+    let start = xml.find(&open)? + open.len();
+    let end = xml[start..].find(&format!("</{}>", tag))? + start;
+    Some(&xml[start..end])
+}
+
+fn xml_unescape(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&gt;", ">")
+        .replace("&lt;", "<")
+        .replace("&amp;", "&")
+}
+
+/// Bounds-checked reading position in an XDF file
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let taken = self.bytes.get(self.position..self.position.checked_add(len)?)?;
+        self.position += len;
+        Some(taken)
+    }
+
+    fn varlen(&mut self) -> Option<u64> {
+        match self.take(1)?[0] {
+            1 => Some(self.take(1)?[0] as u64),
+            4 => Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?) as u64),
+            8 => Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?)),
+            _ => None,
+        }
+    }
+}
+
+/// A stream declared in a stream header chunk
+struct StoredStream {
+    channel_count: usize,
+    format: String,
+    rate: f64,
+    labels: Vec<String>,
+    samples: Vec<(f64, Vec<f64>)>,    // Numeric streams
+    strings: Vec<(f64, Vec<String>)>, // String streams
+    last_time: Option<f64>,
+}
+
+impl StoredStream {
+    fn value_width(&self) -> Option<usize> {
+        match self.format.as_str() {
+            "int8" => Some(1),
+            "int16" => Some(2),
+            "float32" | "int32" => Some(4),
+            "double64" | "int64" => Some(8),
+            _ => None,
+        }
+    }
+
+    fn decode(&self, bytes: &[u8]) -> f64 {
+        match self.format.as_str() {
+            "int8" => bytes[0] as i8 as f64,
+            "int16" => i16::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            "int32" => i32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            "int64" => i64::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            "float32" => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            _ => f64::from_le_bytes(bytes.try_into().unwrap()),
+        }
+    }
+
+    /// Reads the samples of one samples chunk; a sample without a timestamp
+    /// follows the previous one at the nominal rate
+    fn read_samples(&mut self, cursor: &mut Cursor) -> Option<()> {
+        let count = cursor.varlen()?;
+        for _ in 0..count {
+            let time = match cursor.take(1)?[0] {
+                8 => f64::from_le_bytes(cursor.take(8)?.try_into().ok()?),
+                _ => self.last_time.map(|t| t + if self.rate > 0.0 { 1.0 / self.rate } else { 0.0 }).unwrap_or(0.0),
+            };
+            self.last_time = Some(time);
+            match self.value_width() {
+                Some(width) => {
+                    let values = (0..self.channel_count)
+                        .map(|_| cursor.take(width).map(|b| self.decode(b)))
+                        .collect::<Option<Vec<_>>>()?;
+                    self.samples.push((time, values));
+                }
+                None => {
+                    let values = (0..self.channel_count)
+                        .map(|_| {
+                            let len = cursor.varlen()? as usize;
+                            cursor.take(len).map(|b| String::from_utf8_lossy(b).into_owned())
+                        })
+                        .collect::<Option<Vec<_>>>()?;
+                    self.strings.push((time, values));
+                }
+            }
+        }
+        Some(())
+    }
+}
+
+/// Reads an XDF file: the first regularly sampled numeric stream becomes the
+/// signal and string streams become annotations. Marker fields after the label
+/// are read as the duration and channel, the way `XdfWriter` writes them.
+/// Clock offsets are not applied; streams written by this app share one clock.
+pub fn read_xdf(bytes: &[u8]) -> Result<Contents, String> {
+    if !bytes.starts_with(b"XDF:") {
+        return Err("Not an XDF file".to_string());
+    }
+    let mut cursor = Cursor { bytes, position: 4 };
+    let mut streams: Vec<(u32, StoredStream)> = Vec::new();
+    while cursor.position < bytes.len() {
+        // A chunk cut short by an interrupted recording ends the file
+        let Some(length) = cursor.varlen() else {
+            break;
+        };
+        let Some(chunk) = cursor.take(length as usize).filter(|chunk| chunk.len() >= 2) else {
+            break;
+        };
+        let tag = u16::from_le_bytes([chunk[0], chunk[1]]);
+        let mut content = Cursor { bytes: &chunk[2..], position: 0 };
+        match tag {
+            TAG_STREAM_HEADER => {
+                let Some(id) = content.take(4).map(|b| u32::from_le_bytes(b.try_into().unwrap())) else {
+                    continue;
+                };
+                let xml = String::from_utf8_lossy(&chunk[6..]);
+                let channels = xml_value(&xml, "channels").unwrap_or_default();
+                let labels = channels
+                    .split("<label>")
+                    .skip(1)
+                    .filter_map(|rest| rest.split_once("</label>").map(|(label, _)| xml_unescape(label)))
+                    .collect();
+                streams.push((
+                    id,
+                    StoredStream {
+                        channel_count: xml_value(&xml, "channel_count").and_then(|n| n.parse().ok()).unwrap_or(0),
+                        format: xml_value(&xml, "channel_format").unwrap_or("float32").to_string(),
+                        rate: xml_value(&xml, "nominal_srate").and_then(|n| n.parse().ok()).unwrap_or(0.0),
+                        labels,
+                        samples: Vec::new(),
+                        strings: Vec::new(),
+                        last_time: None,
+                    },
+                ));
+            }
+            TAG_SAMPLES => {
+                let Some(id) = content.take(4).map(|b| u32::from_le_bytes(b.try_into().unwrap())) else {
+                    continue;
+                };
+                if let Some((_, stream)) = streams.iter_mut().find(|(stream_id, _)| *stream_id == id) {
+                    if stream.read_samples(&mut content).is_none() {
+                        eprintln!("[XDF] Damaged samples chunk in stream {}", id);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut streams: Vec<StoredStream> = streams.into_iter().map(|(_, stream)| stream).collect();
+    let signal = streams
+        .iter()
+        .position(|stream| stream.rate > 0.0 && stream.value_width().is_some())
+        .map(|index| streams.remove(index))
+        .ok_or("XDF file without a regularly sampled numeric stream")?;
+    let labels = if signal.labels.len() == signal.channel_count {
+        signal.labels
+    } else {
+        (0..signal.channel_count).map(|i| format!("Ch{}", i + 1)).collect()
+    };
+    let to_ms = |seconds: f64| (seconds * 1000.0).round().max(0.0) as u64;
+    let source = SourceInfo {
+        sample_rate: Some(signal.rate),
+        ..Default::default()
+    };
+    let samples = signal
+        .samples
+        .into_iter()
+        .map(|(time, values)| Sample {
+            time: time_from_millis(time * 1000.0),
+            index: None,
+            values: values.into_iter().map(|v| v as f32).collect(),
+        })
+        .collect();
+    let mut annotations: Vec<Annotation> = streams
+        .iter()
+        .flat_map(|stream| &stream.strings)
+        .filter_map(|(time, fields)| {
+            Some(Annotation {
+                onset_ms: to_ms(*time),
+                duration_ms: fields.get(1).and_then(|d| d.parse().ok()).unwrap_or(0.0),
+                channel: fields.get(2).and_then(|c| c.parse().ok()),
+                label: fields.first()?.clone(),
+            })
+        })
+        .collect();
+    annotations.sort_by_key(|annotation| annotation.onset_ms);
+    Ok(Contents {
+        labels,
+        samples,
+        annotations,
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{micros_apart, millis, rows, temp_dir};
+
+    /// Writes `rows` to an XDF file in chunks of 250 and returns its bytes
+    fn write(rows: &[Row], annotations: &[Annotation], frames: &[SystemTime]) -> Vec<u8> {
+        let info = RecordingInfo {
+            labels: vec!["C3 <left> & \"near\"".to_string(), "C4".to_string()],
+            sample_rate: 250.0,
+            ..Default::default()
+        };
+        let path = temp_dir("xdf").join("recording.xdf");
+        let mut writer = writer::create_writer("xdf", &path, &info).unwrap();
+        for chunk in rows.chunks(250) {
+            writer.write_samples(chunk).unwrap();
+        }
+        writer.write_annotations(annotations).unwrap();
+        writer.write_video_frames(frames).unwrap();
+        writer.finish().unwrap();
+        std::fs::read(&path).unwrap()
+    }
+
+    #[test]
+    fn round_trips_signal_markers_and_gaps() {
+        let mut rows = rows(1000, 2, 250.0);
+        rows.drain(400..500);
+        let blink = Annotation {
+            onset_ms: millis(rows[100].1),
+            duration_ms: 400.0,
+            channel: Some(1),
+            label: "blink".to_string(),
+        };
+        let frames: Vec<SystemTime> = rows.iter().step_by(10).map(|(_, time, _)| *time).collect();
+        let bytes = write(&rows, std::slice::from_ref(&blink), &frames);
+
+        let Contents {
+            labels,
+            samples,
+            annotations,
+            source,
+        } = read_xdf(&bytes).unwrap();
+        assert_eq!(labels, vec!["C3 <left> & \"near\"", "C4"]);
+        assert_eq!(source.sample_rate, Some(250.0));
+        assert_eq!(samples.len(), rows.len());
+        for (sample, (_, time, values)) in samples.iter().zip(&rows) {
+            assert!(micros_apart(sample.time, *time) <= 1);
+            assert_eq!(&sample.values, values);
+        }
+        let read = annotations.iter().find(|a| a.label == "blink").unwrap();
+        assert_eq!((read.onset_ms, read.duration_ms, read.channel), (blink.onset_ms, 400.0, Some(1)));
+        let resumed = annotations.iter().find(|a| a.label == writer::index_label(500, 100)).unwrap();
+        assert_eq!(resumed.onset_ms, millis(rows[400].1));
+    }
+
+    #[test]
+    fn reads_complete_chunks_of_truncated_files() {
+        let rows = rows(1000, 2, 250.0);
+        let bytes = write(&rows, &[], &[]);
+        for length in (4..bytes.len()).step_by(97) {
+            // A file cut before the first samples chunk has no signal to speak of
+            let Ok(contents) = read_xdf(&bytes[..length]) else {
+                continue;
+            };
+            assert_eq!(contents.samples.len() % 250, 0, "{}", length);
+            for (sample, (_, _, values)) in contents.samples.iter().zip(&rows) {
+                assert_eq!(&sample.values, values);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        assert!(read_xdf(b"").is_err());
+        assert!(read_xdf(b"EDF:0123456789").unwrap_err().contains("Not an XDF file"));
+
+        // Markers alone are no signal
+        let mut bytes = write(&rows(10, 2, 250.0), &[], &[]);
+        bytes.truncate(4);
+        let mut content = MARKER_STREAM.to_le_bytes().to_vec();
+        content.extend_from_slice(b"<info><channel_count>1</channel_count>");
+        content.extend_from_slice(b"<channel_format>string</channel_format></info>");
+        bytes.push(1);
+        bytes.push(content.len() as u8 + 2);
+        bytes.extend_from_slice(&TAG_STREAM_HEADER.to_le_bytes());
+        bytes.extend_from_slice(&content);
+        assert!(read_xdf(&bytes).unwrap_err().contains("regularly sampled"));
+
+        // A chunk length of invalid width ends the file; a stream header too short for its ID is skipped
+        let mut bytes = write(&rows(10, 2, 250.0), &[], &[]);
+        bytes.extend_from_slice(&[3, 0, 0]);
+        assert_eq!(read_xdf(&bytes).unwrap().samples.len(), 10);
+        let mut bytes = write(&rows(10, 2, 250.0), &[], &[]);
+        bytes.extend_from_slice(&[1, 4, 2, 0, 1, 0]);
+        assert_eq!(read_xdf(&bytes).unwrap().samples.len(), 10);
+    }
+}
@@ -1,8 +1,93 @@
 // Functions for loading and processing recording files
-import { stat, readDir, BaseDirectory } from '@tauri-apps/plugin-fs';
+import { stat, readDir, readTextFile, BaseDirectory } from '@tauri-apps/plugin-fs';
 import { RecordingFile } from './types';
 import { formatFileSize, updateFileDuration } from './formatters';
 
+// Recordings are stored in session_<id> directories next to older flat files
+const SESSION_DIRECTORY_PREFIX = 'session_';
+const SESSION_MANIFEST = 'session.json';
+const CHECKSUM_FILE = 'checksums.json';
+const RECORDING_EXTENSIONS = ['.csv', '.json', '.jsonl', '.bin', '.edf', '.bdf', '.vhdr', '.xdf'];
+
+/**
+ * A recording file found in the directory or one of its session directories
+ */
+interface RecordingEntry {
+  name: string;
+  path: string;
+  startedMs?: number; // Segment start from the session manifest
+}
+
+/**
+ * Whether a file name is a recording, compressed or not
+ */
+function isRecordingFile(name: string): boolean {
+  const lower = name.toLowerCase().replace(/\.(gz|zst)$/, '');
+  if (lower === SESSION_MANIFEST || lower === CHECKSUM_FILE) return false;
+  return RECORDING_EXTENSIONS.some(extension => lower.endsWith(extension));
+}
+
+/**
+ * Recording files of a session directory: the segments its manifest lists,
+ * or every recording file in it when the manifest can't be read
+ */
+async function listSessionRecordings(
+  sessionPath: string,
+  baseDir?: BaseDirectory
+): Promise<RecordingEntry[]> {
+  try {
+    const manifestPath = `${sessionPath}/${SESSION_MANIFEST}`;
+    const text = baseDir ?
+      await readTextFile(manifestPath, { baseDir }) :
+      await readTextFile(manifestPath);
+    const manifest = JSON.parse(text);
+    return (manifest.segments || []).map((segment: { file: string; started_ms?: number }) => ({
+      name: segment.file,
+      path: `${sessionPath}/${segment.file}`,
+      startedMs: segment.started_ms
+    }));
+  } catch (error) {
+    console.warn(`Could not read the manifest of ${sessionPath}, listing its files instead:`, error);
+    const entries = baseDir ?
+      await readDir(sessionPath, { baseDir }) :
+      await readDir(sessionPath);
+    return entries
+      .filter(entry => !entry.isDirectory && isRecordingFile(entry.name))
+      .map(entry => ({ name: entry.name, path: `${sessionPath}/${entry.name}` }));
+  }
+}
+
+/**
+ * Recording files directly in the directory and in its session directories
+ */
+async function listRecordingEntries(
+  directoryPath: string,
+  baseDir?: BaseDirectory
+): Promise<RecordingEntry[]> {
+  const entries = baseDir ?
+    await readDir(directoryPath, { baseDir }) :
+    await readDir(directoryPath);
+
+  const recordings: RecordingEntry[] = entries
+    .filter(entry => !entry.isDirectory && isRecordingFile(entry.name))
+    .map(entry => ({ name: entry.name, path: `${directoryPath}/${entry.name}` }));
+
+  const sessions = entries.filter(entry => entry.isDirectory && entry.name.startsWith(SESSION_DIRECTORY_PREFIX));
+  const sessionRecordings = await Promise.all(
+    sessions.map(entry => listSessionRecordings(`${directoryPath}/${entry.name}`, baseDir))
+  );
+  return recordings.concat(...sessionRecordings);
+}
+
+/**
+ * Start time of a recording: the session manifest's, or the one in its file name
+ */
+function entryTimestamp(entry: RecordingEntry): number {
+  if (entry.startedMs) return entry.startedMs;
+  const match = entry.name.match(/serial_recording_(\d+)/);
+  return match ? parseInt(match[1]) : 0;
+}
+
 /**
  * Load basic file information (lightweight) without detailed stats
  * @param directoryPath The directory path to read files from
@@ -15,29 +100,15 @@ export async function loadBasicFileInfo(
   if (!directoryPath) return [];
   
   try {
-    // Read all files in the directory
-    const entries = baseDir ? 
-      await readDir(directoryPath, { baseDir }) : 
-      await readDir(directoryPath);
-    
-    // Filter for recording files (CSV, JSON, binary)
-    const recordingEntries = entries.filter(entry => {
-      const name = entry.name.toLowerCase();
-      return name.endsWith('.csv') || 
-             name.endsWith('.json') || 
-             name.endsWith('.bin');
-    });
+    // Find the recording files of the directory and its sessions
+    const recordingEntries = await listRecordingEntries(directoryPath, baseDir);
     
     // Create lightweight file objects with just basic info
     const basicFiles = recordingEntries.map(entry => {
-      const fullPath = `${directoryPath}/${entry.name}`;
+      const fullPath = entry.path;
       
-      // Extract timestamp from filename for basic sorting
-      let timestamp = 0;
-      const match = entry.name.match(/serial_recording_(\d+)/);
-      if (match && match[1]) {
-        timestamp = parseInt(match[1]);
-      }
+      // Start time for basic sorting
+      const timestamp = entryTimestamp(entry);
       
       // Create a basic file object with minimal info
       const file: RecordingFile = {
@@ -55,14 +126,7 @@ export async function loadBasicFileInfo(
     });
     
     // Sort files by timestamp, newest first
-    return basicFiles.sort((a, b) => {
-      const getTimestamp = (file: RecordingFile) => {
-        const match = file.name.match(/serial_recording_(\d+)/);
-        return match ? parseInt(match[1]) : 0;
-      };
-      
-      return getTimestamp(b) - getTimestamp(a);
-    });
+    return basicFiles.sort((a, b) => (b.dateObject?.getTime() ?? 0) - (a.dateObject?.getTime() ?? 0));
     
   } catch (error) {
     console.error('Error loading basic file info:', error);
@@ -83,27 +147,15 @@ export async function loadDirectoryFiles(
   if (!directoryPath) return [];
   
   try {
-    // Read all files in the directory using baseDir if provided
-    const entries = baseDir ? 
-      await readDir(directoryPath, { baseDir }) : 
-      await readDir(directoryPath);
-    
-    // Filter for recording files (CSV, JSON, binary)
-    const recordingEntries = entries.filter(entry => {
-      const name = entry.name.toLowerCase();
-      return name.endsWith('.csv') || 
-             name.endsWith('.json') || 
-             name.endsWith('.bin');
-    });
+    // Find the recording files of the directory and its sessions
+    const recordingEntries = await listRecordingEntries(directoryPath, baseDir);
     
     // Get file stats for each recording file
     const filesWithStats = await Promise.all(
       recordingEntries.map(async entry => {
         try {
-          // Construct full path, adjusting based on whether we're using BaseDirectory
-          const fullPath = baseDir ? 
-            `${directoryPath}/${entry.name}` : // When using BaseDirectory, the path is relative
-            `${directoryPath}/${entry.name}`; // For absolute paths (legacy support)
+          // Relative to baseDir when one is given, absolute otherwise
+          const fullPath = entry.path;
             
           // Get file stats (pass baseDir if it's provided)
           const fileStat = baseDir ? 
@@ -138,12 +190,8 @@ export async function loadDirectoryFiles(
             const birthtime = new Date(fileStat.birthtime);
             updateFileDuration(file, birthtime, dateObj);
           } else {
-            // Fall back to filename timestamp if no valid dates
-            const getTimestamp = (file: RecordingFile) => {
-              const match = file.name.match(/serial_recording_(\d+)/);
-              return match ? parseInt(match[1]) : 0;
-            };
-            const timestamp = getTimestamp(file);
+            // Fall back to the session or filename timestamp if no valid dates
+            const timestamp = entryTimestamp(entry);
             if (timestamp > 0) {
               const creationDate = new Date(timestamp);
               updateFileDuration(file, creationDate, dateObj);